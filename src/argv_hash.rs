@@ -0,0 +1,42 @@
+// Digests of a helper invocation's full argv, for helpers that only allow
+// exact, known invocations (argv_hashes). The digest is SHA-256 over each
+// argument (argv[0] included) as its length, a big-endian u64, followed by its
+// raw bytes, so that no two different argvs serialize the same.
+//
+// Interactive: usermode-helper --argv-hash <argv...>
+//
+// Prints the digest of the given argv, for adding to argv_hashes.
+
+use crate::exit::Exit;
+use sha2::{Digest, Sha256};
+use std::ffi::OsString;
+use std::io::Write;
+
+pub(crate) fn digest(args: &[OsString]) -> String {
+    framed_digest(args.iter().map(|arg| arg.as_encoded_bytes()))
+}
+
+// The same framing, for anything else that's a sequence of byte strings.
+pub(crate) fn framed_digest<'a>(parts: impl IntoIterator<Item = &'a [u8]>) -> String {
+    let mut hasher = Sha256::new();
+    for part in parts {
+        hasher.update((part.len() as u64).to_be_bytes());
+        hasher.update(part);
+    }
+    hasher
+        .finalize()
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+pub(crate) fn is_digest(s: &str) -> bool {
+    s.len() == 64 && s.bytes().all(|b| b.is_ascii_hexdigit())
+}
+
+pub(crate) fn run(args: &[OsString]) {
+    if args.is_empty() {
+        fail!(Exit::Usage, "usage: --argv-hash <argv...>");
+    }
+    println!("{}", digest(args));
+}
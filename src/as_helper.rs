@@ -0,0 +1,95 @@
+// Integration testing aid, only in builds with the test-mode feature:
+//
+//     usermode-helper --as <path> [--plan] -- <args...>
+//
+// Runs the helper configured with exactly this path (in the default config)
+// as if the kernel had invoked "<path> <args...>", without having to fake
+// argv[0], and without any other helper getting to match first. The helper's
+// other rules (arguments, environment, ...) still have to allow the
+// invocation. With --plan, the privileges the helper would run with are
+// printed instead, and nothing is restricted or run.
+
+use crate::exit::Exit;
+use crate::{
+    Config, DEFAULT_CONFIG_PATH, KILLSWITCH_PATH, MATCH_TIMEOUT, MAX_KEPT_FD, PrivPlan,
+    arm_setup_timer, cap_names, check_killswitch, close_fds, invoke, priv_plan,
+};
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::ffi::OsString;
+use std::io::Write;
+use std::time::Instant;
+
+const USAGE: &str = "usage: --as <path> [--plan] -- <args...>";
+
+fn print_plan(helper: &str, plan: Option<PrivPlan>) {
+    let names = |caps: &HashSet<_>| match cap_names(caps) {
+        names if names.is_empty() => "none".to_string(),
+        names => names,
+    };
+    println!("helper: {}", helper);
+    let Some(plan) = plan else {
+        println!("caps: unrestricted");
+        return;
+    };
+    println!("caps: {}", names(&plan.caps));
+    println!("ambient: {}", names(&plan.ambient));
+    println!("stripped: {}", names(&plan.stripped));
+    println!("drop_setpcap: {}", plan.drop_setpcap);
+}
+
+pub(crate) fn run(args: &[OsString], started: Instant) {
+    let (path, plan_only, rest) = match args {
+        [path, flag, rest @ ..] if flag == "--plan" => (path, true, rest),
+        [path, rest @ ..] => (path, false, rest),
+        [] => fail!(Exit::Usage, "{}", USAGE),
+    };
+    let Some((separator, helper_args)) = rest.split_first() else {
+        fail!(Exit::Usage, "{}", USAGE);
+    };
+    if separator != "--" {
+        fail!(Exit::Usage, "{}", USAGE);
+    }
+
+    close_fds(MAX_KEPT_FD + 1, &BTreeSet::new());
+    arm_setup_timer(MATCH_TIMEOUT, started);
+    check_killswitch(KILLSWITCH_PATH);
+    let config = Config::load(DEFAULT_CONFIG_PATH.unwrap_or("/etc/usermode-helper.conf"));
+    config.close_unkept_fds();
+    config.kmsg_priority.install();
+    config.check_killswitch();
+    config.check_config_hash();
+
+    let helper = config
+        .helpers
+        .iter()
+        .find(|helper| helper.path.as_deref() == path.to_str())
+        .unwrap_or_else(|| fail!(Exit::Usage, "no helper is configured with path {:?}", path));
+    let argv: Vec<OsString> = std::iter::once(path.clone())
+        .chain(helper_args.iter().cloned())
+        .collect();
+    let env: HashMap<OsString, OsString> = std::env::vars_os().collect();
+    if !helper.allowed(&argv, &env) {
+        fail!(
+            Exit::Denied,
+            "helper {} doesn't allow {:?}",
+            helper.path(),
+            argv
+        );
+    }
+
+    if plan_only {
+        let plan = helper.capabilities.as_ref().map(|caps| {
+            priv_plan(caps, &config.ambient_limit(helper)).unwrap_or_else(|cap| {
+                fail!(
+                    Exit::Privileges,
+                    "refusing to raise {} ambient, ambient_denylist forbids it",
+                    cap
+                )
+            })
+        });
+        return print_plan(helper.path(), plan);
+    }
+    helper.check_own_caps();
+    close_fds(3, &helper.kept_fds());
+    invoke(&config, helper, &argv, started);
+}
@@ -0,0 +1,256 @@
+// Audit trail: one JSON record per invocation, appended to the configured
+// audit_log. Records include the environment the kernel invoked us with (which
+// helpers never see), redacted and bounded so a hostile or runaway environment
+// can't bloat the log.
+//
+// A full disk (ENOSPC) is handled according to audit_full_policy; any other
+// failure to write is left to the caller.
+
+use crate::AuditFullPolicy;
+use crate::cap_usage::Report;
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+use std::ffi::OsString;
+use std::fs;
+use std::io::{self, Write};
+use std::os::unix::fs::OpenOptionsExt;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const MAX_ENV_VARS: usize = 64;
+const MAX_ENV_VALUE_LEN: usize = 256;
+// Variables with any of these as a component of their name (split at '_', so
+// API_KEY or DB_PASSWORD, but not KEYBOARD or BYPASS) have their values
+// redacted.
+const SECRET_MARKERS: &[&str] = &[
+    "PASS",
+    "PASSWD",
+    "PASSWORD",
+    "PASSPHRASE",
+    "SECRET",
+    "SECRETS",
+    "TOKEN",
+    "TOKENS",
+    "KEY",
+    "KEYS",
+    "CRED",
+    "CREDS",
+    "CREDENTIAL",
+    "CREDENTIALS",
+];
+
+#[derive(Serialize)]
+struct Record<'a> {
+    time: u64,
+    decision: &'a str,
+    helper: Option<&'a str>,
+    argv: Vec<String>,
+    env: BTreeMap<String, String>,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    env_truncated: bool,
+}
+
+// What a helper with capability_report was seen holding (see cap_usage.rs).
+#[derive(Serialize)]
+struct CapabilityRecord<'a> {
+    time: u64,
+    helper: &'a str,
+    capability_report: &'a Report,
+}
+
+// A line of a helper's captured output (see capture_output).
+#[derive(Serialize)]
+struct OutputRecord<'a> {
+    time: u64,
+    helper: &'a str,
+    output: &'a str,
+}
+
+pub(crate) fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs())
+}
+
+pub(crate) fn output_record(helper: &str, output: &str) -> Vec<u8> {
+    let record = OutputRecord {
+        time: now(),
+        helper,
+        output,
+    };
+    let mut line = serde_json::to_vec(&record).expect("audit records always serialize");
+    line.push(b'\n');
+    line
+}
+
+fn bounded(value: &str) -> String {
+    if value.len() <= MAX_ENV_VALUE_LEN {
+        return value.to_string();
+    }
+    let mut end = MAX_ENV_VALUE_LEN;
+    while !value.is_char_boundary(end) {
+        end -= 1;
+    }
+    format!("{}...", &value[..end])
+}
+
+fn is_secret(name: &str) -> bool {
+    name.to_uppercase()
+        .split('_')
+        .any(|component| SECRET_MARKERS.contains(&component))
+}
+
+fn sanitized_env(env: &HashMap<OsString, OsString>) -> (BTreeMap<String, String>, bool) {
+    let all: BTreeMap<String, String> = env
+        .iter()
+        .map(|(name, value)| {
+            (
+                name.to_string_lossy().into_owned(),
+                value.to_string_lossy().into_owned(),
+            )
+        })
+        .collect();
+    let truncated = all.len() > MAX_ENV_VARS;
+    let kept = all
+        .into_iter()
+        .take(MAX_ENV_VARS)
+        .map(|(name, value)| {
+            let value = if is_secret(&name) {
+                "<redacted>".to_string()
+            } else {
+                bounded(&value)
+            };
+            (bounded(&name), value)
+        })
+        .collect();
+    (kept, truncated)
+}
+
+pub(crate) fn record(
+    path: &str,
+    full: AuditFullPolicy,
+    args: &[OsString],
+    env: &HashMap<OsString, OsString>,
+    helper: Option<&str>,
+) -> io::Result<()> {
+    let (env, env_truncated) = sanitized_env(env);
+    let record = Record {
+        time: now(),
+        decision: if helper.is_some() {
+            "allowed"
+        } else {
+            "denied"
+        },
+        helper,
+        argv: args
+            .iter()
+            .map(|a| a.to_string_lossy().into_owned())
+            .collect(),
+        env,
+        env_truncated,
+    };
+    append(path, full, &record)
+}
+
+pub(crate) fn capability_report(
+    path: &str,
+    full: AuditFullPolicy,
+    helper: &str,
+    report: &Report,
+) -> io::Result<()> {
+    let record = CapabilityRecord {
+        time: now(),
+        helper,
+        capability_report: report,
+    };
+    append(path, full, &record)
+}
+
+pub(crate) fn is_full(e: &io::Error) -> bool {
+    e.raw_os_error() == Some(libc::ENOSPC)
+}
+
+fn write_line(path: &str, line: &[u8]) -> io::Result<()> {
+    fs::OpenOptions::new()
+        .append(true)
+        .create(true)
+        .mode(0o600)
+        .open(path)
+        .and_then(|mut f| f.write_all(line))
+}
+
+// Moves the log to <path>.old, replacing (and so freeing the space of) the one
+// before. If the log itself is what filled the disk, that doesn't free
+// anything yet, so then the old log goes too.
+fn rotate(path: &str, line: &[u8]) -> io::Result<()> {
+    let old = format!("{}.old", path);
+    match fs::remove_file(&old) {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e),
+        _ => {}
+    }
+    fs::rename(path, &old)?;
+    match write_line(path, line) {
+        Err(e) if is_full(&e) => {
+            fs::remove_file(&old)?;
+            warn!("audit log {} is full, discarded it", path);
+            write_line(path, line)
+        }
+        Err(e) => Err(e),
+        Ok(()) => {
+            warn!("audit log {} is full, moved it to {}", path, old);
+            Ok(())
+        }
+    }
+}
+
+fn append(path: &str, full: AuditFullPolicy, record: &impl Serialize) -> io::Result<()> {
+    let mut line = serde_json::to_vec(record).expect("audit records always serialize");
+    line.push(b'\n');
+
+    match write_line(path, &line) {
+        Err(e) if is_full(&e) => match full {
+            AuditFullPolicy::Fail => Err(e),
+            AuditFullPolicy::Drop => {
+                warn!("audit log {} is full, dropped a record", path);
+                Ok(())
+            }
+            AuditFullPolicy::Rotate => rotate(path, &line),
+        },
+        written => written,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn secret_looking_names_are_secret() {
+        for name in [
+            "API_KEY",
+            "DB_PASSWORD",
+            "PASSWORD",
+            "GITHUB_TOKEN",
+            "aws_secret_access_key",
+            "SSH_KEYS",
+            "SERVICE_CREDENTIALS",
+            "SMTP_PASS",
+        ] {
+            assert!(is_secret(name), "{} should be redacted", name);
+        }
+    }
+
+    #[test]
+    fn names_merely_containing_a_marker_arent_secret() {
+        for name in [
+            "KEYBOARD",
+            "XKB_KEYMAP",
+            "MONKEY",
+            "PASSTHROUGH",
+            "BYPASS",
+            "TOKENIZER",
+            "ACTION",
+        ] {
+            assert!(!is_secret(name), "{} shouldn't be redacted", name);
+        }
+    }
+}
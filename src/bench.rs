@@ -0,0 +1,82 @@
+// Interactive benchmark of huldufolk's own per-invocation overhead:
+//
+//     usermode-helper --bench <config> <argv...>
+//
+// Repeats everything an invocation of argv does short of running the helper:
+// loading the config, matching, and restricting privileges. The latter happens
+// in a forked child, so our own privileges are left alone.
+
+use crate::exit::Exit;
+use crate::{AmbientLimit, Config, Helper, priv_restrict};
+use std::collections::HashMap;
+use std::ffi::OsString;
+use std::io::Write;
+use std::time::{Duration, Instant};
+
+const ITERATIONS: usize = 1000;
+
+pub(crate) fn run(args: &[OsString]) {
+    let [config_path, argv @ ..] = args else {
+        fail!(Exit::Usage, "usage: --bench <config> <argv...>");
+    };
+    if argv.is_empty() {
+        fail!(Exit::Usage, "usage: --bench <config> <argv...>");
+    }
+    let config_path = config_path
+        .to_str()
+        .unwrap_or_else(|| fail!(Exit::Usage, "bad config path {:?}", config_path));
+    let env: HashMap<OsString, OsString> = std::env::vars_os().collect();
+
+    let mut samples = Vec::with_capacity(ITERATIONS);
+    for _ in 0..ITERATIONS {
+        let started = Instant::now();
+        let mut config = Config::load(config_path);
+        // Don't flood the audit log with simulated invocations.
+        config.audit_log = None;
+        // Nor hand a denied one to the deny_stub.
+        config.deny_stub = None;
+        config.check_killswitch();
+        config.check_config_hash();
+        let helper = config.find_helper(argv, &env);
+        helper.check_own_caps();
+        restrict_in_child(helper, &config.ambient_limit(helper));
+        samples.push(started.elapsed());
+    }
+
+    samples.sort();
+    let mean = samples.iter().sum::<Duration>() / ITERATIONS as u32;
+    let median = samples[ITERATIONS / 2];
+    println!("helper: {}", argv[0].to_string_lossy());
+    println!("iterations: {}", ITERATIONS);
+    println!("mean: {:.1}us", mean.as_secs_f64() * 1e6);
+    println!("median: {:.1}us", median.as_secs_f64() * 1e6);
+}
+
+fn restrict_in_child(helper: &Helper, ambient: &AmbientLimit) {
+    match unsafe { libc::fork() } {
+        -1 => fail!(
+            Exit::Exec,
+            "fork failed: {}",
+            std::io::Error::last_os_error()
+        ),
+        0 => {
+            if let Some(caps) = &helper.capabilities {
+                priv_restrict(caps, ambient);
+            }
+            unsafe { libc::_exit(0) }
+        }
+        pid => {
+            let mut status = 0;
+            if unsafe { libc::waitpid(pid, &mut status, 0) } < 0
+                || !libc::WIFEXITED(status)
+                || libc::WEXITSTATUS(status) != 0
+            {
+                fail!(
+                    Exit::Privileges,
+                    "restricting privileges for {} failed",
+                    helper.path()
+                );
+            }
+        }
+    }
+}
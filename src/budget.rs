@@ -0,0 +1,44 @@
+// Per-boot invocation counts for helpers with max_invocations, kept in one
+// file (in /run by default, so reboots reset it) as a JSON object of helper
+// path to count. Concurrent invocations are serialized with flock.
+
+use std::collections::BTreeMap;
+use std::fs::OpenOptions;
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::os::unix::fs::OpenOptionsExt;
+use std::os::unix::io::AsRawFd;
+
+// Counts an invocation of helper, unless it already ran max times; returns
+// whether it did.
+pub(crate) fn take(path: &str, helper: &str, max: u64) -> io::Result<bool> {
+    let mut f = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .mode(0o600)
+        .custom_flags(libc::O_NOFOLLOW)
+        .open(path)?;
+    // Released when f is closed.
+    if unsafe { libc::flock(f.as_raw_fd(), libc::LOCK_EX) } < 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    let mut raw = String::new();
+    f.read_to_string(&mut raw)?;
+    let mut counts: BTreeMap<String, u64> = if raw.is_empty() {
+        BTreeMap::new()
+    } else {
+        serde_json::from_str(&raw).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?
+    };
+    let count = counts.entry(helper.to_string()).or_default();
+    if *count >= max {
+        return Ok(false);
+    }
+    *count += 1;
+
+    let raw = serde_json::to_string(&counts).map_err(io::Error::other)?;
+    f.set_len(0)?;
+    f.seek(SeekFrom::Start(0))?;
+    f.write_all(raw.as_bytes())?;
+    Ok(true)
+}
@@ -0,0 +1,91 @@
+// On-disk cache of the parsed config, for systems where helpers fire often and
+// the config is large. An entry is keyed by a hash of the raw bytes of the
+// config and its drop-ins, and of the identity of the huldufolk binary that
+// wrote it, so an edited config or an upgraded huldufolk never sees a stale
+// entry. Anything unexpected (wrong key, bad checksum, undecodable payload,
+// untrustworthy permissions) just means a full parse.
+
+use crate::{Config, ConfigFile, read_config_file};
+use std::fs;
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::io::Write;
+use std::os::unix::fs::{MetadataExt, OpenOptionsExt};
+
+const CACHE_PATH: &str = match option_env!("CONFIG_CACHE_PATH") {
+    Some(path) => path,
+    None => "/run/usermode-helper.cache",
+};
+const MAGIC: &[u8; 8] = b"UMHCACHE";
+const HEADER_LEN: usize = MAGIC.len() + 16;
+
+fn key(files: &[ConfigFile]) -> Option<u64> {
+    let exe = fs::metadata("/proc/self/exe").ok()?;
+    let mut h = DefaultHasher::new();
+    for file in files {
+        (&file.path, &file.raw).hash(&mut h);
+    }
+    (
+        exe.dev(),
+        exe.ino(),
+        exe.size(),
+        exe.mtime(),
+        exe.mtime_nsec(),
+    )
+        .hash(&mut h);
+    Some(h.finish())
+}
+
+fn checksum(payload: &[u8]) -> u64 {
+    let mut h = DefaultHasher::new();
+    payload.hash(&mut h);
+    h.finish()
+}
+
+pub(crate) fn load(files: &[ConfigFile]) -> Option<Config> {
+    let key = key(files)?;
+    // We write the cache as root with mode 0600; anything else isn't ours.
+    let (data, perms) = read_config_file(CACHE_PATH).ok()?;
+    perms.ok()?;
+
+    let (header, payload) = data.split_at_checked(HEADER_LEN)?;
+    let (magic, rest) = header.split_at(MAGIC.len());
+    let cached_key = u64::from_le_bytes(rest[..8].try_into().ok()?);
+    let cached_sum = u64::from_le_bytes(rest[8..].try_into().ok()?);
+    if magic != MAGIC || cached_key != key || cached_sum != checksum(payload) {
+        return None;
+    }
+
+    let config = rmp_serde::from_slice(payload).ok()?;
+    debug!("using cached config {}", CACHE_PATH);
+    Some(config)
+}
+
+// Best effort: a cache we can't write just means parsing again next time.
+pub(crate) fn store(files: &[ConfigFile], config: &Config) {
+    let Some(key) = key(files) else {
+        return;
+    };
+    let Ok(payload) = rmp_serde::to_vec_named(config) else {
+        return;
+    };
+
+    let mut data = Vec::with_capacity(HEADER_LEN + payload.len());
+    data.extend_from_slice(MAGIC);
+    data.extend_from_slice(&key.to_le_bytes());
+    data.extend_from_slice(&checksum(&payload).to_le_bytes());
+    data.extend_from_slice(&payload);
+
+    // Write-then-rename, so concurrent invocations never read a partial file.
+    let tmp = format!("{}.{}", CACHE_PATH, std::process::id());
+    let written = fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .mode(0o600)
+        .open(&tmp)
+        .and_then(|mut f| f.write_all(&data))
+        .and_then(|_| fs::rename(&tmp, CACHE_PATH));
+    if let Err(e) = written {
+        let _ = fs::remove_file(&tmp);
+        debug!("couldn't write config cache {}: {}", CACHE_PATH, e);
+    }
+}
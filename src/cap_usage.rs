@@ -0,0 +1,202 @@
+// Capability usage reports for supervised helpers with capability_report:
+// while we wait for the helper, the effective capabilities (CapEff in
+// /proc/<pid>/status) of every process below us are sampled, and afterwards a
+// record of which granted capabilities were seen and which never were goes to
+// the audit log. Capabilities that never show up across many runs are
+// candidates for removal from the helper's grant.
+//
+// These are snapshots, not a trace: a capability in CapEff is one a process
+// held, not one it used, and processes that come and go between two samples
+// aren't seen at all. So "observed" only bounds what was used from above, and
+// only "unobserved" capabilities that a helper drops early (or that short-lived
+// children would have held) are worth looking into. An exact answer needs
+// something like a BPF probe on cap_capable, which this doesn't attempt.
+
+use caps::Capability;
+use serde::Serialize;
+use std::collections::HashSet;
+use std::fs;
+use std::path::Path;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+const SAMPLE_INTERVAL: Duration = Duration::from_millis(50);
+// Bounds the work of a single sample on a helper that forks without end.
+const MAX_SAMPLED_PROCESSES: usize = 1024;
+
+fn cap_eff(status: &str) -> Option<u64> {
+    let mask = status
+        .lines()
+        .find_map(|line| line.strip_prefix("CapEff:"))?;
+    u64::from_str_radix(mask.trim(), 16).ok()
+}
+
+fn children(proc_root: &Path, pid: &str) -> Vec<String> {
+    let Ok(tasks) = fs::read_dir(proc_root.join(pid).join("task")) else {
+        return Vec::new();
+    };
+    tasks
+        .flatten()
+        .filter_map(|task| fs::read_to_string(task.path().join("children")).ok())
+        .flat_map(|pids| {
+            pids.split_whitespace()
+                .map(str::to_string)
+                .collect::<Vec<_>>()
+        })
+        .collect()
+}
+
+// The effective capabilities of all our descendants, ORed together. Processes
+// that exit while we look are just skipped.
+fn sample(proc_root: &Path) -> u64 {
+    let mut pending = children(proc_root, "self");
+    let mut seen = 0;
+    let mut mask = 0;
+    while let Some(pid) = pending.pop() {
+        if seen == MAX_SAMPLED_PROCESSES {
+            break;
+        }
+        seen += 1;
+        if let Ok(status) = fs::read_to_string(proc_root.join(&pid).join("status")) {
+            mask |= cap_eff(&status).unwrap_or(0);
+        }
+        pending.extend(children(proc_root, &pid));
+    }
+    mask
+}
+
+// Samples /proc in the background until finished.
+pub(crate) struct Sampler {
+    stop: Arc<AtomicBool>,
+    thread: JoinHandle<(u64, usize)>,
+}
+
+impl Sampler {
+    pub(crate) fn start() -> Self {
+        let stop = Arc::new(AtomicBool::new(false));
+        let stopped = Arc::clone(&stop);
+        let thread = thread::spawn(move || {
+            let (mut mask, mut samples) = (0, 0);
+            while !stopped.load(Ordering::Relaxed) {
+                mask |= sample(Path::new("/proc"));
+                samples += 1;
+                thread::sleep(SAMPLE_INTERVAL);
+            }
+            (mask, samples)
+        });
+        Sampler { stop, thread }
+    }
+
+    // The capabilities seen, and how many samples they were seen in.
+    pub(crate) fn finish(self) -> (u64, usize) {
+        self.stop.store(true, Ordering::Relaxed);
+        self.thread.join().unwrap_or_default()
+    }
+}
+
+#[derive(Serialize, Debug, PartialEq)]
+pub(crate) struct Report {
+    pub(crate) granted: Vec<String>,
+    pub(crate) observed: Vec<String>,
+    pub(crate) unobserved: Vec<String>,
+    pub(crate) samples: usize,
+}
+
+fn names(caps: impl Iterator<Item = Capability>) -> Vec<String> {
+    let mut names: Vec<String> = caps.map(|cap| cap.to_string().to_lowercase()).collect();
+    names.sort();
+    names
+}
+
+impl Report {
+    pub(crate) fn new(granted: &HashSet<Capability>, observed: u64, samples: usize) -> Self {
+        let observed: HashSet<Capability> = caps::all()
+            .into_iter()
+            .filter(|cap| observed & cap.bitmask() != 0)
+            .collect();
+        Report {
+            granted: names(granted.iter().copied()),
+            observed: names(observed.iter().copied()),
+            unobserved: names(granted.difference(&observed).copied()),
+            samples,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn caps(names: &[&str]) -> HashSet<Capability> {
+        names.iter().map(|name| name.parse().unwrap()).collect()
+    }
+
+    // A fake /proc under a temporary directory: processes are (pid, CapEff,
+    // children) of a single task, with "self" being our own.
+    fn fake_proc(name: &str, processes: &[(&str, Option<u64>, &[&str])]) -> std::path::PathBuf {
+        let root = std::env::temp_dir().join(format!("huldufolk-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        for (pid, cap_eff, children) in processes {
+            let task = root.join(pid).join("task").join("1");
+            fs::create_dir_all(&task).unwrap();
+            fs::write(task.join("children"), children.join(" ")).unwrap();
+            if let Some(cap_eff) = cap_eff {
+                let status = format!("Name:\tsh\nCapInh:\t0\nCapEff:\t{:016x}\n", cap_eff);
+                fs::write(root.join(pid).join("status"), status).unwrap();
+            }
+        }
+        root
+    }
+
+    #[test]
+    fn sample_ors_all_descendants() {
+        let sys_admin = Capability::CAP_SYS_ADMIN.bitmask();
+        let net_admin = Capability::CAP_NET_ADMIN.bitmask();
+        let chown = Capability::CAP_CHOWN.bitmask();
+        let root = fake_proc(
+            "sample",
+            &[
+                ("self", Some(u64::MAX), &["10", "11"]),
+                ("10", Some(sys_admin), &["12"]),
+                // Exited between reading the children and the status.
+                ("11", None, &[]),
+                ("12", Some(net_admin), &[]),
+                // Not one of ours.
+                ("13", Some(chown), &[]),
+            ],
+        );
+        assert_eq!(sample(&root), sys_admin | net_admin);
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn sample_without_children_is_empty() {
+        let root = fake_proc("no-children", &[("self", Some(u64::MAX), &[])]);
+        assert_eq!(sample(&root), 0);
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn report_lists_unobserved_caps() {
+        let report = Report::new(
+            &caps(&["CAP_SYS_ADMIN", "CAP_NET_ADMIN", "CAP_CHOWN"]),
+            Capability::CAP_NET_ADMIN.bitmask() | Capability::CAP_KILL.bitmask(),
+            3,
+        );
+        assert_eq!(
+            report,
+            Report {
+                granted: vec![
+                    "cap_chown".to_string(),
+                    "cap_net_admin".to_string(),
+                    "cap_sys_admin".to_string()
+                ],
+                observed: vec!["cap_kill".to_string(), "cap_net_admin".to_string()],
+                unobserved: vec!["cap_chown".to_string(), "cap_sys_admin".to_string()],
+                samples: 3,
+            }
+        );
+    }
+}
@@ -0,0 +1,75 @@
+// Config integrity for measured boot: the bootloader (or whatever established
+// the measurement) passes the expected digest of the config on the kernel
+// command line as huldufolk.confighash=<sha256>, and every helper is denied
+// unless the config we loaded has that digest. A malformed token denies every
+// helper too, and so, with require_config_hash, does a missing token or a
+// command line we can't read.
+//
+// The digest is SHA-256 over the main config and then its drop-ins, in the
+// order they're read, framed like argv_hashes: each file's length as a
+// big-endian u64, followed by its contents.
+//
+// Interactive: usermode-helper --config-hash <config>
+//
+// Prints the digest of the given config (drop-ins included), for the kernel
+// command line.
+
+use crate::Config;
+use crate::exit::Exit;
+use std::ffi::OsString;
+use std::io::{self, Write};
+
+pub(crate) const CMDLINE_PATH: &str = match option_env!("KERNEL_CMDLINE_PATH") {
+    Some(path) => path,
+    None => "/proc/cmdline",
+};
+const TOKEN: &str = "huldufolk.confighash";
+
+pub(crate) fn digest<'a>(files: impl IntoIterator<Item = &'a [u8]>) -> String {
+    crate::argv_hash::framed_digest(files)
+}
+
+pub(crate) fn parse(cmdline: &str) -> Result<Option<String>, String> {
+    parse_token(cmdline, TOKEN)
+}
+
+// The digest token gives, if the command line has it. Anything after a bare
+// "--" is for init rather than the kernel, and isn't looked at. A token given
+// more than once, or that isn't a digest, is an error rather than ignored.
+pub(crate) fn parse_token(cmdline: &str, token: &str) -> Result<Option<String>, String> {
+    let mut found = None;
+    for param in cmdline.split_ascii_whitespace().take_while(|p| *p != "--") {
+        let Some((name, value)) = param.split_once('=') else {
+            continue;
+        };
+        if name != token {
+            continue;
+        }
+        if found.is_some() {
+            return Err(format!("{} is given more than once", token));
+        }
+        let value = value
+            .strip_prefix('"')
+            .and_then(|v| v.strip_suffix('"'))
+            .unwrap_or(value);
+        if !crate::argv_hash::is_digest(value) {
+            return Err(format!("{}={:?} isn't a SHA-256 digest", token, value));
+        }
+        found = Some(value.to_ascii_lowercase());
+    }
+    Ok(found)
+}
+
+pub(crate) fn cmdline() -> io::Result<String> {
+    std::fs::read(CMDLINE_PATH).map(|cmdline| String::from_utf8_lossy(&cmdline).into_owned())
+}
+
+pub(crate) fn run(args: &[OsString]) {
+    let [config_path] = args else {
+        fail!(Exit::Usage, "usage: --config-hash <config>");
+    };
+    let config_path = config_path
+        .to_str()
+        .unwrap_or_else(|| fail!(Exit::Usage, "bad config path {:?}", config_path));
+    println!("{}", Config::load(config_path).digest);
+}
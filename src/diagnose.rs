@@ -0,0 +1,160 @@
+// Diagnostics for helpers with on_exec_failure = "diagnose": when exec fails,
+// a bare ENOENT, EACCES or ENOEXEC says little about what's actually wrong, so
+// we look at the exec target the way the kernel would have. This runs with
+// the helper's restrictions already applied (identity, mount namespace,
+// seccomp), so what we see is what the exec saw, and anything we aren't
+// allowed to look at is reported as such.
+//
+// ENOEXEC rarely gets this far, since exec then falls back to running the file
+// with /bin/sh, but the same findings explain EACCES and ENOENT.
+
+use std::ffi::CString;
+use std::fs::{self, File};
+use std::io::{self, Read};
+use std::os::unix::fs::{FileTypeExt, MetadataExt, PermissionsExt};
+use std::path::Path;
+
+// Enough for any reasonable #! line; the kernel itself reads at most 256 bytes.
+const HEADER_LEN: u64 = 256;
+
+fn file_type(file_type: fs::FileType) -> &'static str {
+    if file_type.is_file() {
+        "regular file"
+    } else if file_type.is_dir() {
+        "directory"
+    } else if file_type.is_symlink() {
+        "symlink"
+    } else if file_type.is_char_device() || file_type.is_block_device() {
+        "device"
+    } else if file_type.is_fifo() {
+        "fifo"
+    } else if file_type.is_socket() {
+        "socket"
+    } else {
+        "unknown file type"
+    }
+}
+
+// The closest ancestor of a missing path that does exist, to tell a missing
+// file from a missing (or unmounted) directory.
+fn missing(path: &str) -> String {
+    let existing = Path::new(path)
+        .ancestors()
+        .skip(1)
+        .find(|dir| fs::symlink_metadata(dir).is_ok());
+    match existing {
+        Some(dir) if dir.as_os_str().len() > 1 => {
+            format!("{} doesn't exist (only {} does)", path, dir.display())
+        }
+        _ => format!("{} doesn't exist", path),
+    }
+}
+
+fn interpreter_exists(interp: &str) -> &'static str {
+    match fs::metadata(interp) {
+        Ok(_) => "",
+        Err(e) if e.kind() == io::ErrorKind::NotFound => ", which doesn't exist",
+        Err(_) => ", which can't be looked up",
+    }
+}
+
+fn executable_by_us(path: &str) -> bool {
+    let Ok(c_path) = CString::new(path) else {
+        return false;
+    };
+    unsafe { libc::access(c_path.as_ptr(), libc::X_OK) == 0 }
+}
+
+fn on_noexec_mount(path: &str) -> bool {
+    let Ok(c_path) = CString::new(path) else {
+        return false;
+    };
+    let mut st: libc::statvfs = unsafe { std::mem::zeroed() };
+    unsafe { libc::statvfs(c_path.as_ptr(), &mut st) == 0 && st.f_flag & libc::ST_NOEXEC != 0 }
+}
+
+// What the first bytes of the file say it is.
+fn contents(path: &str) -> String {
+    let mut header = Vec::new();
+    let read = File::open(path).and_then(|f| f.take(HEADER_LEN).read_to_end(&mut header));
+    if let Err(e) = read {
+        return format!("couldn't read {}: {}", path, e);
+    }
+    if header.is_empty() {
+        return "it's empty".to_string();
+    }
+    if header.starts_with(b"\x7fELF") {
+        return match crate::elf::interpreter(path) {
+            Ok(Some(interp)) => format!(
+                "it's an ELF binary loaded by {}{}",
+                interp,
+                interpreter_exists(&interp)
+            ),
+            Ok(None) => "it's a static ELF binary".to_string(),
+            Err(e) => format!("it's an ELF binary, but {}", e),
+        };
+    }
+    if let Some(line) = header.strip_prefix(b"#!") {
+        let line = line.split(|b| *b == b'\n').next().unwrap_or_default();
+        let line = String::from_utf8_lossy(line);
+        // Only spaces and tabs separate the interpreter from its argument, so
+        // e.g. a \r from DOS line endings ends up in its name.
+        let Some(interp) = line.split([' ', '\t']).find(|word| !word.is_empty()) else {
+            return "it's a script without an interpreter".to_string();
+        };
+        return format!(
+            "it's a script for {:?}{}",
+            interp,
+            interpreter_exists(interp)
+        );
+    }
+    "it's neither an ELF binary nor a #! script".to_string()
+}
+
+// Everything we can tell about why path wasn't executed, as separate findings.
+pub(crate) fn exec_failure(path: &str) -> Vec<String> {
+    let mut findings = Vec::new();
+    let meta = match fs::symlink_metadata(path) {
+        Ok(meta) => meta,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return vec![missing(path)],
+        Err(e) => return vec![format!("couldn't look up {}: {}", path, e)],
+    };
+    let meta = if meta.file_type().is_symlink() {
+        let target = fs::read_link(path).map_or("?".to_string(), |t| t.display().to_string());
+        match fs::metadata(path) {
+            Ok(resolved) => {
+                findings.push(format!("{} is a symlink to {}", path, target));
+                resolved
+            }
+            Err(_) => {
+                findings.push(format!("{} is a dangling symlink to {}", path, target));
+                return findings;
+            }
+        }
+    } else {
+        meta
+    };
+
+    findings.push(format!(
+        "{}, mode {:04o}, owner {}:{}",
+        file_type(meta.file_type()),
+        meta.permissions().mode() & 0o7777,
+        meta.uid(),
+        meta.gid()
+    ));
+    if !meta.is_file() {
+        return findings;
+    }
+    if meta.permissions().mode() & 0o111 == 0 {
+        findings.push("nobody may execute it".to_string());
+    } else if !executable_by_us(path) {
+        findings.push(format!("uid {} may not execute it", unsafe {
+            libc::geteuid()
+        }));
+    }
+    if on_noexec_mount(path) {
+        findings.push("it's on a noexec mount".to_string());
+    }
+    findings.push(contents(path));
+    findings
+}
@@ -0,0 +1,23 @@
+// Interactive: usermode-helper --dump <config>
+//
+// Prints the config the way invocations see it: drop-ins merged, defaults
+// applied, seccomp profiles expanded, and capabilities deduplicated and
+// sorted. Warnings about the config go to stderr while it loads.
+
+use crate::Config;
+use crate::exit::Exit;
+use std::ffi::OsString;
+use std::io::Write;
+
+pub(crate) fn run(args: &[OsString]) {
+    let [config_path] = args else {
+        fail!(Exit::Usage, "usage: --dump <config>");
+    };
+    let config_path = config_path
+        .to_str()
+        .unwrap_or_else(|| fail!(Exit::Usage, "bad config path {:?}", config_path));
+    let config = Config::load(config_path);
+    let dump = toml::to_string(&config)
+        .unwrap_or_else(|e| fail!(Exit::Config, "couldn't serialize config: {}", e));
+    print!("{}", dump);
+}
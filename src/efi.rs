@@ -0,0 +1,21 @@
+// The platform's secure boot state, from the SecureBoot EFI variable.
+
+use std::fs;
+use std::io;
+
+pub(crate) const SECURE_BOOT_VAR: &str =
+    "/sys/firmware/efi/efivars/SecureBoot-8be4df61-93ca-11d2-aa0d-00e098032b8c";
+
+// efivarfs files are the variable's 4-byte attributes followed by its data,
+// here a single byte that is 1 when secure boot is enforced.
+pub(crate) fn secure_boot(path: &str) -> io::Result<bool> {
+    let raw = fs::read(path)?;
+    match raw[..] {
+        [_, _, _, _, 0] => Ok(false),
+        [_, _, _, _, 1] => Ok(true),
+        _ => Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "bad SecureBoot variable",
+        )),
+    }
+}
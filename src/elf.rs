@@ -0,0 +1,118 @@
+// Just enough ELF parsing to find a binary's program interpreter (PT_INTERP),
+// i.e. the dynamic linker the kernel will load to run it.
+
+use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom};
+
+const MAGIC: &[u8; 4] = b"\x7fELF";
+const PT_INTERP: u32 = 3;
+// Far more than any real binary has; bounds the work on a hostile file.
+const MAX_PHNUM: u16 = 512;
+const MAX_INTERP_LEN: u64 = 4096;
+
+fn invalid(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg.to_string())
+}
+
+struct Reader {
+    f: File,
+    is_64: bool,
+    big_endian: bool,
+}
+
+impl Reader {
+    fn bytes<const N: usize>(&mut self) -> io::Result<[u8; N]> {
+        let mut buf = [0; N];
+        self.f.read_exact(&mut buf)?;
+        if self.big_endian {
+            buf.reverse();
+        }
+        Ok(buf)
+    }
+
+    fn u16(&mut self) -> io::Result<u16> {
+        self.bytes().map(u16::from_le_bytes)
+    }
+
+    fn u32(&mut self) -> io::Result<u32> {
+        self.bytes().map(u32::from_le_bytes)
+    }
+
+    // An address/offset sized word: 4 bytes on ELF32, 8 on ELF64.
+    fn word(&mut self) -> io::Result<u64> {
+        if self.is_64 {
+            self.bytes().map(u64::from_le_bytes)
+        } else {
+            self.u32().map(u64::from)
+        }
+    }
+}
+
+// Returns Ok(None) for files that aren't ELF at all (e.g. scripts), and for
+// ELF files without an interpreter (e.g. static binaries).
+pub(crate) fn interpreter(path: &str) -> io::Result<Option<String>> {
+    let mut f = File::open(path)?;
+    let mut ident = [0u8; 16];
+    if f.read_exact(&mut ident).is_err() || &ident[..4] != MAGIC {
+        return Ok(None);
+    }
+    let mut r = Reader {
+        f,
+        is_64: match ident[4] {
+            1 => false,
+            2 => true,
+            _ => return Err(invalid("bad ELF class")),
+        },
+        big_endian: match ident[5] {
+            1 => false,
+            2 => true,
+            _ => return Err(invalid("bad ELF data encoding")),
+        },
+    };
+
+    // e_phoff follows e_type, e_machine, e_version and e_entry.
+    let entry_len = if r.is_64 { 8 } else { 4 };
+    r.f.seek(SeekFrom::Start(16 + 2 + 2 + 4 + entry_len))?;
+    let phoff = r.word()?;
+    // Skip e_shoff, e_flags and e_ehsize.
+    r.f.seek(SeekFrom::Current(entry_len as i64 + 4 + 2))?;
+    let phentsize = r.u16()?;
+    let phnum = r.u16()?;
+    if phnum > MAX_PHNUM {
+        return Err(invalid("too many program headers"));
+    }
+
+    for i in 0..u64::from(phnum) {
+        let header = i
+            .checked_mul(u64::from(phentsize))
+            .and_then(|off| off.checked_add(phoff))
+            .ok_or_else(|| invalid("program header offset overflows"))?;
+        r.f.seek(SeekFrom::Start(header))?;
+        if r.u32()? != PT_INTERP {
+            continue;
+        }
+        // ELF64 has p_flags here, ELF32 has it after p_memsz.
+        if r.is_64 {
+            r.u32()?;
+        }
+        let offset = r.word()?;
+        // Skip p_vaddr and p_paddr.
+        r.word()?;
+        r.word()?;
+        let size = r.word()?;
+        if size > MAX_INTERP_LEN {
+            return Err(invalid("PT_INTERP too large"));
+        }
+
+        let mut interp = vec![0; size as usize];
+        r.f.seek(SeekFrom::Start(offset))?;
+        r.f.read_exact(&mut interp)?;
+        if let Some(nul) = interp.iter().position(|b| *b == 0) {
+            interp.truncate(nul);
+        }
+        return String::from_utf8(interp)
+            .map(Some)
+            .map_err(|_| invalid("PT_INTERP isn't valid UTF-8"));
+    }
+    Ok(None)
+}
@@ -0,0 +1,98 @@
+// Every way huldufolk itself can exit, so monitoring can map exit codes to
+// causes. (A helper's own exit status, passed through when supervising it, is
+// of course not in here.)
+
+#[derive(Clone, Copy)]
+pub(crate) enum Exit {
+    Denied = 1,
+    NoDevNull = 2,
+    FdSetup = 3,
+    Underprivileged = 4,
+    Config = 5,
+    Privileges = 6,
+    Rejected = 7,
+    SetupTimeout = 8,
+    Exec = 9,
+    RunTimeout = 10,
+    Usage = 11,
+    Probe = 12,
+    Integrity = 13,
+    Policy = 14,
+}
+
+impl Exit {
+    pub(crate) const ALL: &[Exit] = &[
+        Exit::Denied,
+        Exit::NoDevNull,
+        Exit::FdSetup,
+        Exit::Underprivileged,
+        Exit::Config,
+        Exit::Privileges,
+        Exit::Rejected,
+        Exit::SetupTimeout,
+        Exit::Exec,
+        Exit::RunTimeout,
+        Exit::Usage,
+        Exit::Probe,
+        Exit::Integrity,
+        Exit::Policy,
+    ];
+
+    pub(crate) fn name(self) -> &'static str {
+        match self {
+            Exit::Denied => "denied",
+            Exit::NoDevNull => "no_dev_null",
+            Exit::FdSetup => "fd_setup",
+            Exit::Underprivileged => "underprivileged",
+            Exit::Config => "config",
+            Exit::Privileges => "privileges",
+            Exit::Rejected => "rejected",
+            Exit::SetupTimeout => "setup_timeout",
+            Exit::Exec => "exec",
+            Exit::RunTimeout => "run_timeout",
+            Exit::Usage => "usage",
+            Exit::Probe => "probe",
+            Exit::Integrity => "integrity",
+            Exit::Policy => "policy",
+        }
+    }
+
+    pub(crate) fn description(self) -> &'static str {
+        match self {
+            Exit::Denied => "no helper matches the invocation, or the killswitch is active",
+            Exit::NoDevNull => "couldn't open /dev/null to sanitize file descriptors",
+            Exit::FdSetup => "couldn't sanitize file descriptors",
+            Exit::Underprivileged => "huldufolk lacks capabilities the helper needs",
+            Exit::Config => "the config couldn't be read, parsed, validated or trusted",
+            Exit::Privileges => "restricting privileges failed",
+            Exit::Rejected => "a check before exec rejected the helper",
+            Exit::SetupTimeout => "huldufolk took longer than setup_timeout",
+            Exit::Exec => "the helper couldn't be run",
+            Exit::RunTimeout => "the helper took longer than run_timeout",
+            Exit::Usage => "bad command line in an interactive mode",
+            Exit::Probe => "the helper failed its probe",
+            Exit::Integrity => {
+                "the config or binary hash doesn't match the kernel command line, or can't be checked"
+            }
+            Exit::Policy => {
+                "a matched helper is denied by secure boot, its invocation budget, the kernel RNG or the audit log"
+            }
+        }
+    }
+
+    pub(crate) fn code(self) -> i32 {
+        self as i32
+    }
+
+    #[allow(clippy::disallowed_methods)]
+    pub(crate) fn exit(self) -> ! {
+        std::process::exit(self.code())
+    }
+}
+
+// Interactive: usermode-helper --exit-codes
+pub(crate) fn print_table() {
+    for exit in Exit::ALL {
+        println!("{}\t{}\t{}", exit.code(), exit.name(), exit.description());
+    }
+}
@@ -0,0 +1,103 @@
+// Interactive: usermode-helper --export-policy <config>
+//
+// Prints what the config allows as JSON, for tools that derive a
+// complementary policy from it (BPF-LSM programs, audit rules): which programs
+// may run as helpers, and the most capabilities each can end up with. Nothing
+// here is enforced by huldufolk itself; it's the same resolved config that
+// invocations use, in a form that doesn't need our config format.
+//
+// The output is versioned: fields may be added within a version, but a field
+// that changes meaning or goes away bumps EXPORT_VERSION. Helpers are listed in
+// config order (main config, then drop-ins) and capability names are sorted,
+// so the same config always exports the same bytes.
+
+use crate::exit::Exit;
+use crate::{Config, Helper, cap_list, priv_plan};
+use serde::Serialize;
+use std::collections::HashSet;
+use std::ffi::OsString;
+use std::io::Write;
+
+const EXPORT_VERSION: u32 = 1;
+
+#[derive(Serialize)]
+struct Export<'a> {
+    version: u32,
+    helpers: Vec<ExportedHelper<'a>>,
+    // Runs, without any capabilities, for invocations no helper matches.
+    deny_stub: Option<&'a str>,
+}
+
+#[derive(Serialize)]
+struct ExportedHelper<'a> {
+    // Exactly one of path, path_prefix, path_suffix and allowed_dirs is set,
+    // like in the config.
+    path: Option<&'a str>,
+    path_prefix: Option<&'a str>,
+    path_suffix: Option<&'a str>,
+    allowed_dirs: Option<&'a [String]>,
+    // What actually gets executed, if it isn't the matched program.
+    exec_path: Option<&'a str>,
+    argc: Option<usize>,
+    user: Option<&'a str>,
+    // The helper's capabilities only apply within its own user namespace.
+    userns: bool,
+    // The helper keeps all of root's capabilities.
+    unrestricted: bool,
+    capabilities: Vec<String>,
+    ambient: Vec<String>,
+}
+
+fn export_helper<'a>(config: &Config, helper: &'a Helper) -> ExportedHelper<'a> {
+    let (unrestricted, caps) = match &helper.capabilities {
+        Some(caps) => (false, caps.clone()),
+        // Switching to another user without capabilities configured drops
+        // them all; otherwise the helper runs as root, with everything.
+        None if !helper.userns && helper.identity().is_some_and(|e| e.uid != 0) => {
+            (false, HashSet::new())
+        }
+        None => (true, caps::all()),
+    };
+    let ambient = match &helper.capabilities {
+        // A helper the ambient_denylist fails never runs, so raises nothing.
+        Some(caps) => priv_plan(caps, &config.ambient_limit(helper))
+            .map(|plan| plan.ambient)
+            .unwrap_or_default(),
+        None => HashSet::new(),
+    };
+    ExportedHelper {
+        path: helper.path.as_deref(),
+        path_prefix: helper.path_prefix.as_deref(),
+        path_suffix: helper.path_suffix.as_deref(),
+        allowed_dirs: helper.allowed_dirs.as_deref(),
+        exec_path: helper.exec_path.as_deref(),
+        argc: helper.argc,
+        user: helper.user.as_deref(),
+        userns: helper.userns,
+        unrestricted,
+        capabilities: cap_list(&caps),
+        ambient: cap_list(&ambient),
+    }
+}
+
+pub(crate) fn run(args: &[OsString]) {
+    let [config_path] = args else {
+        fail!(Exit::Usage, "usage: --export-policy <config>");
+    };
+    let config_path = config_path
+        .to_str()
+        .unwrap_or_else(|| fail!(Exit::Usage, "bad config path {:?}", config_path));
+    let config = Config::load(config_path);
+    let export = Export {
+        version: EXPORT_VERSION,
+        helpers: config
+            .helpers
+            .iter()
+            .map(|helper| export_helper(&config, helper))
+            .collect(),
+        deny_stub: config.deny_stub.as_deref(),
+    };
+    let json = serde_json::to_string_pretty(&export)
+        .unwrap_or_else(|e| fail!(Exit::Config, "couldn't serialize policy: {}", e));
+    println!("{}", json);
+}
@@ -0,0 +1,5 @@
+// Library side of the usermode-helper crate: what other tools (config
+// linters, policy generators) can reuse without pulling in the binary's
+// config, logging and privilege handling.
+
+pub mod seccomp;
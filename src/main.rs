@@ -1,90 +1,2652 @@
 #![deny(warnings)]
 
 use caps::{CapSet, Capability};
-use serde::Deserialize;
-use std::collections::HashSet;
-use std::ffi::OsString;
+use exit::Exit;
+use regex::bytes::Regex;
+use serde::{Deserialize, Serialize};
+use std::cell::Cell;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::ffi::{OsStr, OsString};
 use std::fs;
-use std::io::Write;
+use std::io::{Read, Write};
+use std::os::unix::fs::MetadataExt;
 use std::os::unix::io::{AsRawFd, IntoRawFd};
-use std::os::unix::process::CommandExt;
-use std::process::{Command, exit};
+use std::os::unix::process::{CommandExt, ExitStatusExt};
+use std::path::Path;
+use std::process::{Child, Command, ExitStatus, Stdio};
 use std::str::FromStr;
+use std::sync::OnceLock;
+use std::sync::atomic::{AtomicBool, AtomicU8, Ordering};
+use std::thread::sleep;
+use std::time::{Duration, Instant};
+use usermode_helper::seccomp;
 
 use libc::{PR_SET_NO_NEW_PRIVS, PR_SET_SECUREBITS, c_ulong};
 const SECBIT_NOROOT: c_ulong = 0x01;
+const SECBIT_NO_SETUID_FIXUP: c_ulong = 0x04;
 const DEFAULT_CONFIG_PATH: Option<&'static str> = option_env!("DEFAULT_CONFIG_PATH");
+// Checked before the config is even read, so it works when the config is
+// what's broken. The config's own killswitch comes on top of it.
+const KILLSWITCH_PATH: &str = match option_env!("KILLSWITCH_PATH") {
+    Some(path) => path,
+    None => "/run/usermode-helper.killswitch",
+};
+// Security Hardening: Refuse config files that aren't root-owned or that are
+// group/world-writable. Only a build with ALLOW_UNSAFE_CONFIG_PERMS set skips
+// this, since a setting in the config would be up to whoever can write it.
+const VERIFY_CONFIG_PERMS: bool = option_env!("ALLOW_UNSAFE_CONFIG_PERMS").is_none();
+const DEFAULT_PRECHECK_TIMEOUT: u64 = 5;
+const DEFAULT_PROBE_TIMEOUT: u64 = 5;
+// Most environment variables a helper gets without its own max_env_vars.
+const DEFAULT_MAX_ENV_VARS: usize = 1024;
+// CPU seconds past cpu_time_secs before the hard RLIMIT_CPU kills the helper.
+const CPU_TIME_GRACE: u64 = 2;
+// What userns helpers without a user are mapped to outside their namespace.
+const NOBODY: libc::uid_t = 65534;
+// Highest descriptor keep_fds and close_fds_from can keep open.
+const MAX_KEPT_FD: u32 = 1023;
+// Bounds config loading and matching, before any helper's setup_timeout is
+// known. Set at build time, since it has to hold whatever the config says.
+const MATCH_TIMEOUT: u64 = match option_env!("MATCH_TIMEOUT_SECS") {
+    Some(secs) => parse_secs(secs),
+    None => 30,
+};
 
+const fn parse_secs(secs: &str) -> u64 {
+    let digits = secs.as_bytes();
+    assert!(!digits.is_empty(), "MATCH_TIMEOUT_SECS is empty");
+    let mut value = 0;
+    let mut i = 0;
+    while i < digits.len() {
+        assert!(
+            digits[i].is_ascii_digit(),
+            "MATCH_TIMEOUT_SECS isn't a number"
+        );
+        value = value * 10 + (digits[i] - b'0') as u64;
+        i += 1;
+    }
+    value
+}
+
+// Set once at startup from HULDUFOLK_DEBUG.
+static DEBUG: AtomicBool = AtomicBool::new(false);
+// Set once stderr goes to /dev/kmsg, where each message gets a "<N>" syslog
+// priority prefix, by category (as configured by kmsg_priority, once loaded).
+static KMSG: AtomicBool = AtomicBool::new(false);
+static KMSG_PRIORITIES: [AtomicU8; 4] = [
+    AtomicU8::new(libc::LOG_ERR as u8),
+    AtomicU8::new(libc::LOG_WARNING as u8),
+    AtomicU8::new(libc::LOG_DEBUG as u8),
+    AtomicU8::new(libc::LOG_NOTICE as u8),
+];
+
+#[derive(Clone, Copy)]
+enum LogCategory {
+    Error,
+    Warn,
+    Debug,
+    Audit,
+}
+
+fn log_prefix(category: LogCategory) -> String {
+    if !KMSG.load(Ordering::Relaxed) {
+        return String::new();
+    }
+    let priority = KMSG_PRIORITIES[category as usize].load(Ordering::Relaxed);
+    format!("<{}>", priority)
+}
+
+// Logs the error, along with what its exit code means, and exits with it.
 macro_rules! fail {
+    ($exit:expr, $($arg:tt)*) => ({
+        let exit: $crate::exit::Exit = $exit;
+        let msg = format!(
+            "{}ERROR: {} (exit {}: {})\n",
+            $crate::log_prefix($crate::LogCategory::Error),
+            format_args!($($arg)*),
+            exit.code(),
+            exit.description()
+        );
+        let _ = std::io::stderr().write_all(msg.as_bytes());
+        exit.exit()
+    })
+}
+
+macro_rules! debug {
     ($($arg:tt)*) => ({
-        let msg = format!("ERROR: {}\n", format_args!($($arg)*));
+        if $crate::DEBUG.load(std::sync::atomic::Ordering::Relaxed) {
+            let msg = format!(
+                "{}DEBUG: {}\n",
+                $crate::log_prefix($crate::LogCategory::Debug),
+                format_args!($($arg)*)
+            );
+            let _ = std::io::Write::write_all(&mut std::io::stderr(), msg.as_bytes());
+        }
+    })
+}
+
+macro_rules! warn {
+    ($($arg:tt)*) => ({
+        let msg = format!(
+            "{}WARNING: {}\n",
+            $crate::log_prefix($crate::LogCategory::Warn),
+            format_args!($($arg)*)
+        );
         let _ = std::io::stderr().write_all(msg.as_bytes());
-        exit(1)
     })
 }
 
-#[derive(Deserialize)]
-struct Config {
-    helpers: Vec<Helper>,
+mod argv_hash;
+#[cfg(feature = "test-mode")]
+mod as_helper;
+mod audit;
+mod bench;
+mod budget;
+#[cfg(feature = "config-cache")]
+mod cache;
+mod cap_usage;
+mod config_hash;
+mod diagnose;
+mod dump;
+mod efi;
+mod elf;
+mod exit;
+mod export;
+mod mounts;
+mod output;
+mod passwd;
+mod rng;
+mod schema;
+mod self_verify;
+mod validate;
+
+fn default_true() -> bool {
+    true
+}
+
+fn default_max_dropin_files() -> usize {
+    1024
+}
+
+fn default_workdir() -> String {
+    "/".to_string()
+}
+
+fn default_env_path() -> String {
+    "/sbin:/bin:/usr/sbin:/usr/bin".to_string()
+}
+
+fn default_passwd_file() -> String {
+    "/etc/passwd".to_string()
+}
+
+fn default_invocation_counter() -> String {
+    "/run/usermode-helper.invocations".to_string()
+}
+
+fn default_secure_boot_var() -> String {
+    efi::SECURE_BOOT_VAR.to_string()
+}
+
+fn default_trusted_interpreters() -> Vec<String> {
+    [
+        "/lib64/ld-linux-x86-64.so.2",
+        "/lib/ld-linux.so.2",
+        "/lib/ld-linux-aarch64.so.1",
+        "/lib/ld-linux-armhf.so.3",
+        "/lib/ld-musl-x86_64.so.1",
+        "/lib/ld-musl-aarch64.so.1",
+    ]
+    .map(String::from)
+    .to_vec()
+}
+
+#[derive(Deserialize, Clone, Copy, Default)]
+#[serde(rename_all = "snake_case")]
+enum UnknownCapPolicy {
+    #[default]
+    Error,
+    WarnSkip,
+    Numeric,
+}
+
+thread_local! {
+    // Context for deserialize_caps, which serde gives no other way to reach.
+    static UNKNOWN_CAP_POLICY: Cell<UnknownCapPolicy> = const { Cell::new(UnknownCapPolicy::Error) };
+}
+
+// Settings that must be known before the rest of the config can be parsed.
+#[derive(Deserialize)]
+struct Preamble {
+    // Compatibility: How to treat capability names the caps crate doesn't know,
+    // so one config can serve kernels newer than this build.
+    #[serde(default)]
+    unknown_cap_policy: UnknownCapPolicy,
+    // Security Hardening: Bound the startup work done for the drop-in directory.
+    #[serde(default = "default_max_dropin_files")]
+    max_dropin_files: usize,
+    #[serde(default)]
+    recursive_dropins: bool,
+}
+
+// A drop-in only adds helpers; global settings stay in the main config.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct DropIn {
+    #[serde(default)]
+    helpers: Vec<Helper>,
+}
+
+// Helpers for the same program and argc defined in more than one file are
+// resolved per duplicate_policy; a helper without argc overlaps with every
+// argc. Several entries for a program in a single file are left alone, since
+// their order there is deliberate.
+fn resolve_duplicates(
+    helpers: Vec<Helper>,
+    origins: Vec<&str>,
+    policy: DuplicatePolicy,
+) -> Result<(Vec<Helper>, Vec<&str>), String> {
+    let key = |helper: &Helper| match helper.argc {
+        Some(argc) => format!("{} (argc {})", helper.name(), argc),
+        None => helper.name(),
+    };
+    let overlap = |a: &Helper, b: &Helper| {
+        a.name() == b.name() && (a.argc.is_none() || b.argc.is_none() || a.argc == b.argc)
+    };
+    // Per helper, the files defining a helper it overlaps with, in order.
+    let defined_in: Vec<Vec<&str>> = helpers
+        .iter()
+        .map(|helper| {
+            let mut files = Vec::new();
+            for (other, origin) in helpers.iter().zip(&origins) {
+                if overlap(helper, other) && !files.contains(origin) {
+                    files.push(*origin);
+                }
+            }
+            files
+        })
+        .collect();
+
+    let mut kept = (Vec::new(), Vec::new());
+    for ((helper, origin), files) in helpers.into_iter().zip(origins).zip(defined_in) {
+        let key = key(&helper);
+        let winner = match (files.as_slice(), policy) {
+            ([first, second, ..], DuplicatePolicy::Error) => {
+                return Err(format!(
+                    "helper {} is defined in both {} and {}",
+                    key, first, second
+                ));
+            }
+            ([first, _, ..], DuplicatePolicy::FirstWins) => *first,
+            ([.., _, last], DuplicatePolicy::LastWins) => *last,
+            _ => origin,
+        };
+        if winner == origin {
+            kept.0.push(helper);
+            kept.1.push(origin);
+        } else {
+            warn!(
+                "ignoring helper {} in {}, {} defines it too",
+                key, origin, winner
+            );
+        }
+    }
+    Ok(kept)
+}
+
+// A config file (the main one or a drop-in) as read from disk. Its
+// permissions are checked right away, before anything in it is looked at.
+struct ConfigFile {
+    path: String,
+    raw: Vec<u8>,
+}
+
+impl ConfigFile {
+    fn read(path: &str) -> Self {
+        let (raw, perms) = read_config_file(path)
+            .unwrap_or_else(|e| fail!(Exit::Config, "couldn't read config file {}: {}", path, e));
+        if VERIFY_CONFIG_PERMS && let Err(reason) = perms {
+            fail!(
+                Exit::Config,
+                "refusing to trust config file {}: {}",
+                path,
+                reason
+            );
+        }
+        ConfigFile {
+            path: path.to_string(),
+            raw,
+        }
+    }
+
+    fn text(&self) -> &str {
+        std::str::from_utf8(&self.raw).unwrap_or_else(|e| {
+            fail!(
+                Exit::Config,
+                "couldn't parse config file {}: {}",
+                self.path,
+                e
+            )
+        })
+    }
+}
+
+// Drop-ins are the *.conf files in <config>.d, read in file name order (and,
+// with recursive_dropins, depth first). A missing directory means none.
+fn read_dropins(path: &str, preamble: &Preamble) -> Vec<ConfigFile> {
+    let mut paths = Vec::new();
+    collect_dropins(&format!("{}.d", path), preamble, &mut paths);
+    paths.iter().map(|p| ConfigFile::read(p)).collect()
+}
+
+fn collect_dropins(dir: &str, preamble: &Preamble, paths: &mut Vec<String>) {
+    let entries = match fs::read_dir(dir) {
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return,
+        entries => entries.unwrap_or_else(|e| {
+            fail!(
+                Exit::Config,
+                "couldn't read drop-in directory {}: {}",
+                dir,
+                e
+            )
+        }),
+    };
+    let mut entries: Vec<fs::DirEntry> = entries.collect::<Result<_, _>>().unwrap_or_else(|e| {
+        fail!(
+            Exit::Config,
+            "couldn't read drop-in directory {}: {}",
+            dir,
+            e
+        )
+    });
+    entries.sort_by_key(|entry| entry.file_name());
+
+    for entry in entries {
+        let Some(name) = entry.file_name().to_str().map(str::to_string) else {
+            continue;
+        };
+        let path = format!("{}/{}", dir, name);
+        // Doesn't follow symlinks, so a symlinked directory is never recursed.
+        if entry.file_type().is_ok_and(|t| t.is_dir()) {
+            if preamble.recursive_dropins {
+                collect_dropins(&path, preamble, paths);
+            }
+            continue;
+        }
+        if !name.ends_with(".conf") {
+            continue;
+        }
+        if paths.len() == preamble.max_dropin_files {
+            fail!(
+                Exit::Config,
+                "too many drop-in files in {} (max_dropin_files is {})",
+                dir,
+                preamble.max_dropin_files
+            );
+        }
+        paths.push(path);
+    }
+}
+
+#[derive(Deserialize, Serialize)]
+struct Config {
+    // Incident response: if this path exists, every helper is denied.
+    killswitch: Option<String>,
+    // Quarantine: an invocation no helper matches execs this program (with the
+    // same argv) instead of just failing, e.g. to record or report it. It runs
+    // with no capabilities at all, its bounding set emptied too, and its exit
+    // status is what the kernel sees.
+    deny_stub: Option<String>,
+    // Security Hardening: Helpers without their own workdir start here rather
+    // than in whatever (possibly deleted or attacker-influenced) directory we
+    // were started in. An empty string keeps the inherited directory.
+    #[serde(default = "default_workdir")]
+    default_workdir: String,
+    // PATH in the sanitized environment of helpers without their own env_path.
+    // An empty string really sets PATH="", forcing helpers to use absolute
+    // paths for anything they run.
+    #[serde(default = "default_env_path")]
+    default_env_path: String,
+    // Dynamic linkers that helpers with verify_interp may be loaded by.
+    #[serde(default = "default_trusted_interpreters")]
+    trusted_interpreters: Vec<String>,
+    // Named syscall allowlists for helpers' seccomp setting, e.g.
+    // modprobe = ["@filesystem", "finit_module"]. These take precedence over
+    // the built-in @minimal and @filesystem profiles.
+    #[serde(default)]
+    seccomp_profiles: BTreeMap<String, Vec<String>>,
+    // What to do when the helper we matched and the program we'd actually exec
+    // don't add up, e.g. an exec_path that resolves back to the helper itself.
+    #[serde(default)]
+    exec_coherence: ExecCoherence,
+    // What to do when the main config and drop-ins (or two drop-ins) both
+    // define a helper for the same program and argc: by default, refuse the
+    // config rather than let one file silently override another's grants.
+    #[serde(default)]
+    duplicate_policy: DuplicatePolicy,
+    // Append a JSON record of every invocation (allowed or denied) here.
+    audit_log: Option<String>,
+    // Deny invocations whose audit record can't be written, rather than just
+    // logging the failure.
+    #[serde(default)]
+    audit_required: bool,
+    // What to do when the audit log's filesystem is full; "fail" with
+    // audit_required, "drop" otherwise, if unset.
+    audit_full_policy: Option<AuditFullPolicy>,
+    // Audit-lite: log a one-line summary of every allowed invocation (to kmsg,
+    // like everything else we log).
+    #[serde(default)]
+    log_success: bool,
+    // Where helpers' users are looked up.
+    #[serde(default = "default_passwd_file")]
+    passwd_file: String,
+    // Security Hardening: the uids helpers with a user may run as, as uids and
+    // "first-last" ranges, so that e.g. a package's drop-in can't have its
+    // helper run as another service's user. Unrestricted if unset.
+    allowed_target_uids: Option<Vec<UidRange>>,
+    // Security Hardening: Capabilities never raised ambient, whatever helpers
+    // are configured with, since ambient capabilities persist across exec
+    // unconditionally. Per ambient_denylist_policy, they're either left out of
+    // the ambient set only, or fail the helper.
+    #[serde(
+        deserialize_with = "deserialize_caps",
+        serialize_with = "serialize_caps",
+        skip_serializing_if = "Option::is_none",
+        default
+    )]
+    ambient_denylist: Option<HashSet<Capability>>,
+    #[serde(default)]
+    ambient_denylist_policy: AmbientDenylistPolicy,
+    // Per-boot invocation counts of helpers with max_invocations; should be on
+    // a tmpfs such as /run, so that rebooting resets them.
+    #[serde(default = "default_invocation_counter")]
+    invocation_counter: String,
+    // Platform trust: deny helpers (all of them, unless overridden per helper)
+    // when the boot chain wasn't verified, i.e. secure boot is off. Where the
+    // state can't be told (e.g. on non-EFI systems), secure_boot_unknown
+    // decides.
+    #[serde(default)]
+    require_secure_boot: bool,
+    #[serde(default)]
+    secure_boot_unknown: SecureBootUnknown,
+    #[serde(default = "default_secure_boot_var")]
+    secure_boot_var: String,
+    // Syslog priorities (0-7) of what we log to kmsg, by category, so that e.g.
+    // dmesg -l can tell errors from debug output. Messages logged before the
+    // config is loaded use the defaults.
+    #[serde(default)]
+    kmsg_priority: KmsgPriority,
+    // Measured boot: deny every helper unless the kernel command line has a
+    // huldufolk.confighash, and it matches (see config_hash.rs). A hash on the
+    // command line is checked either way.
+    #[serde(default)]
+    require_config_hash: bool,
+    helpers: Vec<Helper>,
+    // Of the config files as read, main config first.
+    #[serde(skip)]
+    digest: String,
+}
+
+#[derive(Deserialize, Serialize, Clone, Copy)]
+#[serde(default, deny_unknown_fields)]
+struct KmsgPriority {
+    error: u8,
+    warn: u8,
+    debug: u8,
+    audit: u8,
+}
+
+impl Default for KmsgPriority {
+    fn default() -> Self {
+        KmsgPriority {
+            error: libc::LOG_ERR as u8,
+            warn: libc::LOG_WARNING as u8,
+            debug: libc::LOG_DEBUG as u8,
+            audit: libc::LOG_NOTICE as u8,
+        }
+    }
+}
+
+impl KmsgPriority {
+    fn install(self) {
+        for (category, priority) in [
+            (LogCategory::Error, self.error),
+            (LogCategory::Warn, self.warn),
+            (LogCategory::Debug, self.debug),
+            (LogCategory::Audit, self.audit),
+        ] {
+            KMSG_PRIORITIES[category as usize].store(priority, Ordering::Relaxed);
+        }
+    }
+}
+
+impl Config {
+    // Modernization & Refactoring: Encapsulated configuration loading and parsing.
+    fn load(path: &str) -> Self {
+        Self::read(path, false)
+    }
+
+    // Like load, but a full parse also refreshes the config cache. Only the
+    // kernel's invocations do that, so --validate, --dump and the like never
+    // replace the cache helpers are matched against.
+    fn load_and_cache(path: &str) -> Self {
+        Self::read(path, true)
+    }
+
+    fn read(path: &str, refresh_cache: bool) -> Self {
+        let main = ConfigFile::read(path);
+
+        // The preamble also bounds the drop-ins we read, so it comes first.
+        let preamble: Preamble = toml::from_str(main.text())
+            .unwrap_or_else(|e| fail!(Exit::Config, "couldn't parse config file {}: {}", path, e));
+        UNKNOWN_CAP_POLICY.set(preamble.unknown_cap_policy);
+
+        let mut files = vec![main];
+        files.extend(read_dropins(path, &preamble));
+        let mut config = Self::parse(&files, refresh_cache);
+        config.digest = config_hash::digest(files.iter().map(|file| file.raw.as_slice()));
+        config
+    }
+
+    // files holds the main config followed by its drop-ins.
+    fn parse(files: &[ConfigFile], refresh_cache: bool) -> Self {
+        #[cfg(feature = "config-cache")]
+        if let Some(config) = cache::load(files) {
+            return config;
+        }
+
+        let (main, dropins) = files.split_first().expect("no main config file");
+        let mut config: Config = toml::from_str(main.text()).unwrap_or_else(|e| {
+            fail!(
+                Exit::Config,
+                "couldn't parse config file {}: {}",
+                main.path,
+                e
+            )
+        });
+        let mut origins = vec![main.path.as_str(); config.helpers.len()];
+        for file in dropins {
+            let dropin: DropIn = toml::from_str(file.text()).unwrap_or_else(|e| {
+                fail!(
+                    Exit::Config,
+                    "couldn't parse config file {}: {}",
+                    file.path,
+                    e
+                )
+            });
+            origins.extend(std::iter::repeat_n(
+                file.path.as_str(),
+                dropin.helpers.len(),
+            ));
+            config.helpers.extend(dropin.helpers);
+        }
+        let helpers = std::mem::take(&mut config.helpers);
+        let (helpers, origins) = resolve_duplicates(helpers, origins, config.duplicate_policy)
+            .unwrap_or_else(|e| fail!(Exit::Config, "invalid config file {}: {}", main.path, e));
+        config.helpers = helpers;
+
+        config
+            .apply_defaults()
+            .unwrap_or_else(|e| fail!(Exit::Config, "invalid config file {}: {}", main.path, e));
+        for (helper, origin) in config.helpers.iter().zip(origins) {
+            helper.validate().unwrap_or_else(|e| {
+                fail!(
+                    Exit::Config,
+                    "invalid helper {} in {}: {}",
+                    helper.name(),
+                    origin,
+                    e
+                )
+            });
+        }
+
+        #[cfg(feature = "config-cache")]
+        if refresh_cache {
+            cache::store(files, &config);
+        }
+        #[cfg(not(feature = "config-cache"))]
+        let _ = refresh_cache;
+        config
+    }
+
+    // Resolves global defaults into each helper, so everything after load (and
+    // the config cache) only has to look at the helper. Seccomp profile
+    // references are expanded here too.
+    fn apply_defaults(&mut self) -> Result<(), String> {
+        let priority = self.kmsg_priority;
+        if [
+            priority.error,
+            priority.warn,
+            priority.debug,
+            priority.audit,
+        ]
+        .iter()
+        .any(|p| *p > libc::LOG_DEBUG as u8)
+        {
+            return Err("kmsg_priority values must be between 0 and 7".to_string());
+        }
+        if let Some(stub) = &self.deny_stub
+            && !stub.starts_with('/')
+        {
+            return Err(format!("deny_stub {} isn't an absolute path", stub));
+        }
+        for helper in &mut self.helpers {
+            if helper.workdir.is_none() && !self.default_workdir.is_empty() {
+                helper.workdir = Some(self.default_workdir.clone());
+            }
+            if helper.env_path.is_none() {
+                helper.env_path = Some(self.default_env_path.clone());
+            }
+            // Required caps are checked in the ambient set, so one that can't
+            // be raised there would fail every invocation.
+            if let Some(required) = &helper.require_capabilities {
+                if !helper.raise_ambient {
+                    return Err(format!(
+                        "helper {}: require_capabilities needs raise_ambient",
+                        helper.name()
+                    ));
+                }
+                let denied: HashSet<Capability> = required
+                    .intersection(self.ambient_denylist.as_ref().unwrap_or(&HashSet::new()))
+                    .copied()
+                    .collect();
+                if !denied.is_empty() {
+                    return Err(format!(
+                        "helper {}: require_capabilities lists {}, which ambient_denylist denies",
+                        helper.name(),
+                        cap_names(&denied)
+                    ));
+                }
+                helper
+                    .capabilities
+                    .get_or_insert_default()
+                    .extend(required.iter().copied());
+            }
+            if let (Some(caps), Some(denylist), true) = (
+                &helper.capabilities,
+                &self.ambient_denylist,
+                helper.raise_ambient,
+            ) {
+                let denied: HashSet<Capability> = caps.intersection(denylist).copied().collect();
+                if !denied.is_empty() {
+                    warn!(
+                        "helper {} lists {}, which ambient_denylist also denies",
+                        helper.name(),
+                        cap_names(&denied)
+                    );
+                }
+            }
+            if helper.passwd_file.is_none() {
+                helper.passwd_file = Some(self.passwd_file.clone());
+            }
+            check_target_uid(self.allowed_target_uids.as_deref(), helper)?;
+            if helper.require_secure_boot.is_none() {
+                helper.require_secure_boot = Some(self.require_secure_boot);
+            }
+            if helper.capture_output && helper.output_log.is_none() && self.audit_log.is_none() {
+                return Err(format!(
+                    "helper {}: capture_output requires output_log or audit_log",
+                    helper.name()
+                ));
+            }
+            if helper.capability_report && self.audit_log.is_none() {
+                return Err(format!(
+                    "helper {}: capability_report requires audit_log",
+                    helper.name()
+                ));
+            }
+            // Whoever can edit an unverified config could point allowed_dirs
+            // anywhere, and so run anything.
+            if helper.allowed_dirs.is_some() && !VERIFY_CONFIG_PERMS {
+                return Err(format!(
+                    "helper {}: allowed_dirs requires config permission checks, which this build skips",
+                    helper.name()
+                ));
+            }
+            if helper.trusted_interpreters.is_none() {
+                helper.trusted_interpreters = Some(self.trusted_interpreters.clone());
+            }
+            if let Some(spec) = &helper.seccomp {
+                let syscalls = seccomp::resolve(spec, &self.seccomp_profiles)
+                    .map_err(|e| format!("helper {}: {}", helper.name(), e))?;
+                helper.seccomp = Some(syscalls);
+            }
+        }
+        Ok(())
+    }
+
+    // Denies every helper unless the config matches huldufolk.confighash.
+    fn check_config_hash(&self) {
+        let expected = match config_hash::cmdline() {
+            Ok(cmdline) => config_hash::parse(&cmdline)
+                .unwrap_or_else(|e| fail!(Exit::Integrity, "{}, denying all helpers", e)),
+            Err(e) if self.require_config_hash => fail!(
+                Exit::Integrity,
+                "couldn't read {}: {}, denying all helpers",
+                config_hash::CMDLINE_PATH,
+                e
+            ),
+            Err(e) => {
+                debug!(
+                    "no config hash to check, couldn't read {}: {}",
+                    config_hash::CMDLINE_PATH,
+                    e
+                );
+                None
+            }
+        };
+        match expected {
+            None if self.require_config_hash => fail!(
+                Exit::Integrity,
+                "require_config_hash is set, but the kernel command line has no huldufolk.confighash, denying all helpers"
+            ),
+            None => {}
+            Some(expected) if expected == self.digest => {
+                debug!(
+                    "config hash {} matches the kernel command line",
+                    self.digest
+                )
+            }
+            Some(expected) => fail!(
+                Exit::Integrity,
+                "config hash {} doesn't match huldufolk.confighash={}, denying all helpers",
+                self.digest,
+                expected
+            ),
+        }
+    }
+
+    fn check_killswitch(&self) {
+        if let Some(path) = &self.killswitch {
+            check_killswitch(path);
+        }
+    }
+
+    fn find_helper(&self, args: &[OsString], env: &HashMap<OsString, OsString>) -> &Helper {
+        // Note: The kernel guarantees argv[0] exists for usermode helpers.
+        // We panic/fail if it's missing.
+        let name = args.first().expect("program doesn't have a 0 arg?");
+        let helper = self
+            .helpers
+            .iter()
+            .find(|s| s.allowed(args, env) && !s.skipped_as_missing(name));
+        if let Some(helper) = helper {
+            let _ = helper.matched_path.set(name.to_string_lossy().into_owned());
+        }
+        if let Some(log) = &self.audit_log
+            && let Err(e) = audit::record(
+                log,
+                self.audit_full_policy(),
+                args,
+                env,
+                helper.map(|h| h.path()),
+            )
+        {
+            let deny = self.audit_required
+                || (audit::is_full(&e) && self.audit_full_policy() == AuditFullPolicy::Fail);
+            match helper {
+                Some(helper) if deny => fail!(
+                    Exit::Policy,
+                    "couldn't write audit log {}, denying {}: {}",
+                    log,
+                    helper.path(),
+                    e
+                ),
+                _ => warn!("couldn't write audit log {}: {}", log, e),
+            }
+        }
+        helper.unwrap_or_else(|| {
+            if let Some(stub) = &self.deny_stub {
+                warn!("invalid usermode helper {:?}, running {}", name, stub);
+                self.exec_deny_stub(stub, args);
+            }
+            fail!(Exit::Denied, "invalid usermode helper {:?}", name)
+        })
+    }
+
+    // The stub gets no privileges, whatever we were invoked with: nothing in
+    // any capability set (bounding included), and no way to regain any.
+    fn exec_deny_stub(&self, stub: &str, args: &[OsString]) -> ! {
+        let mut cmd = Command::new(stub);
+        cmd.args(args.iter().skip(1))
+            .arg0(&args[0])
+            .env_clear()
+            .env("HOME", "/")
+            .env("TERM", "linux")
+            .env("PATH", &self.default_env_path);
+        if !self.default_workdir.is_empty() {
+            cmd.current_dir(&self.default_workdir);
+        }
+        drop_bounding_set();
+        priv_restrict(&HashSet::new(), &AmbientLimit::default());
+        close_fds(3, &BTreeSet::new());
+        set_setup_timer(Duration::ZERO);
+        let err = cmd.exec();
+        fail!(Exit::Exec, "couldn't run deny_stub {}: {}", stub, err);
+    }
+
+    // Pattern helpers allow any program with a matching name, so at least make
+    // sure the one that matched (and every directory leading to it) can only
+    // have been put there by root.
+    fn check_matched_path(&self, helper: &Helper) {
+        if helper.path.is_some() {
+            return;
+        }
+        let problem = match fs::canonicalize(helper.path()) {
+            Ok(resolved) => helper.outside_allowed_dirs(&resolved).or_else(|| {
+                resolved.ancestors().find_map(|p| match fs::metadata(p) {
+                    Ok(meta) if meta.uid() != 0 => {
+                        Some(format!("{} is owned by uid {}", p.display(), meta.uid()))
+                    }
+                    Ok(meta) if meta.mode() & 0o022 != 0 => Some(format!(
+                        "{} is group/world-writable (mode {:o})",
+                        p.display(),
+                        meta.mode() & 0o7777
+                    )),
+                    Ok(_) => None,
+                    Err(e) => Some(format!("{}: {}", p.display(), e)),
+                })
+            }),
+            Err(e) => Some(e.to_string()),
+        };
+        if let Some(problem) = problem {
+            fail!(
+                Exit::Rejected,
+                "refusing {}, matched by {}: {}",
+                helper.path(),
+                helper.name(),
+                problem
+            );
+        }
+    }
+
+    // Helpers with require_secure_boot only run while secure boot is on.
+    fn check_secure_boot(&self, helper: &Helper) {
+        if helper.require_secure_boot != Some(true) {
+            return;
+        }
+        match efi::secure_boot(&self.secure_boot_var) {
+            Ok(true) => debug!("secure boot is on"),
+            Ok(false) => fail!(
+                Exit::Policy,
+                "secure boot is off, denying {} (require_secure_boot)",
+                helper.path()
+            ),
+            Err(e) => match self.secure_boot_unknown {
+                SecureBootUnknown::Allow => {
+                    warn!(
+                        "secure boot state is unknown, allowing {}: {}",
+                        helper.path(),
+                        e
+                    )
+                }
+                SecureBootUnknown::Deny => fail!(
+                    Exit::Policy,
+                    "secure boot state is unknown, denying {} (require_secure_boot): {}",
+                    helper.path(),
+                    e
+                ),
+            },
+        }
+    }
+
+    // Helpers that should only run a bounded number of times per boot (e.g.
+    // one-shot firmware loaders) are denied once they've used up their budget.
+    // If the count can't be kept, they're denied as well. This is the last
+    // check before privileges are restricted, and the unit isn't given back if
+    // restricting or executing the helper fails after it: an attempt that got
+    // this far counts, whether or not the helper ended up running.
+    fn check_invocation_budget(&self, helper: &Helper) {
+        let Some(max) = helper.max_invocations else {
+            return;
+        };
+        match budget::take(&self.invocation_counter, helper.path(), max) {
+            Ok(true) => {}
+            Ok(false) => fail!(
+                Exit::Policy,
+                "{} already ran its max_invocations of {} since boot",
+                helper.path(),
+                max
+            ),
+            Err(e) => fail!(
+                Exit::Policy,
+                "couldn't count invocation of {} in {}, denying: {}",
+                helper.path(),
+                self.invocation_counter,
+                e
+            ),
+        }
+    }
+
+    fn audit_full_policy(&self) -> AuditFullPolicy {
+        match self.audit_full_policy {
+            Some(policy) => policy,
+            None if self.audit_required => AuditFullPolicy::Fail,
+            None => AuditFullPolicy::Drop,
+        }
+    }
+
+    fn ambient_limit(&self, helper: &Helper) -> AmbientLimit {
+        AmbientLimit {
+            denylist: self.ambient_denylist.clone().unwrap_or_default(),
+            policy: self.ambient_denylist_policy,
+            disabled: !helper.raise_ambient,
+        }
+    }
+
+    // Closes every inherited descriptor that no helper keeps.
+    fn close_unkept_fds(&self) {
+        close_fds(3, &self.helpers.iter().flat_map(Helper::kept_fds).collect());
+    }
+}
+
+#[derive(Deserialize, Serialize)]
+struct Helper {
+    // The program the kernel invokes (argv[0]). Alternatively, path_prefix or
+    // path_suffix match a family of helpers by name, and allowed_dirs any
+    // program directly inside the given directories; whatever program matched
+    // is then the helper's path. Since that allows any binary with a matching
+    // name, the matched program and its directories must be root's alone.
+    path: Option<String>,
+    path_prefix: Option<String>,
+    path_suffix: Option<String>,
+    allowed_dirs: Option<Vec<String>>,
+    #[serde(skip)]
+    matched_path: OnceLock<String>,
+    // Interposition: run this program instead of path, e.g. a wrapper. It still
+    // gets path as argv[0], and everything else (hook, checks, caps) is as
+    // configured for path.
+    exec_path: Option<String>,
+    argc: Option<usize>,
+    // Per-position patterns for argv[1..], which must all match (and imply the
+    // argument count). Each entry is a literal, "<any>", or "<ref:N>", meaning
+    // "equal to argv[N]" for an earlier position N.
+    args_template: Option<Vec<ArgPattern>>,
+    // The tightest allowlist: only invocations whose full argv has one of these
+    // digests (see argv_hash.rs, and --argv-hash to compute them) match.
+    argv_hashes: Option<Vec<String>>,
+    // Every argument that starts with "/" has to be under one of these, and
+    // can't contain "..". Arguments that start with "/" without being paths
+    // can be exempted by argv index with path_check_exempt_args.
+    allowed_path_prefixes: Option<Vec<String>>,
+    #[serde(default)]
+    path_check_exempt_args: Vec<usize>,
+    #[serde(
+        deserialize_with = "deserialize_caps",
+        serialize_with = "serialize_caps",
+        skip_serializing_if = "Option::is_none",
+        default
+    )]
+    // Modernization: Use 'caps' crate (Hashet) instead of the old 'capabilities'.
+    capabilities: Option<HashSet<Capability>>,
+    // Security Hardening: With raise_ambient = false, capabilities are only
+    // granted in the effective, inheritable and permitted sets, never ambient,
+    // so they don't survive the exec of a helper without matching file
+    // capabilities, let alone anything it runs in turn.
+    #[serde(default = "default_true")]
+    raise_ambient: bool,
+    // Capabilities the helper can't do without: granted like capabilities, and
+    // checked to still be there after privileges are restricted, failing
+    // closed otherwise. Unknown names are always an error here.
+    #[serde(
+        deserialize_with = "deserialize_required_caps",
+        serialize_with = "serialize_caps",
+        skip_serializing_if = "Option::is_none",
+        default
+    )]
+    require_capabilities: Option<HashSet<Capability>>,
+    // Policy plug-in: a program run (unprivileged, same argv) before the helper.
+    // The helper is only executed if the hook exits successfully.
+    pre_exec_hook: Option<String>,
+    // Escape hatch for capabilities that can be granted but don't actually work
+    // (e.g. under kernel lockdown): a program run like the pre-exec hook, but
+    // with exactly the helper's capabilities, that has to exit successfully
+    // within capability_precheck_timeout seconds for the helper to run.
+    capability_precheck: Option<String>,
+    capability_precheck_timeout: Option<u64>,
+    // Opt-in sanity check, e.g. ["--version"]: before the real invocation, the
+    // helper is run with these arguments (and probe_timeout), restricted like
+    // the real run, and has to exit successfully.
+    probe_args: Option<Vec<String>>,
+    probe_timeout: Option<u64>,
+    // Watchdogs, in seconds. setup_timeout bounds huldufolk's own work, from
+    // process start until the helper is exec'd (or spawned). run_timeout bounds
+    // the helper itself; setting it makes huldufolk fork and supervise the helper
+    // instead of exec'ing into it.
+    setup_timeout: Option<u64>,
+    run_timeout: Option<u64>,
+    // Run the helper in its own mount namespace, where nothing propagates to or
+    // from the host, with bind_mounts set up in it. verify_mounts then checks
+    // the result against mountinfo and fails closed if it isn't what was asked.
+    #[serde(default)]
+    mount_namespace: bool,
+    #[serde(default)]
+    bind_mounts: Vec<mounts::BindMount>,
+    #[serde(default)]
+    verify_mounts: bool,
+    // Supervised helpers only: log each line the helper prints, prefixed with
+    // "[stdout]" or "[stderr]", to output_log, or to the audit log if it has
+    // none. At most max_output_bytes of output are logged.
+    #[serde(default)]
+    capture_output: bool,
+    output_log: Option<String>,
+    max_output_bytes: Option<u64>,
+    // Supervised helpers only: make huldufolk the subreaper of the helper's
+    // process tree, so that descendants it leaves behind (e.g. by double
+    // forking) are reparented to us rather than to init. They're waited for
+    // until run_timeout, then killed, before we exit.
+    #[serde(default)]
+    reap_descendants: bool,
+    // Least-privilege tuning, for supervised helpers with capabilities: sample
+    // which of them the helper (and anything it runs) holds while it runs, and
+    // append a report to the audit log (see cap_usage.rs).
+    #[serde(default)]
+    capability_report: bool,
+    // Signals the helper starts with blocked, e.g. so it can't be interrupted
+    // mid-operation. SIGKILL and SIGSTOP can't be blocked and are rejected.
+    #[serde(
+        deserialize_with = "deserialize_signals",
+        serialize_with = "serialize_signals",
+        default
+    )]
+    block_signals: Vec<libc::c_int>,
+    // Start the helper with every signal at its default disposition and none
+    // blocked (other than block_signals), whatever state we were started in.
+    #[serde(default = "default_true")]
+    reset_signals: bool,
+    // Descriptors above stderr that the helper is handed and has to keep, e.g.
+    // one the kernel passes it. Every other one is closed once the helper is
+    // matched, from close_fds_from (3 by default) up; anything below
+    // close_fds_from is kept as well. Both go up to MAX_KEPT_FD, since
+    // anything above that is closed before the config is even read.
+    #[serde(default)]
+    keep_fds: Vec<u32>,
+    close_fds_from: Option<u32>,
+    // Environment variables set from invocation arguments, by argv index, e.g.
+    // { FSCK_DEVICE = 1 }. Note this moves untrusted, kernel/user-influenced
+    // data into the helper's environment, where the helper (and anything it
+    // runs) may treat it with less suspicion than its arguments.
+    #[serde(default)]
+    env_from_args: BTreeMap<String, usize>,
+    // Constraints on the environment the kernel invoked us with (e.g. uevent
+    // helpers' ACTION), as an exact value or { regex = "..." }. Regexes must
+    // match the whole value.
+    #[serde(default)]
+    match_env: BTreeMap<String, EnvMatch>,
+    #[serde(default)]
+    match_env_absent: AbsentEnvPolicy,
+    // Working directory for the helper (and its pre-exec hook).
+    workdir: Option<String>,
+    // PATH for the helper (and its pre-exec hook), overriding default_env_path.
+    env_path: Option<String>,
+    // Security Hardening: Before exec, check that an ELF helper's dynamic linker
+    // (PT_INTERP) is one of the trusted interpreters, closing off a library
+    // injection avenue. Static binaries and scripts aren't affected.
+    #[serde(default)]
+    verify_interp: bool,
+    trusted_interpreters: Option<Vec<String>>,
+    // Sanity check: refuse to exec anything but an ELF binary, e.g. a script,
+    // or a text file or directory that path points at by mistake.
+    #[serde(default)]
+    require_elf: bool,
+    // Debugging aid: with "diagnose", a failed exec is reported along with what
+    // we can tell about the exec target (see diagnose.rs), rather than with the
+    // bare errno only.
+    #[serde(default)]
+    on_exec_failure: OnExecFailure,
+    // With "skip", a helper whose program (exec_path, if set) doesn't exist
+    // doesn't match, and matching goes on with the next helper, e.g. for a
+    // config shared by systems that don't all have the program installed.
+    #[serde(default)]
+    missing_binary_policy: MissingBinaryPolicy,
+    // Debugging aid: let this helper dump core, up to core_limit bytes
+    // (unlimited by default). Dumps may contain secrets, so this is off by
+    // default, and helpers otherwise run with RLIMIT_CORE set to 0.
+    #[serde(default)]
+    allow_core: bool,
+    core_limit: Option<u64>,
+    // CPU time (not wall-clock time, unlike run_timeout) the helper may use, in
+    // seconds, as RLIMIT_CPU: the kernel sends SIGXCPU when it's used up, and
+    // SIGKILL CPU_TIME_GRACE seconds later.
+    cpu_time_secs: Option<u64>,
+    // Run the helper as this user (a name or uid from passwd_file) instead of
+    // root. Any capabilities are kept across the switch.
+    user: Option<String>,
+    passwd_file: Option<String>,
+    #[serde(skip)]
+    identity: OnceLock<Option<passwd::Entry>>,
+    // Extra variables for the helper's sanitized environment, overriding the
+    // defaults, e.g. { HOME = "/var/lib/foo" }.
+    #[serde(default)]
+    env: BTreeMap<String, String>,
+    // Most variables the helper's final environment (defaults, env and
+    // env_from_args) may have; DEFAULT_MAX_ENV_VARS if unset. More fails the
+    // invocation.
+    max_env_vars: Option<usize>,
+    // Overrides the global require_secure_boot.
+    require_secure_boot: Option<bool>,
+    // Security Hardening: for helpers that need good randomness early in boot
+    // (e.g. key generation), deny them while the kernel RNG isn't initialized
+    // yet, or with urandom_wait_secs, wait up to that long for it first.
+    #[serde(default)]
+    require_urandom_ready: bool,
+    urandom_wait_secs: Option<u64>,
+    // How many times the helper may run per boot.
+    max_invocations: Option<u64>,
+    // Isolation: run the helper in a new user namespace, as root inside it but
+    // mapped to an unprivileged id (its user's, or nobody's) outside, so its
+    // capabilities only apply within the namespace.
+    #[serde(default)]
+    userns: bool,
+    // Syscalls the helper may make, as names and "@profile" references (see
+    // seccomp_profiles); anything else fails with EPERM. A single profile can
+    // be given as a string. Resolved to plain syscall names at load.
+    #[serde(
+        deserialize_with = "deserialize_seccomp",
+        skip_serializing_if = "Option::is_none",
+        default
+    )]
+    seccomp: Option<Vec<String>>,
+}
+
+#[derive(Deserialize, Serialize)]
+#[serde(untagged)]
+enum RawUidRange {
+    Uid(libc::uid_t),
+    Range(String),
+}
+
+#[derive(Deserialize, Serialize, Clone, Copy)]
+#[serde(try_from = "RawUidRange", into = "RawUidRange")]
+struct UidRange {
+    first: libc::uid_t,
+    last: libc::uid_t,
+}
+
+impl TryFrom<RawUidRange> for UidRange {
+    type Error = String;
+
+    fn try_from(raw: RawUidRange) -> Result<Self, Self::Error> {
+        let range = match raw {
+            RawUidRange::Uid(uid) => {
+                return Ok(UidRange {
+                    first: uid,
+                    last: uid,
+                });
+            }
+            RawUidRange::Range(range) => range,
+        };
+        let bounds = range
+            .split_once('-')
+            .and_then(|(first, last)| Some((first.parse().ok()?, last.parse().ok()?)));
+        match bounds {
+            Some((first, last)) if first <= last => Ok(UidRange { first, last }),
+            _ => Err(format!(
+                "bad uid range {:?}, expected e.g. \"1000-1999\"",
+                range
+            )),
+        }
+    }
+}
+
+impl From<UidRange> for RawUidRange {
+    fn from(range: UidRange) -> Self {
+        if range.first == range.last {
+            RawUidRange::Uid(range.first)
+        } else {
+            RawUidRange::Range(format!("{}-{}", range.first, range.last))
+        }
+    }
+}
+
+// Whether the uid helper runs as (if it has a user) is one allowed_target_uids
+// allows. Resolves the user, so passwd_file has to be set.
+fn check_target_uid(allowed: Option<&[UidRange]>, helper: &Helper) -> Result<(), String> {
+    let (Some(allowed), Some(entry)) = (allowed, helper.identity()) else {
+        return Ok(());
+    };
+    if allowed
+        .iter()
+        .any(|range| (range.first..=range.last).contains(&entry.uid))
+    {
+        return Ok(());
+    }
+    Err(format!(
+        "helper {}: user {} has uid {}, which allowed_target_uids doesn't allow",
+        helper.name(),
+        entry.name,
+        entry.uid
+    ))
+}
+
+#[derive(Deserialize, Serialize)]
+#[serde(untagged)]
+enum RawEnvMatch {
+    Exact(String),
+    Regex { regex: String },
+}
+
+// Regexes keep their source so the config can be serialized back into the
+// same shape it was read from.
+#[derive(Deserialize, Serialize, Clone)]
+#[serde(try_from = "RawEnvMatch", into = "RawEnvMatch")]
+enum EnvMatch {
+    Exact(String),
+    Regex(String, Regex),
+}
+
+impl TryFrom<RawEnvMatch> for EnvMatch {
+    type Error = String;
+
+    fn try_from(raw: RawEnvMatch) -> Result<Self, Self::Error> {
+        Ok(match raw {
+            RawEnvMatch::Exact(value) => EnvMatch::Exact(value),
+            RawEnvMatch::Regex { regex } => {
+                let compiled = anchored_regex(&regex)?;
+                EnvMatch::Regex(regex, compiled)
+            }
+        })
+    }
+}
+
+impl From<EnvMatch> for RawEnvMatch {
+    fn from(m: EnvMatch) -> Self {
+        match m {
+            EnvMatch::Exact(value) => RawEnvMatch::Exact(value),
+            EnvMatch::Regex(regex, _) => RawEnvMatch::Regex { regex },
+        }
+    }
+}
+
+impl EnvMatch {
+    fn matches(&self, value: &OsStr) -> bool {
+        match self {
+            EnvMatch::Exact(expected) => value == expected.as_str(),
+            EnvMatch::Regex(_, regex) => regex.is_match(value.as_encoded_bytes()),
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize, Clone)]
+#[serde(try_from = "String", into = "String")]
+enum ArgPattern {
+    Literal(String),
+    Any,
+    Ref(usize),
+}
+
+impl TryFrom<String> for ArgPattern {
+    type Error = String;
+
+    fn try_from(raw: String) -> Result<Self, Self::Error> {
+        let Some(placeholder) = raw.strip_prefix('<').and_then(|p| p.strip_suffix('>')) else {
+            return Ok(ArgPattern::Literal(raw));
+        };
+        if placeholder == "any" {
+            return Ok(ArgPattern::Any);
+        }
+        placeholder
+            .strip_prefix("ref:")
+            .and_then(|n| n.parse().ok())
+            .map(ArgPattern::Ref)
+            .ok_or_else(|| format!("bad argument placeholder {}", raw))
+    }
+}
+
+impl From<ArgPattern> for String {
+    fn from(p: ArgPattern) -> Self {
+        match p {
+            ArgPattern::Literal(value) => value,
+            ArgPattern::Any => "<any>".to_string(),
+            ArgPattern::Ref(n) => format!("<ref:{}>", n),
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+enum AuditFullPolicy {
+    // Deny the invocation.
+    Fail,
+    // Skip the record, and carry on.
+    Drop,
+    // Make room by rotating the log (see audit::rotate), and carry on.
+    Rotate,
+}
+
+#[derive(Deserialize, Serialize, Clone, Copy, Default)]
+#[serde(rename_all = "snake_case")]
+enum DuplicatePolicy {
+    #[default]
+    Error,
+    FirstWins,
+    LastWins,
+}
+
+#[derive(Deserialize, Serialize, Clone, Copy, Default)]
+#[serde(rename_all = "snake_case")]
+enum ExecCoherence {
+    Ignore,
+    #[default]
+    Warn,
+    Deny,
+}
+
+// How much a failed exec of the helper reports.
+#[derive(Deserialize, Serialize, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "snake_case")]
+enum OnExecFailure {
+    #[default]
+    Report,
+    Diagnose,
+}
+
+// What happens when a matching helper's program doesn't exist.
+#[derive(Deserialize, Serialize, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "snake_case")]
+enum MissingBinaryPolicy {
+    // Run it anyway, and fail to exec.
+    #[default]
+    Fail,
+    // Treat the helper as not matching.
+    Skip,
+}
+
+// What require_secure_boot does when the secure boot state can't be read.
+#[derive(Deserialize, Serialize, Clone, Copy, Default)]
+#[serde(rename_all = "snake_case")]
+enum SecureBootUnknown {
+    Allow,
+    #[default]
+    Deny,
+}
+
+#[derive(Deserialize, Serialize, Clone, Copy, Default)]
+#[serde(rename_all = "snake_case")]
+enum AmbientDenylistPolicy {
+    // Keep the capability in the other sets, just not ambient.
+    #[default]
+    Strip,
+    Fail,
+}
+
+// What priv_restrict may raise ambient.
+#[derive(Clone, Default)]
+struct AmbientLimit {
+    denylist: HashSet<Capability>,
+    policy: AmbientDenylistPolicy,
+    // Nothing at all, for helpers with raise_ambient = false.
+    disabled: bool,
+}
+
+// What a match_env constraint does when its variable isn't set at all.
+#[derive(Deserialize, Serialize, Clone, Copy, Default)]
+#[serde(rename_all = "snake_case")]
+enum AbsentEnvPolicy {
+    #[default]
+    Deny,
+    Ignore,
+}
+
+impl Helper {
+    // The descriptors above stderr the helper starts with.
+    fn kept_fds(&self) -> BTreeSet<u32> {
+        let below = 3..self.close_fds_from.unwrap_or(3);
+        below.chain(self.keep_fds.iter().copied()).collect()
+    }
+
+    // Checks that need more than one field, done once at load.
+    fn validate(&self) -> Result<(), String> {
+        let set = [
+            self.path.is_some(),
+            self.path_prefix.is_some(),
+            self.path_suffix.is_some(),
+            self.allowed_dirs.is_some(),
+        ];
+        if set.iter().filter(|set| **set).count() != 1 {
+            return Err(
+                "exactly one of path, path_prefix, path_suffix and allowed_dirs must be set"
+                    .to_string(),
+            );
+        }
+        if self.allowed_dirs.as_ref().is_some_and(Vec::is_empty) {
+            return Err("allowed_dirs is empty".to_string());
+        }
+        if self.urandom_wait_secs.is_some() && !self.require_urandom_ready {
+            return Err("urandom_wait_secs requires require_urandom_ready".to_string());
+        }
+        if let Some(fd) = self
+            .keep_fds
+            .iter()
+            .chain(&self.close_fds_from)
+            .find(|fd| !(3..=MAX_KEPT_FD).contains(*fd))
+        {
+            return Err(format!(
+                "fd {} is out of range, keep_fds and close_fds_from go from 3 to {}",
+                fd, MAX_KEPT_FD
+            ));
+        }
+        for dir in self.allowed_dirs.iter().flatten() {
+            if !dir.starts_with('/') {
+                return Err(format!("allowed_dirs entry {} isn't an absolute path", dir));
+            }
+        }
+        if let Some(prefix) = &self.path_prefix
+            && !prefix.starts_with('/')
+        {
+            return Err(format!("path_prefix {} isn't an absolute path", prefix));
+        }
+        if self.path_suffix.as_deref() == Some("") {
+            return Err("path_suffix is empty".to_string());
+        }
+        if let Some(dir) = &self.workdir
+            && !dir.starts_with('/')
+        {
+            return Err(format!("workdir {} isn't an absolute path", dir));
+        }
+        if (!self.bind_mounts.is_empty() || self.verify_mounts) && !self.mount_namespace {
+            return Err("bind_mounts and verify_mounts require mount_namespace".to_string());
+        }
+        for bind in &self.bind_mounts {
+            if !bind.source.starts_with('/') || !bind.target.starts_with('/') {
+                return Err(format!(
+                    "bind mount {} -> {} isn't between absolute paths",
+                    bind.source, bind.target
+                ));
+            }
+        }
+        for hash in self.argv_hashes.iter().flatten() {
+            if !argv_hash::is_digest(hash) {
+                return Err(format!("argv_hashes entry {} isn't a SHA-256 digest", hash));
+            }
+        }
+        for prefix in self.allowed_path_prefixes.iter().flatten() {
+            if !prefix.starts_with('/') {
+                return Err(format!(
+                    "allowed_path_prefixes entry {} isn't an absolute path",
+                    prefix
+                ));
+            }
+        }
+        if !self.path_check_exempt_args.is_empty() && self.allowed_path_prefixes.is_none() {
+            return Err("path_check_exempt_args requires allowed_path_prefixes".to_string());
+        }
+        if self.path_check_exempt_args.contains(&0) {
+            return Err("path_check_exempt_args can't exempt argv[0]".to_string());
+        }
+        if self.capture_output && self.run_timeout.is_none() {
+            return Err("capture_output requires run_timeout".to_string());
+        }
+        if self.reap_descendants && self.run_timeout.is_none() {
+            return Err("reap_descendants requires run_timeout".to_string());
+        }
+        if self.capability_report && self.run_timeout.is_none() {
+            return Err("capability_report requires run_timeout".to_string());
+        }
+        if self.capability_report && self.capabilities.is_none() {
+            return Err("capability_report requires capabilities".to_string());
+        }
+        if let Some(exec_path) = &self.exec_path
+            && !exec_path.starts_with('/')
+        {
+            return Err(format!("exec_path {} isn't an absolute path", exec_path));
+        }
+        // argv[N] is at template position N - 1, so a reference has to point
+        // strictly before its own position (argv[0] is always fine).
+        if let Some(template) = &self.args_template {
+            if let Some(argc) = self.argc
+                && argc != template.len() + 1
+            {
+                return Err(format!(
+                    "args_template has {} arguments but argc is {}",
+                    template.len() + 1,
+                    argc
+                ));
+            }
+            for (i, pattern) in template.iter().enumerate() {
+                match pattern {
+                    ArgPattern::Ref(n) if *n > template.len() => {
+                        return Err(format!("<ref:{}> is out of range", n));
+                    }
+                    ArgPattern::Ref(n) if *n > i => {
+                        return Err(format!(
+                            "<ref:{}> refers forward from argument {}",
+                            n,
+                            i + 1
+                        ));
+                    }
+                    _ => {}
+                }
+            }
+        }
+        // Nothing would be found on an empty PATH, so don't rely on one.
+        if self.env_path.as_deref() == Some("") {
+            for program in [self.path.as_ref(), self.pre_exec_hook.as_ref()]
+                .into_iter()
+                .flatten()
+            {
+                if !program.starts_with('/') {
+                    return Err(format!(
+                        "{} must be an absolute path with an empty env_path",
+                        program
+                    ));
+                }
+            }
+        }
+        for interp in self.trusted_interpreters.iter().flatten() {
+            if !interp.starts_with('/') {
+                return Err(format!(
+                    "trusted interpreter {} isn't an absolute path",
+                    interp
+                ));
+            }
+        }
+        for (name, value) in &self.env {
+            if name.is_empty() || name.contains(['=', '\0']) || value.contains('\0') {
+                return Err(format!("bad environment variable {:?}", name));
+            }
+            if name.starts_with("LD_") {
+                return Err(format!("{} can't be set for helpers", name));
+            }
+        }
+        for (name, index) in &self.env_from_args {
+            if name.is_empty() || name.contains(['=', '\0']) {
+                return Err(format!("bad environment variable name {:?}", name));
+            }
+            // Nothing from an invocation gets to steer the dynamic linker.
+            if name.starts_with("LD_") {
+                return Err(format!("{} can't be set from arguments", name));
+            }
+            if let Some(argc) = self.argc
+                && *index >= argc
+            {
+                return Err(format!(
+                    "{} refers to argument {} but argc is {}",
+                    name, index, argc
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    // The program this helper runs as: its path, or for prefix/suffix helpers,
+    // the one that matched.
+    fn path(&self) -> &str {
+        self.matched_path
+            .get()
+            .or(self.path.as_ref())
+            .map_or("", |path| path)
+    }
+
+    // A program in one of the allowed_dirs may be a symlink, but only to a
+    // program directly inside one of them (the same one or another).
+    fn outside_allowed_dirs(&self, resolved: &Path) -> Option<String> {
+        let dirs = self.allowed_dirs.as_ref()?;
+        let inside = resolved.parent().is_some_and(|parent| {
+            dirs.iter()
+                .any(|dir| fs::canonicalize(dir).is_ok_and(|dir| dir == parent))
+        });
+        (!inside).then(|| {
+            format!(
+                "it resolves to {}, outside allowed_dirs",
+                resolved.display()
+            )
+        })
+    }
+
+    // For messages about the helper before it's matched.
+    fn name(&self) -> String {
+        match (&self.path_prefix, &self.path_suffix, &self.allowed_dirs) {
+            (Some(prefix), _, _) => format!("{}*", prefix),
+            (_, Some(suffix), _) => format!("*{}", suffix),
+            (_, _, Some(dirs)) => dirs
+                .iter()
+                .map(|dir| format!("{}/*", dir.trim_end_matches('/')))
+                .collect::<Vec<_>>()
+                .join(", "),
+            _ => self.path.clone().unwrap_or_default(),
+        }
+    }
+
+    // Pattern helpers only match absolute paths, and a prefix or an allowed
+    // directory only matches names directly inside its directory (nothing with
+    // a "/" after it), so none can be walked out of with "..".
+    fn matches_path(&self, arg0: &OsStr) -> bool {
+        if let Some(path) = &self.path {
+            return arg0 == path.as_str();
+        }
+        let Some(arg0) = arg0.to_str().filter(|a| a.starts_with('/')) else {
+            return false;
+        };
+        match (&self.path_prefix, &self.path_suffix) {
+            (Some(prefix), _) => arg0
+                .strip_prefix(prefix.as_str())
+                .is_some_and(|name| !name.is_empty() && !name.contains('/')),
+            (_, Some(suffix)) => {
+                arg0.strip_suffix(suffix.as_str())
+                    .is_some_and(|dir| !dir.is_empty())
+                    && !Path::new(arg0)
+                        .components()
+                        .any(|c| c == std::path::Component::ParentDir)
+            }
+            _ => self.allowed_dirs.iter().flatten().any(|dir| {
+                arg0.strip_prefix(dir.trim_end_matches('/'))
+                    .and_then(|rest| rest.strip_prefix('/'))
+                    .is_some_and(|name| !matches!(name, "" | "." | "..") && !name.contains('/'))
+            }),
+        }
+    }
+
+    fn allowed(&self, args: &[OsString], env: &HashMap<OsString, OsString>) -> bool {
+        if args.first().is_none_or(|a| !self.matches_path(a)) {
+            return false;
+        }
+        if let Some(argc) = self.argc
+            && args.len() != argc
+        {
+            return false;
+        }
+        if let Some(template) = &self.args_template {
+            if args.len() != template.len() + 1 {
+                return false;
+            }
+            let matched = template
+                .iter()
+                .zip(&args[1..])
+                .all(|(pattern, arg)| match pattern {
+                    ArgPattern::Literal(value) => arg == value.as_str(),
+                    ArgPattern::Any => true,
+                    ArgPattern::Ref(n) => *arg == args[*n],
+                });
+            if !matched {
+                return false;
+            }
+        }
+        if !self.path_args_allowed(args) {
+            return false;
+        }
+        if let Some(hashes) = &self.argv_hashes {
+            let digest = argv_hash::digest(args);
+            if !hashes.iter().any(|hash| hash.eq_ignore_ascii_case(&digest)) {
+                debug!("{} doesn't allow argv hash {}", self.name(), digest);
+                return false;
+            }
+        }
+        self.match_env.iter().all(|(name, constraint)| {
+            match (env.get(OsStr::new(name)), self.match_env_absent) {
+                (Some(value), _) => constraint.matches(value),
+                (None, AbsentEnvPolicy::Deny) => false,
+                (None, AbsentEnvPolicy::Ignore) => true,
+            }
+        })
+    }
+
+    // Works on the raw bytes, so arguments that aren't UTF-8 are checked too. A
+    // prefix only covers whole path components: "/var/lib/foo" allows
+    // "/var/lib/foo/bar", but not "/var/lib/foobar".
+    fn path_args_allowed(&self, args: &[OsString]) -> bool {
+        let Some(prefixes) = &self.allowed_path_prefixes else {
+            return true;
+        };
+        args.iter()
+            .enumerate()
+            .skip(1)
+            .filter(|(index, _)| !self.path_check_exempt_args.contains(index))
+            .map(|(_, arg)| arg.as_encoded_bytes())
+            .filter(|arg| arg.starts_with(b"/"))
+            .all(|arg| {
+                let under_prefix = prefixes.iter().any(|prefix| {
+                    let prefix = prefix.trim_end_matches('/').as_bytes();
+                    arg.strip_prefix(prefix)
+                        .is_some_and(|rest| rest.is_empty() || rest.starts_with(b"/"))
+                });
+                let escapes = arg.split(|b| *b == b'/').any(|part| part == b"..");
+                if !under_prefix || escapes {
+                    debug!(
+                        "{} isn't allowed path argument {}",
+                        self.name(),
+                        String::from_utf8_lossy(arg)
+                    );
+                }
+                under_prefix && !escapes
+            })
+    }
+
+    // The helper's user, looked up (once) at runtime rather than at load, so
+    // that a cached config never carries stale passwd data.
+    fn identity(&self) -> Option<&passwd::Entry> {
+        self.identity
+            .get_or_init(|| {
+                let user = self.user.as_ref()?;
+                let file = self.passwd_file.as_deref().unwrap_or_default();
+                let entry = passwd::lookup(file, user)
+                    .unwrap_or_else(|e| fail!(Exit::Config, "couldn't read {}: {}", file, e))
+                    .unwrap_or_else(|| {
+                        fail!(Exit::Config, "unknown user {} for {}", user, self.path())
+                    });
+                Some(entry)
+            })
+            .as_ref()
+    }
+
+    // Turns the current process into what the helper runs as: its limits,
+    // identity and capabilities.
+    fn restrict(&self, config: &Config) {
+        self.apply_core_policy();
+        self.apply_cpu_limit();
+        if self.userns {
+            self.enter_userns();
+        }
+        if self.mount_namespace {
+            self.enter_mount_namespace();
+        }
+        if !self.userns
+            && let Some(entry) = self.identity()
+        {
+            drop_identity(entry, self.capabilities.is_some());
+        }
+        // Restrict privileges based on configured capabilities.
+        if let Some(caps) = &self.capabilities {
+            priv_restrict(caps, &config.ambient_limit(self));
+        }
+        /* ALTERNATIVE APPROACH ("Zero-Trust"):
+         * If no capabilties are defined (empty set), strip all privileges.
+         *
+         *  let empty_caps = HashSet::new();
+         *  let caps = self.capabilities.as_ref().unwrap_or(&empty_caps);
+         *  priv_restrict(caps);
+         */
+        self.check_required_caps();
+    }
+
+    // Runs the helper itself with probe_args, restricted exactly like the real
+    // run, in a forked child, and requires it to exit successfully within
+    // probe_timeout seconds. This catches a broken helper before the kernel
+    // relies on it.
+    fn run_probe(&self, config: &Config) {
+        let Some(probe_args) = &self.probe_args else {
+            return;
+        };
+        let mut argv = vec![OsString::from(self.path())];
+        argv.extend(probe_args.iter().map(OsString::from));
+
+        let pid = match unsafe { libc::fork() } {
+            -1 => fail!(
+                Exit::Probe,
+                "couldn't fork probe of {}: {}",
+                self.path(),
+                std::io::Error::last_os_error()
+            ),
+            0 => {
+                self.restrict(config);
+                self.execute(&argv, false, None);
+                unreachable!("execute returned");
+            }
+            pid => pid,
+        };
+        let timeout = Duration::from_secs(self.probe_timeout.unwrap_or(DEFAULT_PROBE_TIMEOUT));
+        match wait_pid_timeout(pid, timeout) {
+            Ok(Some(status)) if status.success() => debug!("probe of {} passed", self.path()),
+            Ok(Some(status)) => fail!(Exit::Probe, "probe of {} failed: {}", self.path(), status),
+            Ok(None) => fail!(
+                Exit::Probe,
+                "probe of {} timed out after {}s",
+                self.path(),
+                timeout.as_secs()
+            ),
+            Err(e) => fail!(
+                Exit::Probe,
+                "couldn't wait for probe of {}: {}",
+                self.path(),
+                e
+            ),
+        }
+    }
+
+    // Needs CAP_SYS_ADMIN, so this comes before dropping to the helper's user
+    // (or, with userns, after entering the user namespace, which grants it).
+    fn enter_mount_namespace(&self) {
+        mounts::setup(&self.bind_mounts).unwrap_or_else(|e| {
+            fail!(
+                Exit::Privileges,
+                "couldn't set up mount namespace for {}: {}",
+                self.path(),
+                e
+            )
+        });
+        if self.verify_mounts {
+            mounts::verify(&self.bind_mounts).unwrap_or_else(|e| {
+                fail!(
+                    Exit::Privileges,
+                    "mount namespace for {} isn't as configured: {}",
+                    self.path(),
+                    e
+                )
+            });
+            debug!("verified mount namespace for {}", self.path());
+        }
+    }
+
+    // Switches to the helper's unprivileged outside id (its user's, or nobody's)
+    // and creates the user namespace, with root inside it mapped to that id.
+    // This has to come before creating any other namespace, since the
+    // capabilities to do so come from the user namespace. Fails closed if user
+    // namespaces aren't permitted.
+    fn enter_userns(&self) {
+        let nobody = nobody();
+        let entry = self.identity().unwrap_or(&nobody);
+
+        // We're non-dumpable, so once we've switched ids our own /proc files
+        // aren't writable by us anymore; a child that stays root writes the
+        // maps instead.
+        let mut pipe = [0; 2];
+        if unsafe { libc::pipe(pipe.as_mut_ptr()) } < 0 {
+            fail!(
+                Exit::Privileges,
+                "couldn't create a pipe: {}",
+                std::io::Error::last_os_error()
+            );
+        }
+        let [ready, notify] = pipe;
+        let pid = unsafe { libc::getpid() };
+        match unsafe { libc::fork() } {
+            -1 => fail!(
+                Exit::Privileges,
+                "fork failed: {}",
+                std::io::Error::last_os_error()
+            ),
+            0 => unsafe {
+                libc::close(notify);
+                let mut byte = 0u8;
+                // EOF means the parent failed before unsharing.
+                if libc::read(ready, (&raw mut byte).cast(), 1) == 1 {
+                    write_id_maps(pid, entry.uid, entry.gid);
+                }
+                libc::_exit(0)
+            },
+            mapper => {
+                unsafe { libc::close(ready) };
+                drop_identity(entry, false);
+                if unsafe { libc::unshare(libc::CLONE_NEWUSER) } < 0 {
+                    fail!(
+                        Exit::Privileges,
+                        "couldn't create a user namespace for {}: {}",
+                        self.path(),
+                        std::io::Error::last_os_error()
+                    );
+                }
+                let mut status = 0;
+                if unsafe { libc::write(notify, [0u8].as_ptr().cast(), 1) } != 1
+                    || unsafe { libc::waitpid(mapper, &mut status, 0) } < 0
+                    || !libc::WIFEXITED(status)
+                    || libc::WEXITSTATUS(status) != 0
+                {
+                    fail!(Exit::Privileges, "couldn't map ids for {}", self.path());
+                }
+                unsafe { libc::close(notify) };
+            }
+        }
+    }
+
+    // We set up a minimal environment for any process we start on behalf of
+    // this helper. HOME follows the helper's user, if it has one.
+    fn command(&self, path: &str) -> Command {
+        let home = self.identity().map_or("/", |entry| entry.home.as_str());
+        let mut cmd = Command::new(path);
+        cmd.env_clear()
+            .env("HOME", home)
+            .env("TERM", "linux")
+            .env("PATH", self.env_path.as_deref().unwrap_or_default())
+            .envs(&self.env);
+        if let Some(dir) = &self.workdir {
+            cmd.current_dir(dir);
+        }
+        cmd
+    }
+
+    fn run_pre_exec_hook(&self, args: &[OsString]) {
+        let Some(hook) = &self.pre_exec_hook else {
+            return;
+        };
+
+        // The hook sees the exact argv the kernel gave us, but runs as the
+        // helper's user (or nobody) with no capabilities at all, bounding set
+        // included, regardless of what the helper is granted.
+        let entry = self.identity().cloned().unwrap_or_else(nobody);
+        let mut cmd = self.command(hook);
+        cmd.args(args.iter().skip(1)).arg0(&args[0]);
+        unsafe {
+            cmd.pre_exec(move || {
+                drop_bounding_set();
+                drop_identity(&entry, true);
+                priv_restrict(&HashSet::new(), &AmbientLimit::default());
+                Ok(())
+            });
+        }
+
+        match cmd.status() {
+            Ok(status) if status.success() => {
+                debug!("pre-exec hook {} accepted {}", hook, self.path())
+            }
+            Ok(status) => fail!(
+                Exit::Rejected,
+                "pre-exec hook {} rejected {}: {}",
+                hook,
+                self.path(),
+                status
+            ),
+            Err(e) => fail!(Exit::Rejected, "couldn't run pre-exec hook {}: {}", hook, e),
+        }
+    }
+
+    fn run_capability_precheck(&self, args: &[OsString], ambient: &AmbientLimit) {
+        let Some(check) = &self.capability_precheck else {
+            return;
+        };
+
+        // Exactly the helper's capabilities: enough to exercise them, and no
+        // more.
+        let caps = self.capabilities.clone().unwrap_or_default();
+        let ambient = ambient.clone();
+        let mut cmd = self.command(check);
+        cmd.args(args.iter().skip(1)).arg0(&args[0]);
+        unsafe {
+            cmd.pre_exec(move || {
+                priv_restrict(&caps, &ambient);
+                Ok(())
+            });
+        }
+
+        let timeout = Duration::from_secs(
+            self.capability_precheck_timeout
+                .unwrap_or(DEFAULT_PRECHECK_TIMEOUT),
+        );
+        let child = cmd.spawn().unwrap_or_else(|e| {
+            fail!(
+                Exit::Rejected,
+                "couldn't run capability precheck {}: {}",
+                check,
+                e
+            )
+        });
+        match wait_timeout(child, timeout) {
+            Ok(Some(status)) if status.success() => {
+                debug!("capability precheck {} passed for {}", check, self.path())
+            }
+            Ok(Some(status)) => fail!(
+                Exit::Rejected,
+                "capability precheck {} failed for {}: {}",
+                check,
+                self.path(),
+                status
+            ),
+            Ok(None) => fail!(
+                Exit::Rejected,
+                "capability precheck {} for {} timed out after {}s",
+                check,
+                self.path(),
+                timeout.as_secs()
+            ),
+            Err(e) => fail!(
+                Exit::Rejected,
+                "couldn't wait for capability precheck {}: {}",
+                check,
+                e
+            ),
+        }
+    }
+
+    // With require_urandom_ready, denies the helper while the kernel RNG isn't
+    // initialized, polling for up to urandom_wait_secs for it to become so.
+    fn check_urandom_ready(&self) {
+        if !self.require_urandom_ready {
+            return;
+        }
+        let wait = Duration::from_secs(self.urandom_wait_secs.unwrap_or(0));
+        let deadline = Instant::now() + wait;
+        loop {
+            match rng::ready() {
+                Ok(true) => return,
+                Ok(false) if Instant::now() < deadline => sleep(rng::POLL_INTERVAL),
+                Ok(false) => fail!(
+                    Exit::Policy,
+                    "the kernel RNG isn't initialized{}, denying {} (require_urandom_ready)",
+                    if wait.is_zero() {
+                        String::new()
+                    } else {
+                        format!(" after {}s", wait.as_secs())
+                    },
+                    self.path()
+                ),
+                Err(e) => fail!(
+                    Exit::Policy,
+                    "couldn't tell whether the kernel RNG is initialized, denying {}: {}",
+                    self.path(),
+                    e
+                ),
+            }
+        }
+    }
+
+    // Replaces the MATCH_TIMEOUT alarm from startup. The config was loaded
+    // before we knew which helper (and thus which timeout) applies, so the
+    // budget is counted from process start.
+    fn arm_setup_timeout(&self, started: Instant) {
+        let Some(secs) = self.setup_timeout else {
+            set_setup_timer(Duration::ZERO);
+            return;
+        };
+        arm_setup_timer(secs, started);
+    }
+
+    // Sets RLIMIT_CPU, inherited across exec (and fork, for supervised helpers),
+    // if the helper has cpu_time_secs. Like RLIMIT_CORE, this has to happen
+    // while we can still raise the hard limit.
+    fn apply_cpu_limit(&self) {
+        let Some(secs) = self.cpu_time_secs else {
+            return;
+        };
+        let rlim = libc::rlimit {
+            rlim_cur: secs,
+            rlim_max: secs.saturating_add(CPU_TIME_GRACE),
+        };
+        if unsafe { libc::setrlimit(libc::RLIMIT_CPU, &rlim) } < 0 {
+            fail!(
+                Exit::Privileges,
+                "couldn't set RLIMIT_CPU for {}: {}",
+                self.path(),
+                std::io::Error::last_os_error()
+            );
+        }
+    }
+
+    // Sets RLIMIT_CORE (inherited across exec) for the helper. Must run before
+    // priv_restrict, since raising the hard limit needs CAP_SYS_RESOURCE. A
+    // helper that may dump core is only made dumpable by execute, right before
+    // it runs.
+    fn apply_core_policy(&self) {
+        let limit = if self.allow_core {
+            warn!(
+                "core dumps are enabled for {}, they may contain secrets",
+                self.path()
+            );
+            self.core_limit.unwrap_or(libc::RLIM_INFINITY)
+        } else {
+            0
+        };
+        let mut rlim = libc::rlimit {
+            rlim_cur: 0,
+            rlim_max: 0,
+        };
+        unsafe {
+            if libc::getrlimit(libc::RLIMIT_CORE, &mut rlim) < 0 {
+                fail!(Exit::Privileges, "couldn't get RLIMIT_CORE");
+            }
+        }
+        // Only ever raise the hard limit for helpers that asked for dumps.
+        rlim.rlim_cur = limit;
+        if self.allow_core {
+            rlim.rlim_max = rlim.rlim_max.max(limit);
+        } else {
+            rlim.rlim_max = 0;
+        }
+        unsafe {
+            if libc::setrlimit(libc::RLIMIT_CORE, &rlim) < 0 {
+                fail!(
+                    Exit::Privileges,
+                    "couldn't set RLIMIT_CORE for {}: {}",
+                    self.path(),
+                    std::io::Error::last_os_error()
+                );
+            }
+        }
+    }
+
+    // Post-condition of priv_restrict: everything required made it into the
+    // sets the helper will run with.
+    fn check_required_caps(&self) {
+        let Some(required) = &self.require_capabilities else {
+            return;
+        };
+        for set in [CapSet::Permitted, CapSet::Ambient] {
+            let have = caps::read(None, set).unwrap_or_else(|e| {
+                fail!(Exit::Privileges, "couldn't read own {:?} caps: {}", set, e)
+            });
+            let mut missing: Vec<String> = required
+                .difference(&have)
+                .map(|cap| cap.to_string())
+                .collect();
+            if !missing.is_empty() {
+                missing.sort();
+                fail!(
+                    Exit::Privileges,
+                    "{} requires {} but it's missing from the {:?} set",
+                    self.path(),
+                    missing.join(", "),
+                    set
+                );
+            }
+        }
+    }
+
+    // We can only hand out capabilities we hold ourselves, e.g. not when run in
+    // a reduced container. Catch that up front rather than letting
+    // priv_restrict fail confusingly halfway through.
+    fn check_own_caps(&self) {
+        let Some(wanted) = &self.capabilities else {
+            return;
+        };
+        let read = |set| {
+            caps::read(None, set).unwrap_or_else(|e| {
+                fail!(Exit::Privileges, "couldn't read own {:?} caps: {}", set, e)
+            })
+        };
+        let (permitted, bounding) = (read(CapSet::Permitted), read(CapSet::Bounding));
+        let mut missing: Vec<String> = wanted
+            .iter()
+            .filter(|cap| !permitted.contains(cap) || !bounding.contains(cap))
+            .map(|cap| cap.to_string())
+            .collect();
+        if missing.is_empty() {
+            return;
+        }
+        missing.sort();
+        fail!(
+            Exit::Underprivileged,
+            "huldufolk lacks {} needed by {}",
+            missing.join(", "),
+            self.path()
+        );
+    }
+
+    // Whether missing_binary_policy = "skip" takes the helper out of matching
+    // for the program name, since what it would run doesn't exist.
+    fn skipped_as_missing(&self, name: &OsStr) -> bool {
+        if self.missing_binary_policy != MissingBinaryPolicy::Skip {
+            return false;
+        }
+        let target = self.exec_path.as_deref().map_or(name, OsStr::new);
+        match fs::metadata(target) {
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                debug!(
+                    "skipping helper {}, {:?} doesn't exist (missing_binary_policy)",
+                    self.name(),
+                    target
+                );
+                true
+            }
+            _ => false,
+        }
+    }
+
+    // The program actually executed.
+    fn exec_target(&self) -> &str {
+        self.exec_path.as_deref().unwrap_or(self.path())
+    }
+
+    // Guardrail over interposition: the argv[0] we matched must be the
+    // configured path, the exec target must resolve, and an exec_path must
+    // resolve to something other than the helper it interposes.
+    fn check_exec_coherence(&self, args: &[OsString], policy: ExecCoherence) {
+        let problem = if args.first().is_none_or(|a| a != self.path()) {
+            Some(format!(
+                "matched {:?} for helper {}",
+                args.first(),
+                self.path()
+            ))
+        } else {
+            let target = self.exec_target();
+            match (fs::canonicalize(target), &self.exec_path) {
+                (Err(e), _) => Some(format!("exec target {} doesn't resolve: {}", target, e)),
+                (Ok(resolved), Some(exec_path))
+                    if fs::canonicalize(self.path()).is_ok_and(|p| p == resolved) =>
+                {
+                    Some(format!(
+                        "exec_path {} resolves to {} itself",
+                        exec_path,
+                        resolved.display()
+                    ))
+                }
+                (Ok(resolved), _) => {
+                    debug!("{} will exec {}", self.path(), resolved.display());
+                    None
+                }
+            }
+        };
+        match (problem, policy) {
+            (None, _) | (_, ExecCoherence::Ignore) => {}
+            (Some(problem), ExecCoherence::Warn) => {
+                warn!("incoherent exec for {}: {}", self.path(), problem)
+            }
+            (Some(problem), ExecCoherence::Deny) => {
+                fail!(
+                    Exit::Rejected,
+                    "incoherent exec for {}: {}",
+                    self.path(),
+                    problem
+                )
+            }
+        }
+    }
+
+    fn check_elf(&self) {
+        let target = self.exec_target();
+        let mut magic = [0u8; 4];
+        let problem = match fs::File::open(target).and_then(|mut f| f.read_exact(&mut magic)) {
+            Ok(()) if &magic == b"\x7fELF" => return,
+            Ok(()) if magic.starts_with(b"#!") => "it's a script".to_string(),
+            Ok(()) => "it's not ELF".to_string(),
+            Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => "it's too short".to_string(),
+            Err(e) => e.to_string(),
+        };
+        fail!(
+            Exit::Rejected,
+            "{} is not an executable binary (require_elf): {}",
+            target,
+            problem
+        );
+    }
+
+    fn verify_interp(&self) {
+        let target = self.exec_target();
+        let interp = elf::interpreter(target).unwrap_or_else(|e| {
+            fail!(
+                Exit::Rejected,
+                "couldn't read interpreter of {}: {}",
+                target,
+                e
+            )
+        });
+        let Some(interp) = interp else {
+            return;
+        };
+        let trusted = self.trusted_interpreters.as_deref().unwrap_or_default();
+        if !trusted.contains(&interp) {
+            fail!(
+                Exit::Rejected,
+                "{}'s interpreter {} isn't trusted",
+                target,
+                interp
+            );
+        }
+    }
+
+    // With log_success, a one-line summary of the invocation is logged right
+    // before exec, once nothing can reject it anymore. audit_log is where
+    // captured output goes without an output_log, and capability reports go.
+    fn execute(
+        &self,
+        args: &[OsString],
+        log_success: bool,
+        audit_log: Option<(&str, AuditFullPolicy)>,
+    ) {
+        if self.require_elf {
+            self.check_elf();
+        }
+        if self.verify_interp {
+            self.verify_interp();
+        }
+
+        // Modernization: Use std::process::Command instead of unsafe libc::execvp.
+        let mut cmd = self.command(self.exec_target());
+        cmd.args(args.iter().skip(1)).arg0(self.path());
+
+        for (name, index) in &self.env_from_args {
+            let value = args.get(*index).unwrap_or_else(|| {
+                fail!(
+                    Exit::Rejected,
+                    "{} refers to missing argument {}",
+                    name,
+                    index
+                )
+            });
+            if value.as_encoded_bytes().contains(&0) {
+                fail!(
+                    Exit::Rejected,
+                    "argument {} for {} contains a NUL byte",
+                    index,
+                    name
+                );
+            }
+            cmd.env(name, value);
+        }
+
+        // env_clear makes get_envs list the whole environment.
+        let env_vars = cmd.get_envs().filter(|(_, value)| value.is_some()).count();
+        let max_env_vars = self.max_env_vars.unwrap_or(DEFAULT_MAX_ENV_VARS);
+        if env_vars > max_env_vars {
+            fail!(
+                Exit::Rejected,
+                "{} would get {} environment variables, more than max_env_vars ({})",
+                self.path(),
+                env_vars,
+                max_env_vars
+            );
+        }
+
+        if self.reset_signals {
+            unsafe {
+                cmd.pre_exec(reset_signals);
+            }
+        }
+
+        // std resets the child's signal mask before running pre_exec closures, so
+        // this has to happen here rather than in our own process.
+        if !self.block_signals.is_empty() {
+            let signals = self.block_signals.clone();
+            unsafe {
+                cmd.pre_exec(move || block_signals(&signals));
+            }
+        }
+
+        // Only in the child, right before exec, so that neither our setup nor
+        // a supervising huldufolk is ever dumpable; but before seccomp, which
+        // may not allow prctl.
+        if self.allow_core {
+            unsafe {
+                cmd.pre_exec(make_dumpable);
+            }
+        }
+
+        // Last, so that nothing else we do in the child has to be allowed.
+        if let Some(syscalls) = &self.seccomp {
+            debug!(
+                "seccomp filter for {} allows: {}",
+                self.path(),
+                syscalls.join(" ")
+            );
+            let filter = seccomp::filter(syscalls);
+            unsafe {
+                cmd.pre_exec(move || seccomp::install(&filter));
+            }
+        }
+
+        // Phase boundary: everything after this point belongs to the helper, and
+        // a pending timer would otherwise survive the exec.
+        set_setup_timer(Duration::ZERO);
+
+        if log_success {
+            let caps = match &self.capabilities {
+                Some(caps) if caps.is_empty() => "none".to_string(),
+                Some(caps) => cap_names(caps),
+                None => "unrestricted".to_string(),
+            };
+            let msg = format!(
+                "{}huldufolk: allowed {} argc={} caps={}\n",
+                log_prefix(LogCategory::Audit),
+                self.path(),
+                args.len(),
+                caps
+            );
+            let _ = std::io::stderr().write_all(msg.as_bytes());
+        }
+
+        if let Some(secs) = self.run_timeout {
+            let sink = match (&self.output_log, audit_log) {
+                _ if !self.capture_output => None,
+                (Some(log), _) => Some(output::Sink::Log(log)),
+                (None, Some((log, _))) => Some(output::Sink::Audit(log)),
+                (None, None) => None,
+            };
+            if sink.is_some() {
+                cmd.stdout(Stdio::piped()).stderr(Stdio::piped());
+            }
+            if self.reap_descendants
+                && unsafe { libc::prctl(libc::PR_SET_CHILD_SUBREAPER, 1, 0, 0, 0) } < 0
+            {
+                fail!(
+                    Exit::Exec,
+                    "couldn't become subreaper for {}: {}",
+                    self.path(),
+                    std::io::Error::last_os_error()
+                );
+            }
+            let child = cmd.spawn().unwrap_or_else(|e| self.exec_failed(e));
+            self.supervise(child, Duration::from_secs(secs), sink, audit_log);
+        }
+
+        let err = cmd.exec();
+        self.exec_failed(err);
+    }
+
+    // exec only returns on failure, so there's nothing left to do but report it.
+    fn exec_failed(&self, err: std::io::Error) -> ! {
+        if self.on_exec_failure == OnExecFailure::Diagnose {
+            let findings = diagnose::exec_failure(self.exec_target());
+            fail!(Exit::Exec, "exec failed: {}; {}", err, findings.join("; "));
+        }
+        fail!(Exit::Exec, "exec failed: {}", err);
+    }
+
+    // Waits for the helper, killing it if it outlives run_timeout, and exits with
+    // its status so the kernel sees the same result it would have without us.
+    fn supervise(
+        &self,
+        mut child: Child,
+        timeout: Duration,
+        sink: Option<output::Sink>,
+        audit_log: Option<(&str, AuditFullPolicy)>,
+    ) -> ! {
+        let deadline = Instant::now() + timeout;
+        let report = audit_log
+            .filter(|_| self.capability_report)
+            .map(|log| (log, cap_usage::Sampler::start()));
+        if let (Some(sink), Some(stdout), Some(stderr)) =
+            (sink, child.stdout.take(), child.stderr.take())
+        {
+            let max_bytes = self
+                .max_output_bytes
+                .unwrap_or(output::DEFAULT_MAX_OUTPUT_BYTES);
+            output::capture(self.path(), sink, max_bytes, stdout, stderr, deadline);
+        }
+        let waited = wait_timeout(child, deadline.saturating_duration_since(Instant::now()));
+        if self.reap_descendants {
+            reap_descendants(deadline);
+        }
+        if let Some(((log, full), sampler)) = report {
+            let (observed, samples) = sampler.finish();
+            let granted = self.capabilities.clone().unwrap_or_default();
+            let report = cap_usage::Report::new(&granted, observed, samples);
+            // The helper has already run, so there's nothing left to deny.
+            if let Err(e) = audit::capability_report(log, full, self.path(), &report) {
+                warn!("couldn't write audit log {}: {}", log, e);
+            }
+        }
+        match waited {
+            // Not one of ours: the helper's status is passed through as is.
+            #[allow(clippy::disallowed_methods)]
+            Ok(Some(status)) => std::process::exit(exit_code(status)),
+            Ok(None) => fail!(
+                Exit::RunTimeout,
+                "{} exceeded run timeout of {}s",
+                self.path(),
+                timeout.as_secs()
+            ),
+            Err(e) => fail!(Exit::Exec, "couldn't wait for {}: {}", self.path(), e),
+        }
+    }
+}
+
+// Waits for child, killing it if it outlives timeout, in which case there's no
+// status to return.
+fn wait_timeout(child: Child, timeout: Duration) -> std::io::Result<Option<ExitStatus>> {
+    wait_pid_timeout(child.id() as libc::pid_t, timeout)
 }
 
-impl Config {
-    // Modernization & Refactoring: Encapsulated configuration loading and parsing.
-    fn load(path: &str) -> Self {
-        let raw = fs::read_to_string(path)
-            .unwrap_or_else(|e| fail!("couldn't read config file {}: {}", path, e));
+// With the helper gone, its orphaned descendants are our children. Waits for
+// them until deadline, then kills them; killing one may orphan more, so this
+// goes on until there are no children left.
+fn reap_descendants(deadline: Instant) {
+    let mut status = 0;
+    loop {
+        match unsafe { libc::waitpid(-1, &mut status, libc::WNOHANG) } {
+            -1 if std::io::Error::last_os_error().kind() == std::io::ErrorKind::Interrupted => {}
+            -1 => return,
+            0 if Instant::now() >= deadline => {
+                for pid in children() {
+                    unsafe {
+                        libc::kill(pid, libc::SIGKILL);
+                    }
+                }
+                sleep(Duration::from_millis(10));
+            }
+            0 => sleep(Duration::from_millis(10)),
+            pid => debug!("reaped descendant {}", pid),
+        }
+    }
+}
+
+// Our children, from /proc/self/task/*/children.
+fn children() -> Vec<libc::pid_t> {
+    let Ok(tasks) = fs::read_dir("/proc/self/task") else {
+        return Vec::new();
+    };
+    tasks
+        .flatten()
+        .filter_map(|task| fs::read_to_string(task.path().join("children")).ok())
+        .flat_map(|pids| {
+            pids.split_whitespace()
+                .filter_map(|pid| pid.parse().ok())
+                .collect::<Vec<_>>()
+        })
+        .collect()
+}
 
-        toml::from_str(&raw)
-            .unwrap_or_else(|e| fail!("couldn't parse config file {}: {}", path, e))
+fn wait_pid_timeout(pid: libc::pid_t, timeout: Duration) -> std::io::Result<Option<ExitStatus>> {
+    let deadline = Instant::now() + timeout;
+    let mut status = 0;
+    loop {
+        match unsafe { libc::waitpid(pid, &mut status, libc::WNOHANG) } {
+            -1 => return Err(std::io::Error::last_os_error()),
+            0 if Instant::now() >= deadline => unsafe {
+                libc::kill(pid, libc::SIGKILL);
+                libc::waitpid(pid, &mut status, 0);
+                return Ok(None);
+            },
+            0 => sleep(Duration::from_millis(10)),
+            _ => return Ok(Some(ExitStatus::from_raw(status))),
+        }
     }
+}
 
-    fn find_helper(&self, args: &[OsString]) -> &Helper {
-        // Note: The kernel guarantees argv[0] exists for usermode helpers.
-        // We panic/fail if it's missing.
-        let name = args.get(0).expect("program doesn't have a 0 arg?");
-        self.helpers
-            .iter()
-            .find(|s| s.allowed(args))
-            .unwrap_or_else(|| fail!("invalid usermode helper {:?}", name))
+fn exit_code(status: ExitStatus) -> i32 {
+    status
+        .code()
+        .unwrap_or_else(|| 128 + status.signal().unwrap_or(0))
+}
+
+// Anything other than a clean "not found" (including a dangling symlink or a
+// permission error) counts as the killswitch being active.
+fn check_killswitch(path: &str) {
+    match fs::symlink_metadata(path) {
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+        Err(e) => fail!(
+            Exit::Denied,
+            "couldn't check killswitch {}, denying all helpers: {}",
+            path,
+            e
+        ),
+        Ok(_) => fail!(
+            Exit::Denied,
+            "killswitch {} is active, denying all helpers",
+            path
+        ),
     }
 }
 
-#[derive(Deserialize)]
-struct Helper {
-    path: String,
-    argc: Option<usize>,
-    #[serde(deserialize_with = "deserialize_caps", default)]
-    // Modernization: Use 'caps' crate (Hashet) instead of the old 'capabilities'.
-    capabilities: Option<HashSet<Capability>>,
+// Arms the setup alarm to go off secs after started.
+fn arm_setup_timer(secs: u64, started: Instant) {
+    let remaining = Duration::from_secs(secs).saturating_sub(started.elapsed());
+    if remaining.is_zero() {
+        fail!(Exit::SetupTimeout, "setup timeout of {}s exceeded", secs);
+    }
+    unsafe {
+        libc::signal(
+            libc::SIGALRM,
+            setup_timed_out as *const () as libc::sighandler_t,
+        );
+    }
+    set_setup_timer(remaining);
 }
 
-impl Helper {
-    fn allowed(&self, args: &[OsString]) -> bool {
-        if !args.get(0).map_or(false, |a| a == self.path.as_str()) {
-            return false;
+fn set_setup_timer(remaining: Duration) {
+    let timer = libc::itimerval {
+        it_interval: libc::timeval {
+            tv_sec: 0,
+            tv_usec: 0,
+        },
+        it_value: libc::timeval {
+            tv_sec: remaining.as_secs() as libc::time_t,
+            tv_usec: remaining.subsec_micros() as libc::suseconds_t,
+        },
+    };
+    unsafe {
+        if libc::syscall(
+            libc::SYS_setitimer,
+            libc::ITIMER_REAL,
+            &timer,
+            std::ptr::null_mut::<libc::itimerval>(),
+        ) < 0
+        {
+            fail!(Exit::SetupTimeout, "couldn't set setup timer");
         }
-        if let Some(argc) = self.argc {
-            if args.len() != argc {
-                return false;
-            }
+    }
+}
+
+// Runs in the forked child, right before exec. exec only resets caught signals,
+// so without this the helper would inherit ignored signals (e.g. SIGPIPE) and
+// the signal mask from whoever started us.
+fn reset_signals() -> std::io::Result<()> {
+    unsafe {
+        let mut action: libc::sigaction = std::mem::zeroed();
+        action.sa_sigaction = libc::SIG_DFL;
+        libc::sigemptyset(&mut action.sa_mask);
+        // Some signals (SIGKILL, SIGSTOP, real-time ones reserved by libc) can't
+        // be changed; they're at their defaults anyway.
+        for sig in 1..=libc::SIGRTMAX() {
+            libc::sigaction(sig, &action, std::ptr::null_mut());
+        }
+        let mut set: libc::sigset_t = std::mem::zeroed();
+        libc::sigemptyset(&mut set);
+        if libc::sigprocmask(libc::SIG_SETMASK, &set, std::ptr::null_mut()) < 0 {
+            return Err(std::io::Error::last_os_error());
         }
-        true
     }
+    Ok(())
+}
 
-    fn execute(&self, args: &[OsString]) {
-        // Modernization: Use std::process::Command instead of unsafe libc::execvp.
-        // We set up a minimal environment for the new process.
-        let mut cmd = Command::new(&self.path);
+// Runs in the child, right before exec, for helpers with allow_core.
+fn make_dumpable() -> std::io::Result<()> {
+    if unsafe { libc::prctl(libc::PR_SET_DUMPABLE, 1, 0, 0, 0) } < 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    Ok(())
+}
 
-        cmd.env_clear()
-            .env("HOME", "/")
-            .env("TERM", "linux")
-            .env("PATH", "/sbin:/bin:/usr/sbin:/usr/bin")
-            .args(args.iter().skip(1))
-            .arg0(&self.path);
+// Runs in the forked child, right before exec. Blocked signals stay blocked
+// across exec (only caught signals are reset), so the helper inherits the mask.
+fn block_signals(signals: &[libc::c_int]) -> std::io::Result<()> {
+    unsafe {
+        let mut set: libc::sigset_t = std::mem::zeroed();
+        libc::sigemptyset(&mut set);
+        for sig in signals {
+            libc::sigaddset(&mut set, *sig);
+        }
+        if libc::sigprocmask(libc::SIG_BLOCK, &set, std::ptr::null_mut()) < 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+    }
+    Ok(())
+}
 
-        let err = cmd.exec();
-        fail!("exec failed: {}", err);
+// SIGALRM handler; only async-signal-safe calls allowed here, so the message
+// fail! would log is spelled out by hand.
+extern "C" fn setup_timed_out(_: libc::c_int) {
+    const MSG: &[u8] =
+        b"ERROR: setup timeout exceeded (exit 8: huldufolk took too long to set up the helper)\n";
+    unsafe {
+        libc::write(libc::STDERR_FILENO, MSG.as_ptr().cast(), MSG.len());
+        libc::_exit(Exit::SetupTimeout.code());
     }
 }
 
@@ -96,35 +2658,230 @@ where
     D: serde::Deserializer<'de>,
 {
     let s: String = Deserialize::deserialize(deserializer)?;
+    parse_caps(&s, UNKNOWN_CAP_POLICY.get()).map(Some)
+}
+
+fn deserialize_required_caps<'de, D>(
+    deserializer: D,
+) -> Result<Option<HashSet<Capability>>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    let s: String = Deserialize::deserialize(deserializer)?;
+    let policy = match UNKNOWN_CAP_POLICY.get() {
+        UnknownCapPolicy::WarnSkip => UnknownCapPolicy::Error,
+        policy => policy,
+    };
+    parse_caps(&s, policy).map(Some)
+}
+
+fn parse_caps<E: serde::de::Error>(
+    s: &str,
+    policy: UnknownCapPolicy,
+) -> Result<HashSet<Capability>, E> {
     let clean_s = s.trim().trim_start_matches("=").trim();
 
     if clean_s.is_empty() {
-        return Ok(Some(HashSet::new()));
+        return Ok(HashSet::new());
     }
 
-    let caps = clean_s
+    let mut caps = HashSet::new();
+    let parsed = clean_s
         .split(|c: char| c.is_whitespace() || c == ',')
         .filter(|part| !part.is_empty())
-        .map(|part| {
-            let name = part.split(|c| c == '+' || c == '-').next().unwrap_or(part);
-            Capability::from_str(&name.to_uppercase())
-                .map_err(|_| serde::de::Error::custom(format!("bad caps {}", name)))
+        .filter_map(|part| {
+            let name = part.split(['+', '-']).next().unwrap_or(part);
+            let bad_caps = || E::custom(format!("bad caps {}", name));
+            match (Capability::from_str(&name.to_uppercase()), policy) {
+                (Ok(cap), _) => Some(Ok(cap)),
+                (Err(_), UnknownCapPolicy::Error) => Some(Err(bad_caps())),
+                (Err(_), UnknownCapPolicy::WarnSkip) => {
+                    warn!("ignoring unknown capability {}", name);
+                    None
+                }
+                (Err(_), UnknownCapPolicy::Numeric) => {
+                    Some(cap_from_number(name).ok_or_else(bad_caps))
+                }
+            }
+        });
+    // Names and numbers both count, so "cap_sys_admin,cap_21" is a duplicate.
+    for cap in parsed {
+        let cap = cap?;
+        if !caps.insert(cap) {
+            warn!(
+                "capability {} is listed more than once",
+                cap.to_string().to_lowercase()
+            );
+        }
+    }
+    Ok(caps)
+}
+
+// Config regexes always have to match the whole value.
+fn anchored_regex(s: &str) -> Result<Regex, String> {
+    Regex::new(&format!("^(?:{})$", s)).map_err(|e| format!("bad regex {}: {}", s, e))
+}
+
+fn deserialize_signals<'de, D>(deserializer: D) -> Result<Vec<libc::c_int>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    let names: Vec<String> = Deserialize::deserialize(deserializer)?;
+    names
+        .iter()
+        .map(|name| {
+            let upper = name.to_uppercase();
+            let short = upper.strip_prefix("SIG").unwrap_or(&upper);
+            match signal_from_name(short) {
+                Some(libc::SIGKILL | libc::SIGSTOP) => Err(serde::de::Error::custom(format!(
+                    "signal {} can't be blocked",
+                    name
+                ))),
+                Some(sig) => Ok(sig),
+                None => Err(serde::de::Error::custom(format!("bad signal {}", name))),
+            }
         })
-        .collect::<Result<HashSet<_>, _>>()?;
+        .collect()
+}
+
+const SIGNALS: &[(&str, libc::c_int)] = &[
+    ("HUP", libc::SIGHUP),
+    ("INT", libc::SIGINT),
+    ("QUIT", libc::SIGQUIT),
+    ("ILL", libc::SIGILL),
+    ("TRAP", libc::SIGTRAP),
+    ("ABRT", libc::SIGABRT),
+    ("BUS", libc::SIGBUS),
+    ("FPE", libc::SIGFPE),
+    ("KILL", libc::SIGKILL),
+    ("USR1", libc::SIGUSR1),
+    ("SEGV", libc::SIGSEGV),
+    ("USR2", libc::SIGUSR2),
+    ("PIPE", libc::SIGPIPE),
+    ("ALRM", libc::SIGALRM),
+    ("TERM", libc::SIGTERM),
+    ("CHLD", libc::SIGCHLD),
+    ("CONT", libc::SIGCONT),
+    ("STOP", libc::SIGSTOP),
+    ("TSTP", libc::SIGTSTP),
+    ("TTIN", libc::SIGTTIN),
+    ("TTOU", libc::SIGTTOU),
+    ("URG", libc::SIGURG),
+    ("XCPU", libc::SIGXCPU),
+    ("XFSZ", libc::SIGXFSZ),
+    ("VTALRM", libc::SIGVTALRM),
+    ("PROF", libc::SIGPROF),
+    ("WINCH", libc::SIGWINCH),
+    ("IO", libc::SIGIO),
+    ("PWR", libc::SIGPWR),
+    ("SYS", libc::SIGSYS),
+];
+
+fn signal_from_name(name: &str) -> Option<libc::c_int> {
+    SIGNALS
+        .iter()
+        .find(|(n, _)| *n == name)
+        .map(|(_, sig)| *sig)
+}
+
+fn signal_name(sig: libc::c_int) -> String {
+    match SIGNALS.iter().find(|(_, s)| *s == sig) {
+        Some((name, _)) => format!("SIG{}", name),
+        None => sig.to_string(),
+    }
+}
+
+fn serialize_signals<S>(signals: &[libc::c_int], serializer: S) -> Result<S::Ok, S::Error>
+where
+    S: serde::Serializer,
+{
+    serializer.collect_seq(signals.iter().map(|sig| signal_name(*sig)))
+}
+
+fn deserialize_seccomp<'de, D>(deserializer: D) -> Result<Option<Vec<String>>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum OneOrMany {
+        One(String),
+        Many(Vec<String>),
+    }
+
+    Ok(Some(match OneOrMany::deserialize(deserializer)? {
+        OneOrMany::One(entry) => vec![entry],
+        OneOrMany::Many(entries) => entries,
+    }))
+}
+
+// The inverse of deserialize_caps, in canonical libcap-style form.
+fn serialize_caps<S>(caps: &Option<HashSet<Capability>>, serializer: S) -> Result<S::Ok, S::Error>
+where
+    S: serde::Serializer,
+{
+    let caps = caps.as_ref().expect("skipped when None");
+    serializer.serialize_str(&format!("={}", cap_names(caps)))
+}
+
+fn cap_list(caps: &HashSet<Capability>) -> Vec<String> {
+    let mut names: Vec<String> = caps.iter().map(|c| c.to_string().to_lowercase()).collect();
+    names.sort();
+    names
+}
+
+fn cap_names(caps: &HashSet<Capability>) -> String {
+    cap_list(caps).join(",")
+}
+
+// Interprets "cap_N" (or a bare "N") as the capability numbered N, if this build
+// knows about it.
+fn cap_from_number(name: &str) -> Option<Capability> {
+    let canonical = caps::to_canonical(name);
+    let index: u8 = canonical.strip_prefix("CAP_")?.parse().ok()?;
+    caps::all().into_iter().find(|cap| cap.index() == index)
+}
+
+// Reads a config file and checks its ownership/mode. The permission verdict is
+// computed on the descriptor we actually read from (fstat), so it can't be raced
+// by swapping the file between the check and the read. If the path itself is a
+// symlink, the link must be root-owned too, otherwise whoever owns it can
+// repoint it at a file of their choosing.
+fn read_config_file(path: &str) -> std::io::Result<(Vec<u8>, Result<(), String>)> {
+    let link = fs::symlink_metadata(path)?;
+    let mut f = fs::File::open(path)?;
+    let meta = f.metadata()?;
 
-    Ok(Some(caps))
+    let perms = if link.file_type().is_symlink() && link.uid() != 0 {
+        Err(format!("symlink is owned by uid {}", link.uid()))
+    } else if !meta.is_file() {
+        Err("not a regular file".to_string())
+    } else if meta.uid() != 0 {
+        Err(format!("owned by uid {}", meta.uid()))
+    } else if meta.mode() & 0o022 != 0 {
+        Err(format!(
+            "mode {:o} is group/world-writable",
+            meta.mode() & 0o7777
+        ))
+    } else {
+        Ok(())
+    };
+
+    let mut raw = Vec::new();
+    f.read_to_end(&mut raw)?;
+    Ok((raw, perms))
 }
 
-// Security Hardening: Enforce a deterministic FD state to prevent any
-// attacker-controlled descriptors from leaking into the target command.
+// Security Hardening: Enforce a deterministic state for the standard streams.
+// The rest are closed by close_fds.
 fn sanitize_fds(preserve_stderr: bool) {
     let nfd = match fs::OpenOptions::new().read(true).write(true).open("/dev/null") {
         Ok(f) => f.into_raw_fd(),
-        Err(_) => exit(2),
+        Err(_) => Exit::NoDevNull.exit(),
     };
     let fail_if = |failed: bool| {
         if failed {
-            exit(3);
+            Exit::FdSetup.exit();
         }
     };
 
@@ -135,86 +2892,370 @@ fn sanitize_fds(preserve_stderr: bool) {
         if !preserve_stderr {
             fail_if(libc::dup2(nfd, libc::STDERR_FILENO) < 0);
         }
+        if nfd > libc::STDERR_FILENO {
+            libc::close(nfd);
+        }
+    }
+}
+
+// The ranges to pass to close_range to close every descriptor from "from" up,
+// except the ones in keep.
+fn close_ranges(from: u32, keep: &BTreeSet<u32>) -> Vec<(u32, u32)> {
+    let mut ranges = Vec::new();
+    let mut start = from;
+    for &fd in keep.range(from..) {
+        if fd > start {
+            ranges.push((start, fd - 1));
+        }
+        let Some(next) = fd.checked_add(1) else {
+            return ranges;
+        };
+        start = next;
+    }
+    ranges.push((start, u32::MAX));
+    ranges
+}
 
-        // Close all others (3+).
-        fail_if(libc::syscall(libc::SYS_close_range, 3, !0u32, 0) < 0);
+// Security Hardening: Everything we were started with above stderr (other
+// than what the helper keeps) is closed before anything runs, so no
+// attacker-controlled descriptors leak into the helper. Only the matched
+// helper knows what to keep, so this narrows down in steps: at startup,
+// everything above MAX_KEPT_FD; once the config is loaded, everything no
+// helper keeps; and once the helper is matched, everything it doesn't keep.
+fn close_fds(from: u32, keep: &BTreeSet<u32>) {
+    for (first, last) in close_ranges(from, keep) {
+        if unsafe { libc::syscall(libc::SYS_close_range, first, last, 0) } < 0 {
+            fail!(
+                Exit::FdSetup,
+                "couldn't close fds {}-{}: {}",
+                first,
+                last,
+                std::io::Error::last_os_error()
+            );
+        }
     }
 }
 
 // Logic change: Simplified to "best effort".
 // Removed 'eprintln' (since stderr is not yet connected) and 'CString' allocations.
 fn log_to_kmsg() {
-    if let Ok(f) = fs::OpenOptions::new().write(true).open("/dev/kmsg") {
-        unsafe {
-            libc::dup2(f.as_raw_fd(), libc::STDERR_FILENO);
+    if let Ok(f) = fs::OpenOptions::new().write(true).open("/dev/kmsg")
+        && unsafe { libc::dup2(f.as_raw_fd(), libc::STDERR_FILENO) } >= 0
+    {
+        KMSG.store(true, Ordering::Relaxed);
+    }
+}
+
+// Maps root in pid's (new) user namespace to uid and gid. Setgroups is denied
+// in it, so the helper can't drop supplementary groups to get around
+// negative group permissions.
+fn write_id_maps(pid: libc::pid_t, uid: libc::uid_t, gid: libc::gid_t) {
+    for (file, contents) in [
+        ("setgroups", "deny".to_string()),
+        ("uid_map", format!("0 {} 1\n", uid)),
+        ("gid_map", format!("0 {} 1\n", gid)),
+    ] {
+        let path = format!("/proc/{}/{}", pid, file);
+        fs::write(&path, contents)
+            .unwrap_or_else(|e| fail!(Exit::Privileges, "couldn't write {}: {}", path, e));
+    }
+}
+
+fn nobody() -> passwd::Entry {
+    passwd::Entry {
+        name: "nobody".to_string(),
+        uid: NOBODY,
+        gid: NOBODY,
+        home: "/".to_string(),
+    }
+}
+
+// Switches to the helper's user. With keep_caps, capabilities survive the
+// switch away from root (for priv_restrict to then narrow down), rather than
+// being cleared by the kernel as usual.
+fn drop_identity(entry: &passwd::Entry, keep_caps: bool) {
+    unsafe {
+        if keep_caps && libc::prctl(PR_SET_SECUREBITS, SECBIT_NO_SETUID_FIXUP, 0, 0, 0) < 0 {
+            fail!(Exit::Privileges, "couldn't set securebits");
+        }
+        // Supplementary groups are only reset if there's something to drop,
+        // which also keeps this working where setgroups is denied (e.g. in
+        // unprivileged user namespaces).
+        let mut groups: [libc::gid_t; 2] = [0; 2];
+        let ngroups = libc::getgroups(2, groups.as_mut_ptr());
+        let clean = ngroups == 0 || (ngroups == 1 && groups[0] == entry.gid);
+        if (!clean && libc::setgroups(1, &entry.gid) < 0)
+            || libc::setresgid(entry.gid, entry.gid, entry.gid) < 0
+            || libc::setresuid(entry.uid, entry.uid, entry.uid) < 0
+        {
+            fail!(
+                Exit::Privileges,
+                "couldn't switch to user {}: {}",
+                entry.name,
+                std::io::Error::last_os_error()
+            );
         }
     }
 }
 
+// The capability sets priv_restrict applies, worked out without touching the
+// kernel. Fails with the first capability the ambient denylist refuses, if its
+// policy is to fail.
+#[derive(Debug, PartialEq)]
+struct PrivPlan {
+    // Effective, Inheritable and Permitted alike.
+    caps: HashSet<Capability>,
+    ambient: HashSet<Capability>,
+    // Left out of the ambient set by the denylist.
+    stripped: HashSet<Capability>,
+    // CAP_SETPCAP isn't allowed, so it's removed from every set, bounding
+    // included, once nothing else needs it.
+    drop_setpcap: bool,
+}
+
+fn priv_plan(
+    caps_to_apply: &HashSet<Capability>,
+    ambient: &AmbientLimit,
+) -> Result<PrivPlan, Capability> {
+    let (stripped, raised): (HashSet<Capability>, HashSet<Capability>) = if ambient.disabled {
+        Default::default()
+    } else {
+        caps_to_apply
+            .iter()
+            .partition(|cap| ambient.denylist.contains(cap))
+    };
+    if let (Some(cap), AmbientDenylistPolicy::Fail) = (
+        stripped.iter().min_by_key(|cap| cap.index()),
+        ambient.policy,
+    ) {
+        return Err(*cap);
+    }
+    Ok(PrivPlan {
+        caps: caps_to_apply.clone(),
+        ambient: raised,
+        stripped,
+        drop_setpcap: !caps_to_apply.contains(&Capability::CAP_SETPCAP),
+    })
+}
+
 // Refactoring: Isolate privilege restriction (caps, NNP) into a dedidcated function.
-fn priv_restrict(caps_to_apply: &HashSet<Capability>) {
+fn priv_restrict(caps_to_apply: &HashSet<Capability>, ambient: &AmbientLimit) {
+    let plan = priv_plan(caps_to_apply, ambient).unwrap_or_else(|cap| {
+        fail!(
+            Exit::Privileges,
+            "refusing to raise {} ambient, ambient_denylist forbids it",
+            cap
+        )
+    });
+
     // 1. Disable "Magic Root" behavior.
     // Instruct kernel NOT to automatically grant full capabilities during execve.
     unsafe {
         if libc::prctl(PR_SET_SECUREBITS, SECBIT_NOROOT, 0, 0, 0) < 0 {
-            fail!("couln't set securebits");
+            fail!(Exit::Privileges, "couln't set securebits");
         }
     }
 
     // 2. Drop all capabilities from Effective, Inheritable and Permitted sets,
-    // except the ones explicitly allowed in configuration.
-    for set in [CapSet::Effective, CapSet::Inheritable, CapSet::Permitted] {
-        caps::set(None, set, caps_to_apply)
-            .unwrap_or_else(|e| fail!("couldn't apply caps to {:?}: {}", set, e));
+    // except the ones explicitly allowed in configuration. If we hold
+    // CAP_SETPCAP, it's kept until step 4 either way, since dropping it from
+    // the bounding set needs it.
+    let holds_setpcap =
+        caps::has_cap(None, CapSet::Permitted, Capability::CAP_SETPCAP).unwrap_or(false);
+    let mut working = plan.caps.clone();
+    if !plan.drop_setpcap {
+        warn!("CAP_SETPCAP is allowed, so the helper can change its own capabilities");
+    } else if holds_setpcap {
+        working.insert(Capability::CAP_SETPCAP);
+    }
+    for (set, caps) in [
+        (CapSet::Effective, &working),
+        (CapSet::Inheritable, &plan.caps),
+        (CapSet::Permitted, &working),
+    ] {
+        caps::set(None, set, caps)
+            .unwrap_or_else(|e| fail!(Exit::Privileges, "couldn't apply caps to {:?}: {}", set, e));
     }
 
-    // 3. Add allowed capabilities to the Ambient set so they persist across execve.
-    for cap in caps_to_apply {
-        caps::raise(None, CapSet::Ambient, *cap)
-            .unwrap_or_else(|e| fail!("couldn't set ambient cap {:?}: {}", cap, e));
+    // 3. Add allowed capabilities to the Ambient set so they persist across execve,
+    // unless the ambient denylist forbids it.
+    for cap in &plan.stripped {
+        warn!("not raising {} ambient, ambient_denylist forbids it", cap);
+    }
+    for cap in &plan.ambient {
+        caps::raise(None, CapSet::Ambient, *cap).unwrap_or_else(|e| {
+            fail!(
+                Exit::Privileges,
+                "couldn't set ambient cap {:?}: {}",
+                cap,
+                e
+            )
+        });
+    }
+
+    // 4. Without CAP_SETPCAP, nothing can change the sets above anymore; it goes
+    // last, from the bounding set too, so it can't be regained.
+    if plan.drop_setpcap && holds_setpcap {
+        drop_setpcap();
     }
 
-    // 4. Security Hardening: Set the NNP (No New Privileges) bit.
+    // 5. Security Hardening: Set the NNP (No New Privileges) bit.
     // NNP complements SECBIT_NOROOT by ensuring privileges cannot be re-acquired
     // after execve (e.g., through setuid/setgid bit or file capabilities).
     unsafe {
         if libc::prctl(PR_SET_NO_NEW_PRIVS, 1, 0, 0, 0) < 0 {
-            fail!("failed to set nnp");
+            fail!(Exit::Privileges, "failed to set nnp");
+        }
+    }
+}
+
+// Everything but CAP_SETPCAP, which dropping the rest takes; priv_restrict
+// drops that one last.
+fn drop_bounding_set() {
+    for cap in caps::all() {
+        if cap == Capability::CAP_SETPCAP
+            || !caps::has_cap(None, CapSet::Bounding, cap).unwrap_or(true)
+        {
+            continue;
         }
+        caps::drop(None, CapSet::Bounding, cap).unwrap_or_else(|e| {
+            fail!(
+                Exit::Privileges,
+                "couldn't drop {} from the bounding set: {}",
+                cap,
+                e
+            )
+        });
+    }
+}
+
+fn drop_setpcap() {
+    let cap = Capability::CAP_SETPCAP;
+    if caps::has_cap(None, CapSet::Bounding, cap).unwrap_or(true) {
+        caps::drop(None, CapSet::Bounding, cap).unwrap_or_else(|e| {
+            fail!(
+                Exit::Privileges,
+                "couldn't drop {} from the bounding set: {}",
+                cap,
+                e
+            )
+        });
     }
+    for set in [CapSet::Effective, CapSet::Permitted] {
+        caps::drop(None, set, cap).unwrap_or_else(|e| {
+            fail!(
+                Exit::Privileges,
+                "couldn't drop {} from {:?}: {}",
+                cap,
+                set,
+                e
+            )
+        });
+    }
+}
+
+// Interactive modes are only recognized when we're run under our own name; the
+// kernel always passes the path of the helper it wanted as argv[0].
+fn invoked_as_self(args: &[OsString]) -> bool {
+    let (Some(arg0), Ok(exe)) = (args.first(), std::env::current_exe()) else {
+        return false;
+    };
+    let name = Path::new(arg0).file_name();
+    name.is_some() && name == exe.file_name()
 }
 
 fn main() {
+    let started = Instant::now();
+    // Snapshot the environment the kernel gave us before anything touches it.
+    let env: HashMap<OsString, OsString> = std::env::vars_os().collect();
+
     // Debug logic: Logs are sent to kmsg by default (production).
     // They are preserved on stderr only when HULDUFOLK_DEBUG is set, to allow output
-    // capture for manual debugging or integration testing.
-    let debug_mode = std::env::var("HULDUFOLK_DEBUG").is_ok();
+    // capture for manual debugging or integration testing. HULDUFOLK_DEBUG=kmsg
+    // enables debug output while still logging to kmsg.
+    let debug_var = std::env::var_os("HULDUFOLK_DEBUG");
+    DEBUG.store(debug_var.is_some(), Ordering::Relaxed);
+    let debug_mode = debug_var.is_some_and(|v| v != "kmsg");
+
+    let args: Vec<OsString> = std::env::args_os().collect();
+    if invoked_as_self(&args) {
+        match args.get(1).and_then(|a| a.to_str()) {
+            Some("--argv-hash") => return argv_hash::run(&args[2..]),
+            #[cfg(feature = "test-mode")]
+            Some("--as") => return as_helper::run(&args[2..], started),
+            Some("--bench") => return bench::run(&args[2..]),
+            Some("--config-hash") => return config_hash::run(&args[2..]),
+            Some("--dump") => return dump::run(&args[2..]),
+            Some("--exit-codes") => return exit::print_table(),
+            Some("--export-policy") => return export::run(&args[2..]),
+            Some("--schema") => return schema::print(),
+            Some("--validate") => return validate::run(&args[2..]),
+            _ => {}
+        }
+    }
 
     sanitize_fds(debug_mode);
+    close_fds(MAX_KEPT_FD + 1, &BTreeSet::new());
+    // Security Hardening: Don't leave core dumps (or ptrace access) of a root
+    // process holding the invocation's arguments and environment around. A
+    // helper with allow_core undoes this in its own process, right before exec.
+    unsafe {
+        if libc::prctl(libc::PR_SET_DUMPABLE, 0, 0, 0, 0) < 0 {
+            fail!(Exit::Privileges, "couldn't make ourselves non-dumpable");
+        }
+    }
     if !debug_mode {
         log_to_kmsg();
     }
+    // Nothing before the helper is matched may hang either, e.g. reading the
+    // config or writing the audit log.
+    arm_setup_timer(MATCH_TIMEOUT, started);
+    if cfg!(feature = "self-verify") {
+        self_verify::check();
+    }
 
+    check_killswitch(KILLSWITCH_PATH);
     let path = DEFAULT_CONFIG_PATH.unwrap_or("/etc/usermode-helper.conf");
-    let config = Config::load(path);
+    let config = Config::load_and_cache(path);
+    config.close_unkept_fds();
+    config.kmsg_priority.install();
+    // Before anything else the config could fail us on, so the killswitch
+    // still works when some other check is what's going wrong.
+    config.check_killswitch();
+    config.check_config_hash();
 
-    let args: Vec<OsString> = std::env::args_os().collect();
-    let helper = config.find_helper(&args);
-
-    // Restrict privileges based on configured capabilities.
-    if let Some(caps) = &helper.capabilities {
-        priv_restrict(caps);
-    }
-    /* ALTERNATIVE APPROACH ("Zero-Trust"):
-     * If no capabilties are defined (empty set), strip all privileges.
-     *
-     *  let empty_caps = HashSet::new();
-     *  let caps = helper.capabilities.as_ref().unwrap_or(&empty_caps);
-     *  priv_restrict(caps);
-     */
-
-    if debug_mode {
-        let msg = format!("-- DEBUG CAPS for {} --\n", helper.path);
+    let helper = config.find_helper(&args, &env);
+    // An under-privileged huldufolk is a deployment problem, so it's reported
+    // as such rather than as whichever check it would otherwise trip first.
+    helper.check_own_caps();
+    close_fds(3, &helper.kept_fds());
+    invoke(&config, helper, &args, started);
+}
+
+// Everything from a matched helper on: the remaining checks, restricting
+// ourselves, and running the helper.
+fn invoke(config: &Config, helper: &Helper, args: &[OsString], started: Instant) {
+    helper.arm_setup_timeout(started);
+    config.check_matched_path(helper);
+    // Checked at load too, but a cached config may predate passwd changes.
+    if let Err(e) = check_target_uid(config.allowed_target_uids.as_deref(), helper) {
+        fail!(Exit::Config, "{}", e);
+    }
+    config.check_secure_boot(helper);
+    helper.check_urandom_ready();
+    helper.check_exec_coherence(args, config.exec_coherence);
+    helper.run_pre_exec_hook(args);
+    helper.run_capability_precheck(args, &config.ambient_limit(helper));
+
+    helper.run_probe(config);
+    // Last, so that only invocations that get as far as running use it up.
+    config.check_invocation_budget(helper);
+
+    helper.restrict(config);
+
+    if DEBUG.load(Ordering::Relaxed) {
+        let prefix = log_prefix(LogCategory::Debug);
+        let msg = format!("{}-- DEBUG CAPS for {} --\n", prefix, helper.path());
         let _ = std::io::stderr().write_all(msg.as_bytes());
         for set in [
             CapSet::Effective,
@@ -223,10 +3264,178 @@ fn main() {
             CapSet::Ambient,
         ] {
             let c = caps::read(None, set).unwrap_or_default();
-            let line = format!("{:?}: {:?}\n", set, c);
+            let line = format!("{}{:?}: {:?}\n", prefix, set, c);
             let _ = std::io::stderr().write_all(line.as_bytes());
         }
     }
 
-    helper.execute(&args);
+    let audit_log = config
+        .audit_log
+        .as_deref()
+        .map(|log| (log, config.audit_full_policy()));
+    helper.execute(args, config.log_success, audit_log);
+}
+
+// The capability plan and the fd ranges to close need no privileges, unlike
+// applying them, so they're checked here rather than in the bats suite.
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn caps(names: &[&str]) -> HashSet<Capability> {
+        names.iter().map(|name| name.parse().unwrap()).collect()
+    }
+
+    fn limit(denylist: &[&str], policy: AmbientDenylistPolicy) -> AmbientLimit {
+        AmbientLimit {
+            denylist: caps(denylist),
+            policy,
+            disabled: false,
+        }
+    }
+
+    #[test]
+    fn plan_raises_everything_without_a_denylist() {
+        let plan = priv_plan(
+            &caps(&["CAP_SYS_ADMIN", "CAP_NET_ADMIN"]),
+            &AmbientLimit::default(),
+        );
+        assert_eq!(
+            plan,
+            Ok(PrivPlan {
+                caps: caps(&["CAP_SYS_ADMIN", "CAP_NET_ADMIN"]),
+                ambient: caps(&["CAP_SYS_ADMIN", "CAP_NET_ADMIN"]),
+                stripped: caps(&[]),
+                drop_setpcap: true,
+            })
+        );
+    }
+
+    #[test]
+    fn plan_strips_denylisted_caps_from_ambient_only() {
+        let plan = priv_plan(
+            &caps(&["CAP_SYS_ADMIN", "CAP_NET_ADMIN"]),
+            &limit(
+                &["CAP_SYS_ADMIN", "CAP_SYS_MODULE"],
+                AmbientDenylistPolicy::Strip,
+            ),
+        );
+        assert_eq!(
+            plan,
+            Ok(PrivPlan {
+                caps: caps(&["CAP_SYS_ADMIN", "CAP_NET_ADMIN"]),
+                ambient: caps(&["CAP_NET_ADMIN"]),
+                stripped: caps(&["CAP_SYS_ADMIN"]),
+                drop_setpcap: true,
+            })
+        );
+    }
+
+    #[test]
+    fn plan_fails_on_the_lowest_denylisted_cap() {
+        let plan = priv_plan(
+            &caps(&["CAP_SYS_ADMIN", "CAP_NET_ADMIN", "CAP_CHOWN"]),
+            &limit(
+                &["CAP_SYS_ADMIN", "CAP_NET_ADMIN"],
+                AmbientDenylistPolicy::Fail,
+            ),
+        );
+        assert_eq!(plan, Err(Capability::CAP_NET_ADMIN));
+    }
+
+    #[test]
+    fn plan_ignores_the_fail_policy_when_nothing_is_denylisted() {
+        let plan = priv_plan(
+            &caps(&["CAP_CHOWN"]),
+            &limit(&["CAP_SYS_ADMIN"], AmbientDenylistPolicy::Fail),
+        );
+        assert_eq!(plan.map(|plan| plan.ambient), Ok(caps(&["CAP_CHOWN"])));
+    }
+
+    #[test]
+    fn plan_keeps_setpcap_only_when_allowed() {
+        let plan = priv_plan(&caps(&["CAP_SETPCAP"]), &AmbientLimit::default());
+        assert_eq!(plan.map(|plan| plan.drop_setpcap), Ok(false));
+        let plan = priv_plan(&caps(&["CAP_SYS_ADMIN"]), &AmbientLimit::default());
+        assert_eq!(plan.map(|plan| plan.drop_setpcap), Ok(true));
+    }
+
+    #[test]
+    fn plan_for_no_caps_is_empty() {
+        let plan = priv_plan(
+            &caps(&[]),
+            &limit(&["CAP_SYS_ADMIN"], AmbientDenylistPolicy::Fail),
+        );
+        assert_eq!(
+            plan,
+            Ok(PrivPlan {
+                caps: caps(&[]),
+                ambient: caps(&[]),
+                stripped: caps(&[]),
+                drop_setpcap: true,
+            })
+        );
+    }
+
+    #[test]
+    fn plan_without_ambient_ignores_the_denylist() {
+        let plan = priv_plan(
+            &caps(&["CAP_SYS_ADMIN", "CAP_NET_ADMIN"]),
+            &AmbientLimit {
+                disabled: true,
+                ..limit(&["CAP_SYS_ADMIN"], AmbientDenylistPolicy::Fail)
+            },
+        );
+        assert_eq!(
+            plan,
+            Ok(PrivPlan {
+                caps: caps(&["CAP_SYS_ADMIN", "CAP_NET_ADMIN"]),
+                ambient: caps(&[]),
+                stripped: caps(&[]),
+                drop_setpcap: true,
+            })
+        );
+    }
+
+    // The fd sweeps pass close_range the ranges between kept descriptors; which
+    // descriptors a helper actually ends up with is checked in the bats suite.
+
+    fn keep(fds: &[u32]) -> BTreeSet<u32> {
+        fds.iter().copied().collect()
+    }
+
+    #[test]
+    fn close_ranges_without_keep_is_one_sweep() {
+        assert_eq!(close_ranges(3, &keep(&[])), vec![(3, u32::MAX)]);
+        assert_eq!(close_ranges(10, &keep(&[])), vec![(10, u32::MAX)]);
+    }
+
+    #[test]
+    fn close_ranges_skip_kept_fds() {
+        assert_eq!(
+            close_ranges(3, &keep(&[5, 9])),
+            vec![(3, 4), (6, 8), (10, u32::MAX)]
+        );
+    }
+
+    #[test]
+    fn close_ranges_with_adjacent_kept_fds() {
+        assert_eq!(
+            close_ranges(3, &keep(&[3, 4, 6, 7])),
+            vec![(5, 5), (8, u32::MAX)]
+        );
+    }
+
+    #[test]
+    fn close_ranges_ignore_fds_below_from() {
+        assert_eq!(
+            close_ranges(8, &keep(&[4, 8, 12])),
+            vec![(9, 11), (13, u32::MAX)]
+        );
+    }
+
+    #[test]
+    fn close_ranges_keep_the_last_fd() {
+        assert_eq!(close_ranges(3, &keep(&[u32::MAX])), vec![(3, u32::MAX - 1)]);
+    }
 }
@@ -0,0 +1,178 @@
+// Mount namespaces for helpers with mount_namespace: a private copy of our
+// mounts, with the helper's bind_mounts set up in it, and an optional check
+// (verify_mounts) that the result is what was asked for. Under nested
+// containers, mount calls can succeed without the mounts ending up where or
+// how we expect, e.g. with propagation still shared with the host.
+
+use serde::{Deserialize, Serialize};
+use std::ffi::CString;
+use std::fs;
+use std::io;
+use std::ptr;
+
+#[derive(Deserialize, Serialize, Clone)]
+pub(crate) struct BindMount {
+    pub(crate) source: String,
+    pub(crate) target: String,
+    #[serde(default)]
+    pub(crate) read_only: bool,
+}
+
+// One line of /proc/self/mountinfo, as far as we care.
+pub(crate) struct Mount {
+    pub(crate) mount_point: String,
+    pub(crate) options: Vec<String>,
+    // The optional fields, e.g. "shared:1" or "master:2".
+    pub(crate) propagation: Vec<String>,
+}
+
+impl Mount {
+    fn private(&self) -> bool {
+        !self
+            .propagation
+            .iter()
+            .any(|tag| tag.starts_with("shared:") || tag.starts_with("master:"))
+    }
+}
+
+// Paths in mountinfo have spaces, tabs, newlines and backslashes escaped as
+// octal, e.g. "\040".
+fn unescape(field: &str) -> String {
+    let mut out = Vec::with_capacity(field.len());
+    let bytes = field.as_bytes();
+    let mut i = 0;
+    while i < bytes.len() {
+        let octal = bytes.get(i + 1..i + 4).and_then(|digits| {
+            let digits = std::str::from_utf8(digits).ok()?;
+            u8::from_str_radix(digits, 8).ok()
+        });
+        match (bytes[i], octal) {
+            (b'\\', Some(byte)) => {
+                out.push(byte);
+                i += 4;
+            }
+            (byte, _) => {
+                out.push(byte);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+// See proc_pid_mountinfo(5): ID, parent ID, major:minor, root, mount point,
+// options, optional fields up to a "-", then filesystem details we don't need.
+fn parse_line(line: &str) -> Option<Mount> {
+    let mut fields = line.split(' ');
+    let mount_point = fields.nth(4)?;
+    let options = fields.next()?;
+    let propagation = fields.by_ref().take_while(|field| *field != "-");
+    Some(Mount {
+        mount_point: unescape(mount_point),
+        options: options.split(',').map(str::to_string).collect(),
+        propagation: propagation.map(str::to_string).collect(),
+    })
+}
+
+pub(crate) fn mountinfo() -> io::Result<Vec<Mount>> {
+    fs::read_to_string("/proc/self/mountinfo")?
+        .lines()
+        .map(|line| {
+            parse_line(line).ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("bad mountinfo line {:?}", line),
+                )
+            })
+        })
+        .collect()
+}
+
+fn mount(source: Option<&str>, target: &str, flags: libc::c_ulong) -> Result<(), String> {
+    let c_path = |path: &str| CString::new(path).map_err(|_| format!("bad path {:?}", path));
+    let source = source.map(c_path).transpose()?;
+    let c_target = c_path(target)?;
+    let source_ptr = source.as_ref().map_or(ptr::null(), |s| s.as_ptr());
+    if unsafe {
+        libc::mount(
+            source_ptr,
+            c_target.as_ptr(),
+            ptr::null(),
+            flags,
+            ptr::null(),
+        )
+    } < 0
+    {
+        return Err(format!("{}: {}", target, io::Error::last_os_error()));
+    }
+    Ok(())
+}
+
+// A remount has to repeat the flags the mount already has, since inside a user
+// namespace the kernel refuses to clear the ones inherited from outside it.
+fn locked_flags(target: &str) -> Result<libc::c_ulong, String> {
+    let c_target = CString::new(target).map_err(|_| format!("bad path {:?}", target))?;
+    let mut st: libc::statvfs = unsafe { std::mem::zeroed() };
+    if unsafe { libc::statvfs(c_target.as_ptr(), &mut st) } < 0 {
+        return Err(format!("{}: {}", target, io::Error::last_os_error()));
+    }
+    Ok([
+        (libc::ST_NOSUID, libc::MS_NOSUID),
+        (libc::ST_NODEV, libc::MS_NODEV),
+        (libc::ST_NOEXEC, libc::MS_NOEXEC),
+        (libc::ST_NOATIME, libc::MS_NOATIME),
+        (libc::ST_NODIRATIME, libc::MS_NODIRATIME),
+        (libc::ST_RELATIME, libc::MS_RELATIME),
+    ]
+    .into_iter()
+    .filter(|(st_flag, _)| st.f_flag & st_flag != 0)
+    .fold(0, |flags, (_, ms_flag)| flags | ms_flag))
+}
+
+// Unshares the mount namespace, stops propagation in both directions, and sets
+// up the bind mounts.
+pub(crate) fn setup(binds: &[BindMount]) -> Result<(), String> {
+    if unsafe { libc::unshare(libc::CLONE_NEWNS) } < 0 {
+        return Err(format!("unshare: {}", io::Error::last_os_error()));
+    }
+    mount(None, "/", libc::MS_REC | libc::MS_PRIVATE)?;
+    for bind in binds {
+        mount(
+            Some(&bind.source),
+            &bind.target,
+            libc::MS_BIND | libc::MS_REC,
+        )?;
+        if bind.read_only {
+            let flags = locked_flags(&bind.target)?;
+            mount(
+                None,
+                &bind.target,
+                flags | libc::MS_BIND | libc::MS_REMOUNT | libc::MS_RDONLY,
+            )?;
+        }
+    }
+    Ok(())
+}
+
+// Checks the namespace we're in against what setup was asked for: nothing
+// propagates to or from other namespaces, and every bind mount is mounted at
+// its target, read-only if it should be.
+pub(crate) fn verify(binds: &[BindMount]) -> Result<(), String> {
+    let mounts = mountinfo().map_err(|e| format!("couldn't read mountinfo: {}", e))?;
+    if let Some(shared) = mounts.iter().find(|m| !m.private()) {
+        return Err(format!(
+            "{} isn't private ({})",
+            shared.mount_point,
+            shared.propagation.join(" ")
+        ));
+    }
+    for bind in binds {
+        let Some(top) = mounts.iter().rev().find(|m| m.mount_point == bind.target) else {
+            return Err(format!("nothing is mounted at {}", bind.target));
+        };
+        if bind.read_only && !top.options.iter().any(|option| option == "ro") {
+            return Err(format!("{} isn't read-only", bind.target));
+        }
+    }
+    Ok(())
+}
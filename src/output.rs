@@ -0,0 +1,184 @@
+// Output capture for supervised helpers with capture_output: their stdout and
+// stderr are read through pipes while we wait for them, and every line is
+// logged with the stream it came from, e.g. "[stderr] no such device". Lines
+// go to the helper's output_log, or to the audit log as JSON records.
+//
+// At most max_output_bytes are kept; anything after that is still read (so the
+// helper never blocks on a full pipe) but dropped.
+
+use std::fs::{self, File};
+use std::io::{self, Read, Write};
+use std::os::unix::fs::OpenOptionsExt;
+use std::os::unix::io::AsRawFd;
+use std::process::{ChildStderr, ChildStdout};
+use std::time::{Duration, Instant};
+
+pub(crate) const DEFAULT_MAX_OUTPUT_BYTES: u64 = 64 * 1024;
+
+// Where captured lines are appended.
+pub(crate) enum Sink<'a> {
+    Log(&'a str),
+    Audit(&'a str),
+}
+
+impl Sink<'_> {
+    fn path(&self) -> &str {
+        match self {
+            Sink::Log(path) | Sink::Audit(path) => path,
+        }
+    }
+}
+
+struct Stream<R> {
+    name: &'static str,
+    reader: Option<R>,
+    partial: Vec<u8>,
+}
+
+impl<R: AsRawFd> Stream<R> {
+    // -1 once the stream is closed, which poll ignores anyway.
+    fn fd(&self) -> libc::c_int {
+        self.reader.as_ref().map_or(-1, AsRawFd::as_raw_fd)
+    }
+}
+
+struct Capture<'a> {
+    helper: &'a str,
+    sink: Sink<'a>,
+    file: Option<File>,
+    remaining: u64,
+    truncated: bool,
+}
+
+impl Capture<'_> {
+    fn line(&mut self, stream: &str, line: &[u8]) {
+        let line = format!("[{}] {}", stream, String::from_utf8_lossy(line));
+        let record = match self.sink {
+            Sink::Log(_) => format!("{} {}\n", crate::audit::now(), line).into_bytes(),
+            Sink::Audit(_) => crate::audit::output_record(self.helper, &line),
+        };
+        let Some(file) = &mut self.file else {
+            return;
+        };
+        if let Err(e) = file.write_all(&record) {
+            warn!(
+                "couldn't write output of {} to {}: {}",
+                self.helper,
+                self.sink.path(),
+                e
+            );
+            self.file = None;
+        }
+    }
+
+    // Keeps what fits in the budget, and logs every line that's complete.
+    fn data<R>(&mut self, stream: &mut Stream<R>, data: &[u8]) {
+        let kept = data.len().min(self.remaining as usize);
+        self.remaining -= kept as u64;
+        self.truncated |= kept < data.len();
+        stream.partial.extend_from_slice(&data[..kept]);
+        while let Some(end) = stream.partial.iter().position(|b| *b == b'\n') {
+            let line: Vec<u8> = stream.partial.drain(..=end).collect();
+            self.line(stream.name, &line[..end]);
+        }
+    }
+
+    fn drain<R: Read>(&mut self, stream: &mut Stream<R>) {
+        let Some(reader) = &mut stream.reader else {
+            return;
+        };
+        let mut buf = [0; 4096];
+        let read = reader.read(&mut buf);
+        match read {
+            Ok(0) => stream.reader = None,
+            Ok(n) => self.data(stream, &buf[..n]),
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => {}
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(_) => stream.reader = None,
+        }
+        if stream.reader.is_none() {
+            self.flush(stream);
+        }
+    }
+
+    // Logs what's left of an unterminated last line.
+    fn flush<R>(&mut self, stream: &mut Stream<R>) {
+        if !stream.partial.is_empty() {
+            let line = std::mem::take(&mut stream.partial);
+            self.line(stream.name, &line);
+        }
+    }
+}
+
+fn set_nonblocking(fd: libc::c_int) {
+    unsafe {
+        let flags = libc::fcntl(fd, libc::F_GETFL);
+        libc::fcntl(fd, libc::F_SETFL, flags | libc::O_NONBLOCK);
+    }
+}
+
+// Logs the helper's output until both pipes are closed or the deadline passes,
+// whichever comes first.
+pub(crate) fn capture(
+    helper: &str,
+    sink: Sink,
+    max_bytes: u64,
+    stdout: ChildStdout,
+    stderr: ChildStderr,
+    deadline: Instant,
+) {
+    let file = fs::OpenOptions::new()
+        .append(true)
+        .create(true)
+        .mode(0o600)
+        .open(sink.path())
+        .inspect_err(|e| warn!("couldn't open {}: {}", sink.path(), e))
+        .ok();
+    let mut capture = Capture {
+        helper,
+        sink,
+        file,
+        remaining: max_bytes,
+        truncated: false,
+    };
+    let mut out = Stream {
+        name: "stdout",
+        reader: Some(stdout),
+        partial: Vec::new(),
+    };
+    let mut err = Stream {
+        name: "stderr",
+        reader: Some(stderr),
+        partial: Vec::new(),
+    };
+    set_nonblocking(out.fd());
+    set_nonblocking(err.fd());
+
+    while (out.reader.is_some() || err.reader.is_some()) && Instant::now() < deadline {
+        let mut fds = [out.fd(), err.fd()].map(|fd| libc::pollfd {
+            fd,
+            events: libc::POLLIN,
+            revents: 0,
+        });
+        let wait = deadline
+            .saturating_duration_since(Instant::now())
+            .min(Duration::from_millis(100));
+        unsafe {
+            libc::poll(
+                fds.as_mut_ptr(),
+                fds.len() as libc::nfds_t,
+                wait.as_millis() as libc::c_int,
+            );
+        }
+        capture.drain(&mut out);
+        capture.drain(&mut err);
+    }
+
+    // Lines cut short by the deadline are still worth having.
+    capture.flush(&mut out);
+    capture.flush(&mut err);
+    if capture.truncated {
+        let note = format!("output truncated after {} bytes", max_bytes);
+        capture.line("huldufolk", note.as_bytes());
+    }
+}
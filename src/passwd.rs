@@ -0,0 +1,44 @@
+// Minimal passwd(5) lookup. We're statically linked against musl and run before
+// much of userspace is up, so this reads the file directly rather than going
+// through NSS.
+
+use std::fs;
+use std::io;
+
+#[derive(Clone)]
+pub(crate) struct Entry {
+    pub(crate) name: String,
+    pub(crate) uid: libc::uid_t,
+    pub(crate) gid: libc::gid_t,
+    pub(crate) home: String,
+}
+
+// Looks up a user by name, or by uid if user is numeric.
+pub(crate) fn lookup(path: &str, user: &str) -> io::Result<Option<Entry>> {
+    let contents = fs::read_to_string(path)?;
+    for line in contents.lines() {
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let fields: Vec<&str> = line.split(':').collect();
+        let [name, _, uid, gid, _, home, _] = fields[..] else {
+            continue;
+        };
+        if name != user && uid != user {
+            continue;
+        }
+        let bad = |what| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("bad {} for {}", what, name),
+            )
+        };
+        return Ok(Some(Entry {
+            name: name.to_string(),
+            uid: uid.parse().map_err(|_| bad("uid"))?,
+            gid: gid.parse().map_err(|_| bad("gid"))?,
+            home: home.to_string(),
+        }));
+    }
+    Ok(None)
+}
@@ -0,0 +1,100 @@
+// Whether the kernel's RNG is initialized, for helpers with
+// require_urandom_ready: getrandom with GRND_NONBLOCK fails with EAGAIN until
+// it is. Kernels too old for getrandom only have entropy_avail to go by.
+
+use std::fs;
+use std::io;
+use std::time::Duration;
+
+const ENTROPY_AVAIL: &str = "/proc/sys/kernel/random/entropy_avail";
+// What entropy_avail reads once the RNG is fully seeded on current kernels.
+const MIN_ENTROPY_AVAIL: u32 = 256;
+// How often to check again while waiting with urandom_wait_secs.
+pub(crate) const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+fn getrandom_nonblock() -> io::Result<()> {
+    let mut byte = 0u8;
+    let ret = unsafe { libc::getrandom((&mut byte as *mut u8).cast(), 1, libc::GRND_NONBLOCK) };
+    if ret < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+// Decides from getrandom's result, falling back to entropy_avail only if
+// there's no getrandom.
+fn readiness(
+    getrandom: io::Result<()>,
+    entropy_avail: impl FnOnce() -> io::Result<String>,
+) -> io::Result<bool> {
+    match getrandom {
+        Ok(()) => Ok(true),
+        Err(e) if e.raw_os_error() == Some(libc::EAGAIN) => Ok(false),
+        Err(e) if e.raw_os_error() == Some(libc::ENOSYS) => {
+            let avail = entropy_avail()?;
+            let avail: u32 = avail.trim().parse().map_err(|_| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("bad entropy_avail {:?}", avail),
+                )
+            })?;
+            Ok(avail >= MIN_ENTROPY_AVAIL)
+        }
+        Err(e) => Err(e),
+    }
+}
+
+pub(crate) fn ready() -> io::Result<bool> {
+    readiness(getrandom_nonblock(), || fs::read_to_string(ENTROPY_AVAIL))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entropy(avail: &str) -> impl FnOnce() -> io::Result<String> {
+        let avail = avail.to_string();
+        move || Ok(avail)
+    }
+
+    fn errno(errno: i32) -> io::Result<()> {
+        Err(io::Error::from_raw_os_error(errno))
+    }
+
+    #[test]
+    fn is_ready_when_getrandom_succeeds() {
+        let ready = readiness(Ok(()), || panic!("entropy_avail isn't needed"));
+        assert!(ready.unwrap());
+    }
+
+    #[test]
+    fn isnt_ready_while_getrandom_would_block() {
+        let ready = readiness(errno(libc::EAGAIN), entropy("256\n"));
+        assert!(!ready.unwrap());
+    }
+
+    #[test]
+    fn without_getrandom_goes_by_entropy_avail() {
+        for (avail, ready) in [
+            ("0\n", false),
+            ("255\n", false),
+            ("256\n", true),
+            ("4096", true),
+        ] {
+            let verdict = readiness(errno(libc::ENOSYS), entropy(avail));
+            assert_eq!(verdict.unwrap(), ready, "entropy_avail {:?}", avail);
+        }
+    }
+
+    #[test]
+    fn readiness_fails_on_bad_entropy_avail() {
+        assert!(readiness(errno(libc::ENOSYS), entropy("lots\n")).is_err());
+        let unreadable = || Err(io::Error::from_raw_os_error(libc::EACCES));
+        assert!(readiness(errno(libc::ENOSYS), unreadable).is_err());
+    }
+
+    #[test]
+    fn readiness_fails_on_other_getrandom_errors() {
+        assert!(readiness(errno(libc::EFAULT), entropy("256")).is_err());
+    }
+}
@@ -0,0 +1,325 @@
+// Interactive: usermode-helper --schema
+//
+// Prints a JSON Schema of the config format, for editors to complete and
+// validate configs with. The fields come from the ones serde's derives know
+// about, so a new config field can't be left out silently: without an entry in
+// the tables below, --schema refuses to run. Likewise, the choices of enum
+// settings are the variants serde accepts, not a copy of them.
+
+use crate::{
+    AbsentEnvPolicy, AmbientDenylistPolicy, AuditFullPolicy, Config, DuplicatePolicy,
+    ExecCoherence, Helper, MAX_KEPT_FD, MissingBinaryPolicy, OnExecFailure, Preamble,
+    SecureBootUnknown, UnknownCapPolicy,
+};
+use serde::de::{self, Deserialize, Deserializer, Visitor};
+use serde_json::{Map, Value, json};
+use std::cell::Cell;
+
+// A deserializer that only records the field (or variant) names a derived
+// Deserialize asks for, and then bails out.
+struct Names<'a>(&'a Cell<&'static [&'static str]>);
+
+impl<'de> Deserializer<'de> for Names<'_> {
+    type Error = de::value::Error;
+
+    fn deserialize_any<V: Visitor<'de>>(self, _: V) -> Result<V::Value, Self::Error> {
+        Err(de::Error::custom("not a struct"))
+    }
+
+    fn deserialize_struct<V: Visitor<'de>>(
+        self,
+        _: &'static str,
+        fields: &'static [&'static str],
+        _: V,
+    ) -> Result<V::Value, Self::Error> {
+        self.0.set(fields);
+        Err(de::Error::custom("only after the field names"))
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        _: &'static str,
+        variants: &'static [&'static str],
+        _: V,
+    ) -> Result<V::Value, Self::Error> {
+        self.0.set(variants);
+        Err(de::Error::custom("only after the variant names"))
+    }
+
+    serde::forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+        bytes byte_buf option unit unit_struct newtype_struct seq tuple
+        tuple_struct map identifier ignored_any
+    }
+}
+
+// Field names of a struct, or variant names of an enum.
+fn names<T: for<'de> Deserialize<'de>>() -> &'static [&'static str] {
+    let names = Cell::new(&[][..]);
+    let _ = T::deserialize(Names(&names));
+    names.get()
+}
+
+fn string(description: &str) -> Value {
+    json!({ "type": "string", "description": description })
+}
+
+fn strings(description: &str) -> Value {
+    json!({ "type": "array", "items": { "type": "string" }, "description": description })
+}
+
+fn boolean(description: &str) -> Value {
+    json!({ "type": "boolean", "description": description })
+}
+
+fn count(description: &str) -> Value {
+    json!({ "type": "integer", "minimum": 0, "description": description })
+}
+
+fn choice<T: for<'de> Deserialize<'de>>(description: &str) -> Value {
+    json!({ "enum": names::<T>(), "description": description })
+}
+
+fn config_property(field: &str) -> Option<Value> {
+    Some(match field {
+        "unknown_cap_policy" => {
+            choice::<UnknownCapPolicy>("How to treat capability names this build doesn't know")
+        }
+        "max_dropin_files" => count("Most drop-in files read (1024 by default)"),
+        "recursive_dropins" => boolean("Read drop-ins in subdirectories of the .d directory"),
+        "killswitch" => string("Deny every helper while anything exists at this path"),
+        "deny_stub" => {
+            string("Program run, with no capabilities, instead of failing unmatched invocations")
+        }
+        "default_workdir" => {
+            string("Working directory of helpers without a workdir; \"\" keeps the inherited one")
+        }
+        "default_env_path" => string("PATH of helpers without an env_path"),
+        "trusted_interpreters" => strings("Dynamic linkers helpers with verify_interp may use"),
+        "seccomp_profiles" => json!({
+            "type": "object",
+            "additionalProperties": { "type": "array", "items": { "type": "string" } },
+            "description": "Named syscall lists, referenced as @name",
+        }),
+        "exec_coherence" => choice::<ExecCoherence>(
+            "What to do when the matched helper and the program to execute don't add up",
+        ),
+        "duplicate_policy" => choice::<DuplicatePolicy>(
+            "What to do when two config files define a helper for the same program and argc",
+        ),
+        "audit_log" => string("Append a JSON record of every invocation to this file"),
+        "audit_required" => boolean("Deny invocations whose audit record can't be written"),
+        "audit_full_policy" => {
+            choice::<AuditFullPolicy>("What to do when the audit log's filesystem is full")
+        }
+        "log_success" => boolean("Log a one-line summary of every allowed invocation to kmsg"),
+        "passwd_file" => string("Where helpers' users are looked up"),
+        "allowed_target_uids" => json!({
+            "type": "array",
+            "items": {
+                "oneOf": [
+                    { "type": "integer", "minimum": 0 },
+                    { "type": "string", "pattern": "^[0-9]+-[0-9]+$" },
+                ],
+            },
+            "description": "Uids helpers may run as, e.g. [65534, \"1000-1999\"]",
+        }),
+        "ambient_denylist" => string("Capabilities never raised ambient, e.g. \"cap_sys_admin\""),
+        "ambient_denylist_policy" => choice::<AmbientDenylistPolicy>(
+            "Whether helpers with a denylisted capability run without it ambient, or fail",
+        ),
+        "invocation_counter" => {
+            string("File keeping per-boot invocation counts for max_invocations")
+        }
+        "require_secure_boot" => boolean("Deny helpers while secure boot is off"),
+        "secure_boot_unknown" => choice::<SecureBootUnknown>(
+            "What require_secure_boot does when the secure boot state can't be read",
+        ),
+        "secure_boot_var" => string("The SecureBoot EFI variable"),
+        "require_config_hash" => boolean(
+            "Deny every helper unless huldufolk.confighash on the kernel command line matches",
+        ),
+        "kmsg_priority" => json!({
+            "type": "object",
+            "properties": {
+                "error": { "type": "integer", "minimum": 0, "maximum": 7 },
+                "warn": { "type": "integer", "minimum": 0, "maximum": 7 },
+                "debug": { "type": "integer", "minimum": 0, "maximum": 7 },
+                "audit": { "type": "integer", "minimum": 0, "maximum": 7 },
+            },
+            "additionalProperties": false,
+            "description": "Syslog priorities of kmsg messages, by category",
+        }),
+        "helpers" => json!({ "type": "array", "items": { "$ref": "#/$defs/helper" } }),
+        _ => return None,
+    })
+}
+
+fn helper_property(field: &str) -> Option<Value> {
+    let caps = "Capabilities, e.g. \"cap_sys_admin,cap_net_admin\"";
+    let seconds = "Timeout in seconds";
+    Some(match field {
+        "path" => string("The helper, as the kernel invokes it"),
+        "path_prefix" => string("Matches helpers whose path starts with this, instead of path"),
+        "path_suffix" => string("Matches helpers whose path ends with this, instead of path"),
+        "allowed_dirs" => strings("Matches any program directly inside these directories"),
+        "exec_path" => string("Program executed instead of path, with path as argv[0]"),
+        "argc" => count("Required number of arguments, including argv[0]"),
+        "args_template" => strings("Patterns for argv[1..]: literals, \"<any>\" or \"<ref:N>\""),
+        "argv_hashes" => json!({
+            "type": "array",
+            "items": { "type": "string", "pattern": "^[0-9a-fA-F]{64}$" },
+            "description": "SHA-256 digests of the only allowed argvs, from --argv-hash",
+        }),
+        "allowed_path_prefixes" => {
+            strings("Directories every argument starting with \"/\" has to be under")
+        }
+        "path_check_exempt_args" => json!({
+            "type": "array",
+            "items": { "type": "integer", "minimum": 1 },
+            "description": "Argument indices allowed_path_prefixes doesn't apply to",
+        }),
+        "capabilities" => string(caps),
+        "raise_ambient" => boolean("Raise the helper's capabilities ambient (true by default)"),
+        "require_capabilities" => string(caps),
+        "pre_exec_hook" => {
+            string("Program that has to succeed, run unprivileged, before the helper")
+        }
+        "capability_precheck" => {
+            string("Program that has to succeed, run with the helper's capabilities")
+        }
+        "capability_precheck_timeout" => count(seconds),
+        "probe_args" => strings("Arguments to probe the helper with, e.g. [\"--version\"]"),
+        "probe_timeout" => count(seconds),
+        "setup_timeout" => count(seconds),
+        "run_timeout" => count(seconds),
+        "mount_namespace" => boolean("Run the helper in a private mount namespace"),
+        "bind_mounts" => json!({
+            "type": "array",
+            "items": {
+                "type": "object",
+                "properties": {
+                    "source": { "type": "string" },
+                    "target": { "type": "string" },
+                    "read_only": { "type": "boolean" },
+                },
+                "required": ["source", "target"],
+                "additionalProperties": false,
+            },
+            "description": "Bind mounts set up in the helper's mount namespace",
+        }),
+        "verify_mounts" => {
+            boolean("Check the mount namespace against mountinfo before running the helper")
+        }
+        "capture_output" => boolean("Log the supervised helper's output lines"),
+        "output_log" => string("Where captured output goes instead of the audit log"),
+        "max_output_bytes" => count("Most bytes of captured output logged (65536 by default)"),
+        "reap_descendants" => {
+            boolean("Reap (and at run_timeout, kill) what the supervised helper leaves behind")
+        }
+        "capability_report" => {
+            boolean("Log which granted capabilities the supervised helper was seen holding")
+        }
+        "block_signals" => strings("Signals the helper starts with blocked, e.g. \"SIGTERM\""),
+        "reset_signals" => {
+            boolean("Start the helper with default signal dispositions and none blocked")
+        }
+        "keep_fds" => json!({
+            "type": "array",
+            "items": { "type": "integer", "minimum": 3, "maximum": MAX_KEPT_FD },
+            "description": "Descriptors above stderr the helper keeps",
+        }),
+        "close_fds_from" => json!({
+            "type": "integer",
+            "minimum": 3,
+            "maximum": MAX_KEPT_FD,
+            "description": "Lowest descriptor closed before the helper runs",
+        }),
+        "env_from_args" => json!({
+            "type": "object",
+            "additionalProperties": { "type": "integer", "minimum": 0 },
+            "description": "Environment variables set from arguments, by argv index",
+        }),
+        "match_env" => json!({
+            "type": "object",
+            "additionalProperties": {
+                "oneOf": [
+                    { "type": "string" },
+                    {
+                        "type": "object",
+                        "properties": { "regex": { "type": "string" } },
+                        "required": ["regex"],
+                        "additionalProperties": false,
+                    },
+                ],
+            },
+            "description": "Required values (or { regex = ... }) of the invocation's environment",
+        }),
+        "match_env_absent" => choice::<AbsentEnvPolicy>("What an absent match_env variable does"),
+        "workdir" => string("Working directory of the helper"),
+        "env_path" => string("PATH of the helper"),
+        "require_elf" => boolean("Refuse to run the helper unless it's an ELF binary"),
+        "verify_interp" => boolean("Only run ELF helpers with a trusted dynamic linker"),
+        "on_exec_failure" => choice::<OnExecFailure>(
+            "Whether a failed exec also reports what's wrong with the exec target",
+        ),
+        "missing_binary_policy" => choice::<MissingBinaryPolicy>(
+            "Whether a helper whose program doesn't exist still matches",
+        ),
+        "trusted_interpreters" => strings("Overrides the global trusted_interpreters"),
+        "allow_core" => boolean("Let the helper dump core"),
+        "core_limit" => count("Core dump size limit in bytes, with allow_core"),
+        "cpu_time_secs" => count("CPU time limit in seconds (RLIMIT_CPU)"),
+        "user" => string("User name or uid to run the helper as"),
+        "passwd_file" => string("Overrides the global passwd_file"),
+        "env" => json!({
+            "type": "object",
+            "additionalProperties": { "type": "string" },
+            "description": "Extra environment variables for the helper",
+        }),
+        "max_env_vars" => count("Most environment variables the helper gets (1024 by default)"),
+        "require_secure_boot" => boolean("Overrides the global require_secure_boot"),
+        "require_urandom_ready" => {
+            boolean("Deny the helper while the kernel RNG isn't initialized")
+        }
+        "urandom_wait_secs" => count("How long to wait for the kernel RNG before denying"),
+        "max_invocations" => count("How many times the helper may run per boot"),
+        "userns" => boolean("Run the helper in a new user namespace, mapped to an unprivileged id"),
+        "seccomp" => json!({
+            "oneOf": [{ "type": "string" }, { "type": "array", "items": { "type": "string" } }],
+            "description": "Allowed syscalls and @profiles",
+        }),
+        _ => return None,
+    })
+}
+
+fn properties(fields: &[&str], property: fn(&str) -> Option<Value>) -> Map<String, Value> {
+    fields
+        .iter()
+        .map(|field| {
+            let schema =
+                property(field).unwrap_or_else(|| panic!("no schema for config field {}", field));
+            (field.to_string(), schema)
+        })
+        .collect()
+}
+
+pub(crate) fn print() {
+    // The preamble is parsed from the same top-level table as the rest.
+    let mut top = properties(names::<Preamble>(), config_property);
+    top.extend(properties(names::<Config>(), config_property));
+    let schema = json!({
+        "$schema": "https://json-schema.org/draft/2020-12/schema",
+        "title": "usermode-helper config",
+        "type": "object",
+        "properties": top,
+        "required": ["helpers"],
+        "$defs": {
+            "helper": {
+                "type": "object",
+                "properties": properties(names::<Helper>(), helper_property),
+            },
+        },
+    });
+    println!("{:#}", schema);
+}
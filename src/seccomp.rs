@@ -0,0 +1,636 @@
+// Named seccomp profiles: allowlists of syscalls that helpers reference as
+// "@name" instead of each carrying its own long list. Profiles from the config
+// take precedence over the built-in ones below, and may reference each other.
+
+use std::collections::{BTreeMap, BTreeSet};
+
+const BUILTIN_PROFILES: &[(&str, &[&str])] = &[
+    // Enough for a dynamically linked program to start, do I/O on files it
+    // opens and exit.
+    (
+        "minimal",
+        &[
+            "execve",
+            "brk",
+            "mmap",
+            "munmap",
+            "mprotect",
+            "mremap",
+            "madvise",
+            "set_tid_address",
+            "set_robust_list",
+            "rseq",
+            "prlimit64",
+            "getrandom",
+            "futex",
+            "rt_sigaction",
+            "rt_sigprocmask",
+            "rt_sigreturn",
+            "sigaltstack",
+            "openat",
+            "read",
+            "pread64",
+            "readv",
+            "write",
+            "writev",
+            "close",
+            "close_range",
+            "fstat",
+            "newfstatat",
+            "statx",
+            "lseek",
+            "faccessat",
+            "faccessat2",
+            "readlinkat",
+            "fcntl",
+            "ioctl",
+            "getcwd",
+            "getpid",
+            "gettid",
+            "getuid",
+            "geteuid",
+            "getgid",
+            "getegid",
+            "uname",
+            "clock_gettime",
+            "clock_nanosleep",
+            "nanosleep",
+            "sched_getaffinity",
+            "sched_yield",
+            "tgkill",
+            "exit",
+            "exit_group",
+            #[cfg(target_arch = "x86_64")]
+            "arch_prctl",
+            #[cfg(target_arch = "x86_64")]
+            "access",
+            #[cfg(target_arch = "x86_64")]
+            "open",
+            #[cfg(target_arch = "x86_64")]
+            "stat",
+            #[cfg(target_arch = "x86_64")]
+            "lstat",
+            #[cfg(target_arch = "x86_64")]
+            "readlink",
+        ],
+    ),
+    // Creating, removing and modifying files and directories.
+    (
+        "filesystem",
+        &[
+            "@minimal",
+            "mkdirat",
+            "mknodat",
+            "unlinkat",
+            "renameat",
+            "renameat2",
+            "linkat",
+            "symlinkat",
+            "fchmod",
+            "fchmodat",
+            "fchown",
+            "fchownat",
+            "truncate",
+            "ftruncate",
+            "fallocate",
+            "fsync",
+            "fdatasync",
+            "sync",
+            "syncfs",
+            "flock",
+            "getdents64",
+            "utimensat",
+            "statfs",
+            "fstatfs",
+            "chdir",
+            "fchdir",
+            "umask",
+            "dup",
+            "dup3",
+            "pipe2",
+            #[cfg(target_arch = "x86_64")]
+            "mkdir",
+            #[cfg(target_arch = "x86_64")]
+            "rmdir",
+            #[cfg(target_arch = "x86_64")]
+            "unlink",
+            #[cfg(target_arch = "x86_64")]
+            "rename",
+            #[cfg(target_arch = "x86_64")]
+            "link",
+            #[cfg(target_arch = "x86_64")]
+            "symlink",
+            #[cfg(target_arch = "x86_64")]
+            "chmod",
+            #[cfg(target_arch = "x86_64")]
+            "chown",
+            #[cfg(target_arch = "x86_64")]
+            "lchown",
+            #[cfg(target_arch = "x86_64")]
+            "creat",
+            #[cfg(target_arch = "x86_64")]
+            "getdents",
+            #[cfg(target_arch = "x86_64")]
+            "dup2",
+            #[cfg(target_arch = "x86_64")]
+            "pipe",
+        ],
+    ),
+];
+
+// Expands a helper's seccomp setting (syscall names and "@profile" references)
+// into a sorted, deduplicated list of syscall names.
+pub fn resolve(
+    spec: &[String],
+    profiles: &BTreeMap<String, Vec<String>>,
+) -> Result<Vec<String>, String> {
+    if AUDIT_ARCH.is_none() {
+        return Err("seccomp isn't supported on this architecture".to_string());
+    }
+    let mut syscalls = BTreeSet::new();
+    let mut stack = Vec::new();
+    for entry in spec {
+        expand(entry, profiles, &mut stack, &mut syscalls)?;
+    }
+    Ok(syscalls.into_iter().collect())
+}
+
+fn expand(
+    entry: &str,
+    profiles: &BTreeMap<String, Vec<String>>,
+    stack: &mut Vec<String>,
+    syscalls: &mut BTreeSet<String>,
+) -> Result<(), String> {
+    let Some(name) = entry.strip_prefix('@') else {
+        if syscall_number(entry).is_none() {
+            return Err(format!("unknown syscall {}", entry));
+        }
+        syscalls.insert(entry.to_string());
+        return Ok(());
+    };
+
+    if stack.iter().any(|n| n == name) {
+        return Err(format!("seccomp profile @{} includes itself", name));
+    }
+    let entries: Vec<&str> = if let Some(list) = profiles.get(name) {
+        list.iter().map(String::as_str).collect()
+    } else if let Some((_, list)) = BUILTIN_PROFILES.iter().find(|(n, _)| *n == name) {
+        list.to_vec()
+    } else {
+        return Err(format!("unknown seccomp profile @{}", name));
+    };
+
+    stack.push(name.to_string());
+    for entry in entries {
+        expand(entry, profiles, stack, syscalls)?;
+    }
+    stack.pop();
+    Ok(())
+}
+
+fn syscall_number(name: &str) -> Option<libc::c_long> {
+    SYSCALLS
+        .iter()
+        .chain(ARCH_SYSCALLS)
+        .find(|(n, _)| *n == name)
+        .map(|(_, nr)| *nr)
+}
+
+// Not in libc; see <linux/audit.h>.
+#[cfg(target_arch = "x86_64")]
+const AUDIT_ARCH: Option<u32> = Some(0xc000_003e);
+#[cfg(target_arch = "aarch64")]
+const AUDIT_ARCH: Option<u32> = Some(0xc000_00b7);
+#[cfg(not(any(target_arch = "x86_64", target_arch = "aarch64")))]
+const AUDIT_ARCH: Option<u32> = None;
+
+// Offsets into struct seccomp_data.
+const NR_OFFSET: u32 = 0;
+const ARCH_OFFSET: u32 = 4;
+
+// Builds a filter allowing the given (already resolved) syscalls and failing
+// everything else with EPERM. execve is always allowed, since the filter is
+// installed before the helper is exec'd. Calls made under a foreign syscall
+// ABI kill the process outright.
+pub fn filter(syscalls: &[String]) -> Vec<libc::sock_filter> {
+    use libc::{BPF_ABS, BPF_JEQ, BPF_JMP, BPF_K, BPF_LD, BPF_RET, BPF_W};
+
+    let arch = AUDIT_ARCH.expect("seccomp profiles are rejected at load on this architecture");
+    let mut prog = vec![
+        stmt(BPF_LD | BPF_W | BPF_ABS, ARCH_OFFSET),
+        jump(BPF_JMP | BPF_JEQ | BPF_K, arch, 1, 0),
+        stmt(BPF_RET | BPF_K, libc::SECCOMP_RET_KILL_PROCESS),
+        stmt(BPF_LD | BPF_W | BPF_ABS, NR_OFFSET),
+    ];
+    let allowed = syscalls
+        .iter()
+        .filter_map(|name| syscall_number(name))
+        .chain([libc::SYS_execve]);
+    for nr in allowed {
+        prog.push(jump(BPF_JMP | BPF_JEQ | BPF_K, nr as u32, 0, 1));
+        prog.push(stmt(BPF_RET | BPF_K, libc::SECCOMP_RET_ALLOW));
+    }
+    prog.push(stmt(
+        BPF_RET | BPF_K,
+        libc::SECCOMP_RET_ERRNO | libc::EPERM as u32,
+    ));
+    prog
+}
+
+fn stmt(code: u32, k: u32) -> libc::sock_filter {
+    jump(code, k, 0, 0)
+}
+
+fn jump(code: u32, k: u32, jt: u8, jf: u8) -> libc::sock_filter {
+    libc::sock_filter {
+        code: code as u16,
+        jt,
+        jf,
+        k,
+    }
+}
+
+// Runs in the child between fork and exec, so it only makes raw syscalls.
+pub fn install(filter: &[libc::sock_filter]) -> std::io::Result<()> {
+    let prog = libc::sock_fprog {
+        len: filter.len() as u16,
+        filter: filter.as_ptr() as *mut libc::sock_filter,
+    };
+    unsafe {
+        if libc::prctl(libc::PR_SET_NO_NEW_PRIVS, 1, 0, 0, 0) != 0
+            || libc::prctl(
+                libc::PR_SET_SECCOMP,
+                libc::SECCOMP_MODE_FILTER,
+                &prog as *const libc::sock_fprog,
+            ) != 0
+        {
+            return Err(std::io::Error::last_os_error());
+        }
+    }
+    Ok(())
+}
+
+const SYSCALLS: &[(&str, libc::c_long)] = &[
+    ("read", libc::SYS_read),
+    ("write", libc::SYS_write),
+    ("close", libc::SYS_close),
+    ("fstat", libc::SYS_fstat),
+    ("lseek", libc::SYS_lseek),
+    ("mmap", libc::SYS_mmap),
+    ("mprotect", libc::SYS_mprotect),
+    ("munmap", libc::SYS_munmap),
+    ("brk", libc::SYS_brk),
+    ("rt_sigaction", libc::SYS_rt_sigaction),
+    ("rt_sigprocmask", libc::SYS_rt_sigprocmask),
+    ("rt_sigreturn", libc::SYS_rt_sigreturn),
+    ("ioctl", libc::SYS_ioctl),
+    ("pread64", libc::SYS_pread64),
+    ("pwrite64", libc::SYS_pwrite64),
+    ("readv", libc::SYS_readv),
+    ("writev", libc::SYS_writev),
+    ("sched_yield", libc::SYS_sched_yield),
+    ("mremap", libc::SYS_mremap),
+    ("msync", libc::SYS_msync),
+    ("mincore", libc::SYS_mincore),
+    ("madvise", libc::SYS_madvise),
+    ("dup", libc::SYS_dup),
+    ("dup3", libc::SYS_dup3),
+    ("nanosleep", libc::SYS_nanosleep),
+    ("getitimer", libc::SYS_getitimer),
+    ("setitimer", libc::SYS_setitimer),
+    ("getpid", libc::SYS_getpid),
+    ("sendfile", libc::SYS_sendfile),
+    ("socket", libc::SYS_socket),
+    ("connect", libc::SYS_connect),
+    ("accept", libc::SYS_accept),
+    ("sendto", libc::SYS_sendto),
+    ("recvfrom", libc::SYS_recvfrom),
+    ("sendmsg", libc::SYS_sendmsg),
+    ("recvmsg", libc::SYS_recvmsg),
+    ("shutdown", libc::SYS_shutdown),
+    ("bind", libc::SYS_bind),
+    ("listen", libc::SYS_listen),
+    ("getsockname", libc::SYS_getsockname),
+    ("getpeername", libc::SYS_getpeername),
+    ("socketpair", libc::SYS_socketpair),
+    ("setsockopt", libc::SYS_setsockopt),
+    ("getsockopt", libc::SYS_getsockopt),
+    ("clone", libc::SYS_clone),
+    ("execve", libc::SYS_execve),
+    ("exit", libc::SYS_exit),
+    ("wait4", libc::SYS_wait4),
+    ("kill", libc::SYS_kill),
+    ("uname", libc::SYS_uname),
+    ("fcntl", libc::SYS_fcntl),
+    ("flock", libc::SYS_flock),
+    ("fsync", libc::SYS_fsync),
+    ("fdatasync", libc::SYS_fdatasync),
+    ("truncate", libc::SYS_truncate),
+    ("ftruncate", libc::SYS_ftruncate),
+    ("getcwd", libc::SYS_getcwd),
+    ("chdir", libc::SYS_chdir),
+    ("fchdir", libc::SYS_fchdir),
+    ("fchmod", libc::SYS_fchmod),
+    ("fchown", libc::SYS_fchown),
+    ("umask", libc::SYS_umask),
+    ("gettimeofday", libc::SYS_gettimeofday),
+    ("getrlimit", libc::SYS_getrlimit),
+    ("getrusage", libc::SYS_getrusage),
+    ("sysinfo", libc::SYS_sysinfo),
+    ("times", libc::SYS_times),
+    ("ptrace", libc::SYS_ptrace),
+    ("getuid", libc::SYS_getuid),
+    ("syslog", libc::SYS_syslog),
+    ("getgid", libc::SYS_getgid),
+    ("setuid", libc::SYS_setuid),
+    ("setgid", libc::SYS_setgid),
+    ("geteuid", libc::SYS_geteuid),
+    ("getegid", libc::SYS_getegid),
+    ("setpgid", libc::SYS_setpgid),
+    ("getppid", libc::SYS_getppid),
+    ("setsid", libc::SYS_setsid),
+    ("setreuid", libc::SYS_setreuid),
+    ("setregid", libc::SYS_setregid),
+    ("getgroups", libc::SYS_getgroups),
+    ("setgroups", libc::SYS_setgroups),
+    ("setresuid", libc::SYS_setresuid),
+    ("getresuid", libc::SYS_getresuid),
+    ("setresgid", libc::SYS_setresgid),
+    ("getresgid", libc::SYS_getresgid),
+    ("getpgid", libc::SYS_getpgid),
+    ("setfsuid", libc::SYS_setfsuid),
+    ("setfsgid", libc::SYS_setfsgid),
+    ("getsid", libc::SYS_getsid),
+    ("capget", libc::SYS_capget),
+    ("capset", libc::SYS_capset),
+    ("rt_sigpending", libc::SYS_rt_sigpending),
+    ("rt_sigtimedwait", libc::SYS_rt_sigtimedwait),
+    ("rt_sigqueueinfo", libc::SYS_rt_sigqueueinfo),
+    ("rt_sigsuspend", libc::SYS_rt_sigsuspend),
+    ("sigaltstack", libc::SYS_sigaltstack),
+    ("personality", libc::SYS_personality),
+    ("statfs", libc::SYS_statfs),
+    ("fstatfs", libc::SYS_fstatfs),
+    ("getpriority", libc::SYS_getpriority),
+    ("setpriority", libc::SYS_setpriority),
+    ("sched_setparam", libc::SYS_sched_setparam),
+    ("sched_getparam", libc::SYS_sched_getparam),
+    ("sched_setscheduler", libc::SYS_sched_setscheduler),
+    ("sched_getscheduler", libc::SYS_sched_getscheduler),
+    ("sched_get_priority_max", libc::SYS_sched_get_priority_max),
+    ("sched_get_priority_min", libc::SYS_sched_get_priority_min),
+    ("mlock", libc::SYS_mlock),
+    ("munlock", libc::SYS_munlock),
+    ("mlockall", libc::SYS_mlockall),
+    ("munlockall", libc::SYS_munlockall),
+    ("vhangup", libc::SYS_vhangup),
+    ("pivot_root", libc::SYS_pivot_root),
+    ("prctl", libc::SYS_prctl),
+    ("adjtimex", libc::SYS_adjtimex),
+    ("setrlimit", libc::SYS_setrlimit),
+    ("chroot", libc::SYS_chroot),
+    ("sync", libc::SYS_sync),
+    ("acct", libc::SYS_acct),
+    ("settimeofday", libc::SYS_settimeofday),
+    ("mount", libc::SYS_mount),
+    ("umount2", libc::SYS_umount2),
+    ("swapon", libc::SYS_swapon),
+    ("swapoff", libc::SYS_swapoff),
+    ("reboot", libc::SYS_reboot),
+    ("sethostname", libc::SYS_sethostname),
+    ("setdomainname", libc::SYS_setdomainname),
+    ("init_module", libc::SYS_init_module),
+    ("delete_module", libc::SYS_delete_module),
+    ("quotactl", libc::SYS_quotactl),
+    ("gettid", libc::SYS_gettid),
+    ("readahead", libc::SYS_readahead),
+    ("setxattr", libc::SYS_setxattr),
+    ("lsetxattr", libc::SYS_lsetxattr),
+    ("fsetxattr", libc::SYS_fsetxattr),
+    ("getxattr", libc::SYS_getxattr),
+    ("lgetxattr", libc::SYS_lgetxattr),
+    ("fgetxattr", libc::SYS_fgetxattr),
+    ("listxattr", libc::SYS_listxattr),
+    ("llistxattr", libc::SYS_llistxattr),
+    ("flistxattr", libc::SYS_flistxattr),
+    ("removexattr", libc::SYS_removexattr),
+    ("lremovexattr", libc::SYS_lremovexattr),
+    ("fremovexattr", libc::SYS_fremovexattr),
+    ("tkill", libc::SYS_tkill),
+    ("futex", libc::SYS_futex),
+    ("sched_setaffinity", libc::SYS_sched_setaffinity),
+    ("sched_getaffinity", libc::SYS_sched_getaffinity),
+    ("io_setup", libc::SYS_io_setup),
+    ("io_destroy", libc::SYS_io_destroy),
+    ("io_getevents", libc::SYS_io_getevents),
+    ("io_submit", libc::SYS_io_submit),
+    ("io_cancel", libc::SYS_io_cancel),
+    ("remap_file_pages", libc::SYS_remap_file_pages),
+    ("getdents64", libc::SYS_getdents64),
+    ("set_tid_address", libc::SYS_set_tid_address),
+    ("restart_syscall", libc::SYS_restart_syscall),
+    ("fadvise64", libc::SYS_fadvise64),
+    ("timer_create", libc::SYS_timer_create),
+    ("timer_settime", libc::SYS_timer_settime),
+    ("timer_gettime", libc::SYS_timer_gettime),
+    ("timer_getoverrun", libc::SYS_timer_getoverrun),
+    ("timer_delete", libc::SYS_timer_delete),
+    ("clock_settime", libc::SYS_clock_settime),
+    ("clock_gettime", libc::SYS_clock_gettime),
+    ("clock_getres", libc::SYS_clock_getres),
+    ("clock_nanosleep", libc::SYS_clock_nanosleep),
+    ("exit_group", libc::SYS_exit_group),
+    ("epoll_ctl", libc::SYS_epoll_ctl),
+    ("tgkill", libc::SYS_tgkill),
+    ("mbind", libc::SYS_mbind),
+    ("set_mempolicy", libc::SYS_set_mempolicy),
+    ("get_mempolicy", libc::SYS_get_mempolicy),
+    ("mq_open", libc::SYS_mq_open),
+    ("mq_unlink", libc::SYS_mq_unlink),
+    ("kexec_load", libc::SYS_kexec_load),
+    ("waitid", libc::SYS_waitid),
+    ("add_key", libc::SYS_add_key),
+    ("request_key", libc::SYS_request_key),
+    ("keyctl", libc::SYS_keyctl),
+    ("ioprio_set", libc::SYS_ioprio_set),
+    ("ioprio_get", libc::SYS_ioprio_get),
+    ("inotify_add_watch", libc::SYS_inotify_add_watch),
+    ("inotify_rm_watch", libc::SYS_inotify_rm_watch),
+    ("openat", libc::SYS_openat),
+    ("mkdirat", libc::SYS_mkdirat),
+    ("mknodat", libc::SYS_mknodat),
+    ("fchownat", libc::SYS_fchownat),
+    ("newfstatat", libc::SYS_newfstatat),
+    ("unlinkat", libc::SYS_unlinkat),
+    ("renameat", libc::SYS_renameat),
+    ("linkat", libc::SYS_linkat),
+    ("symlinkat", libc::SYS_symlinkat),
+    ("readlinkat", libc::SYS_readlinkat),
+    ("fchmodat", libc::SYS_fchmodat),
+    ("faccessat", libc::SYS_faccessat),
+    ("pselect6", libc::SYS_pselect6),
+    ("ppoll", libc::SYS_ppoll),
+    ("unshare", libc::SYS_unshare),
+    ("set_robust_list", libc::SYS_set_robust_list),
+    ("get_robust_list", libc::SYS_get_robust_list),
+    ("splice", libc::SYS_splice),
+    ("tee", libc::SYS_tee),
+    ("sync_file_range", libc::SYS_sync_file_range),
+    ("vmsplice", libc::SYS_vmsplice),
+    ("move_pages", libc::SYS_move_pages),
+    ("utimensat", libc::SYS_utimensat),
+    ("epoll_pwait", libc::SYS_epoll_pwait),
+    ("timerfd_create", libc::SYS_timerfd_create),
+    ("fallocate", libc::SYS_fallocate),
+    ("timerfd_settime", libc::SYS_timerfd_settime),
+    ("timerfd_gettime", libc::SYS_timerfd_gettime),
+    ("accept4", libc::SYS_accept4),
+    ("eventfd2", libc::SYS_eventfd2),
+    ("epoll_create1", libc::SYS_epoll_create1),
+    ("pipe2", libc::SYS_pipe2),
+    ("inotify_init1", libc::SYS_inotify_init1),
+    ("preadv", libc::SYS_preadv),
+    ("pwritev", libc::SYS_pwritev),
+    ("rt_tgsigqueueinfo", libc::SYS_rt_tgsigqueueinfo),
+    ("perf_event_open", libc::SYS_perf_event_open),
+    ("recvmmsg", libc::SYS_recvmmsg),
+    ("fanotify_init", libc::SYS_fanotify_init),
+    ("fanotify_mark", libc::SYS_fanotify_mark),
+    ("prlimit64", libc::SYS_prlimit64),
+    ("name_to_handle_at", libc::SYS_name_to_handle_at),
+    ("open_by_handle_at", libc::SYS_open_by_handle_at),
+    ("clock_adjtime", libc::SYS_clock_adjtime),
+    ("syncfs", libc::SYS_syncfs),
+    ("sendmmsg", libc::SYS_sendmmsg),
+    ("setns", libc::SYS_setns),
+    ("getcpu", libc::SYS_getcpu),
+    ("process_vm_readv", libc::SYS_process_vm_readv),
+    ("process_vm_writev", libc::SYS_process_vm_writev),
+    ("kcmp", libc::SYS_kcmp),
+    ("finit_module", libc::SYS_finit_module),
+    ("sched_setattr", libc::SYS_sched_setattr),
+    ("sched_getattr", libc::SYS_sched_getattr),
+    ("renameat2", libc::SYS_renameat2),
+    ("seccomp", libc::SYS_seccomp),
+    ("getrandom", libc::SYS_getrandom),
+    ("memfd_create", libc::SYS_memfd_create),
+    ("bpf", libc::SYS_bpf),
+    ("execveat", libc::SYS_execveat),
+    ("membarrier", libc::SYS_membarrier),
+    ("mlock2", libc::SYS_mlock2),
+    ("copy_file_range", libc::SYS_copy_file_range),
+    ("preadv2", libc::SYS_preadv2),
+    ("pwritev2", libc::SYS_pwritev2),
+    ("statx", libc::SYS_statx),
+    ("rseq", libc::SYS_rseq),
+    ("pidfd_send_signal", libc::SYS_pidfd_send_signal),
+    ("pidfd_open", libc::SYS_pidfd_open),
+    ("clone3", libc::SYS_clone3),
+    ("close_range", libc::SYS_close_range),
+    ("openat2", libc::SYS_openat2),
+    ("faccessat2", libc::SYS_faccessat2),
+];
+
+// Legacy syscalls that newer architectures only have *at() variants of.
+#[cfg(target_arch = "x86_64")]
+const ARCH_SYSCALLS: &[(&str, libc::c_long)] = &[
+    ("open", libc::SYS_open),
+    ("stat", libc::SYS_stat),
+    ("lstat", libc::SYS_lstat),
+    ("poll", libc::SYS_poll),
+    ("access", libc::SYS_access),
+    ("pipe", libc::SYS_pipe),
+    ("select", libc::SYS_select),
+    ("dup2", libc::SYS_dup2),
+    ("pause", libc::SYS_pause),
+    ("alarm", libc::SYS_alarm),
+    ("fork", libc::SYS_fork),
+    ("vfork", libc::SYS_vfork),
+    ("getdents", libc::SYS_getdents),
+    ("rename", libc::SYS_rename),
+    ("mkdir", libc::SYS_mkdir),
+    ("rmdir", libc::SYS_rmdir),
+    ("creat", libc::SYS_creat),
+    ("link", libc::SYS_link),
+    ("unlink", libc::SYS_unlink),
+    ("symlink", libc::SYS_symlink),
+    ("readlink", libc::SYS_readlink),
+    ("chmod", libc::SYS_chmod),
+    ("chown", libc::SYS_chown),
+    ("lchown", libc::SYS_lchown),
+    ("mknod", libc::SYS_mknod),
+    ("utime", libc::SYS_utime),
+    ("utimes", libc::SYS_utimes),
+    ("futimesat", libc::SYS_futimesat),
+    ("arch_prctl", libc::SYS_arch_prctl),
+    ("time", libc::SYS_time),
+    ("epoll_create", libc::SYS_epoll_create),
+    ("epoll_wait", libc::SYS_epoll_wait),
+    ("inotify_init", libc::SYS_inotify_init),
+    ("signalfd", libc::SYS_signalfd),
+    ("eventfd", libc::SYS_eventfd),
+    ("getpgrp", libc::SYS_getpgrp),
+];
+#[cfg(not(target_arch = "x86_64"))]
+const ARCH_SYSCALLS: &[(&str, libc::c_long)] = &[];
+
+#[cfg(all(test, any(target_arch = "x86_64", target_arch = "aarch64")))]
+mod tests {
+    use super::*;
+
+    fn profiles(entries: &[(&str, &[&str])]) -> BTreeMap<String, Vec<String>> {
+        entries
+            .iter()
+            .map(|(name, list)| {
+                (
+                    name.to_string(),
+                    list.iter().map(|s| s.to_string()).collect(),
+                )
+            })
+            .collect()
+    }
+
+    fn spec(entries: &[&str]) -> Vec<String> {
+        entries.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn minimal_profile_is_enough_to_run_a_program() {
+        let syscalls = resolve(&spec(&["@minimal"]), &BTreeMap::new()).unwrap();
+        for syscall in ["execve", "mmap", "read", "write", "exit_group"] {
+            assert!(syscalls.iter().any(|s| s == syscall), "{} missing", syscall);
+        }
+        assert!(!syscalls.iter().any(|s| s == "mount"));
+    }
+
+    #[test]
+    fn references_resolve_through_config_and_builtin_profiles() {
+        let profiles = profiles(&[("modprobe", &["@minimal", "init_module"])]);
+        let syscalls = resolve(&spec(&["@modprobe", "delete_module"]), &profiles).unwrap();
+        for syscall in ["init_module", "delete_module", "exit_group"] {
+            assert!(syscalls.iter().any(|s| s == syscall), "{} missing", syscall);
+        }
+        let mut sorted = syscalls.clone();
+        sorted.sort();
+        sorted.dedup();
+        assert_eq!(syscalls, sorted);
+    }
+
+    #[test]
+    fn config_profiles_take_precedence() {
+        let profiles = profiles(&[("minimal", &["read"])]);
+        let syscalls = resolve(&spec(&["@minimal"]), &profiles).unwrap();
+        assert_eq!(syscalls, ["read"]);
+    }
+
+    #[test]
+    fn bad_references_are_rejected() {
+        let profiles = profiles(&[("a", &["@b"]), ("b", &["@a"])]);
+        let err = resolve(&spec(&["@a"]), &profiles).unwrap_err();
+        assert_eq!(err, "seccomp profile @a includes itself");
+        let err = resolve(&spec(&["@nonexistent"]), &profiles).unwrap_err();
+        assert_eq!(err, "unknown seccomp profile @nonexistent");
+        let err = resolve(&spec(&["not_a_syscall"]), &profiles).unwrap_err();
+        assert_eq!(err, "unknown syscall not_a_syscall");
+    }
+}
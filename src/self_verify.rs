@@ -0,0 +1,95 @@
+// Self-integrity, in builds with the self-verify feature: before every
+// invocation, before even the config is loaded, the SHA-256 of our own binary
+// (/proc/self/exe, as sha256sum prints it) must match
+// huldufolk.selfhash=<sha256> on the kernel command line. Every helper is
+// denied if it doesn't, if the token is missing or malformed, or if either the
+// command line or the binary can't be read.
+//
+// The expected digest can't be baked in at build time, since it would then be
+// part of what it's the digest of. And a binary modified to skip this check
+// isn't caught by it, so this is about binaries replaced or corrupted on disk;
+// measuring the binary before it runs is up to the boot chain.
+
+use crate::config_hash;
+use crate::exit::Exit;
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::io::Write;
+
+const TOKEN: &str = "huldufolk.selfhash";
+const EXE: &str = "/proc/self/exe";
+
+fn sha256(data: &[u8]) -> String {
+    Sha256::digest(data)
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+fn compare(expected: Option<&str>, actual: &str) -> Result<(), String> {
+    match expected {
+        Some(expected) if expected == actual => Ok(()),
+        Some(expected) => Err(format!(
+            "binary hash {} doesn't match {}={}",
+            actual, TOKEN, expected
+        )),
+        None => Err(format!("the kernel command line has no {}", TOKEN)),
+    }
+}
+
+pub(crate) fn check() {
+    let verdict = config_hash::cmdline()
+        .map_err(|e| format!("couldn't read {}: {}", config_hash::CMDLINE_PATH, e))
+        .and_then(|cmdline| config_hash::parse_token(&cmdline, TOKEN))
+        .and_then(|expected| {
+            let exe = fs::read(EXE).map_err(|e| format!("couldn't read {}: {}", EXE, e))?;
+            compare(expected.as_deref(), &sha256(&exe))
+        });
+    match verdict {
+        Ok(()) => debug!("binary hash matches the kernel command line"),
+        Err(e) => fail!(Exit::Integrity, "{}, denying all helpers", e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ABC_SHA256: &str = "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad";
+
+    #[test]
+    fn self_hash_is_plain_sha256() {
+        assert_eq!(sha256(b"abc"), ABC_SHA256);
+    }
+
+    #[test]
+    fn self_hash_matches() {
+        assert_eq!(compare(Some(ABC_SHA256), ABC_SHA256), Ok(()));
+    }
+
+    #[test]
+    fn self_hash_mismatch_is_an_error() {
+        let other = sha256(b"abd");
+        let err = compare(Some(&other), ABC_SHA256).unwrap_err();
+        assert!(err.contains("doesn't match huldufolk.selfhash"), "{}", err);
+    }
+
+    #[test]
+    fn missing_self_hash_is_an_error() {
+        assert!(compare(None, ABC_SHA256).is_err());
+    }
+
+    #[test]
+    fn self_hash_token_is_parsed_like_the_config_hash() {
+        let upper = ABC_SHA256.to_ascii_uppercase();
+        let cmdline = format!(
+            "ro huldufolk.selfhash=\"{}\" -- huldufolk.selfhash=x",
+            upper
+        );
+        assert_eq!(
+            config_hash::parse_token(&cmdline, "huldufolk.selfhash"),
+            Ok(Some(ABC_SHA256.to_string()))
+        );
+        assert_eq!(config_hash::parse(&cmdline), Ok(None));
+    }
+}
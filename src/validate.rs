@@ -0,0 +1,82 @@
+// Interactive: usermode-helper --validate <config> [--plan]
+//
+// Loads the config the way invocations do, failing (with exit code 5) if it's
+// invalid, and prints a summary. With --plan, it prints each helper's complete
+// launch plan as JSON instead, for reviewing a config with nothing implicit:
+// the helper with drop-ins merged, defaults applied and seccomp profiles
+// expanded, and the capability sets it would run with.
+
+use crate::exit::Exit;
+use crate::{Config, Helper, cap_list, priv_plan};
+use serde::Serialize;
+use std::ffi::OsString;
+use std::io::Write;
+
+#[derive(Serialize)]
+struct Privileges {
+    // Effective, Inheritable and Permitted alike.
+    caps: Vec<String>,
+    ambient: Vec<String>,
+    // Left out of the ambient set by ambient_denylist.
+    stripped: Vec<String>,
+    drop_setpcap: bool,
+}
+
+#[derive(Serialize)]
+struct LaunchPlan<'a> {
+    helper: String,
+    // No capabilities configured: run as root, the helper keeps all of them.
+    unrestricted: bool,
+    privileges: Option<Privileges>,
+    // Set if ambient_denylist makes every invocation of the helper fail.
+    refused_ambient: Option<String>,
+    config: &'a Helper,
+}
+
+fn launch_plan<'a>(config: &Config, helper: &'a Helper) -> LaunchPlan<'a> {
+    let mut plan = LaunchPlan {
+        helper: helper.name(),
+        unrestricted: helper.capabilities.is_none(),
+        privileges: None,
+        refused_ambient: None,
+        config: helper,
+    };
+    if let Some(caps) = &helper.capabilities {
+        match priv_plan(caps, &config.ambient_limit(helper)) {
+            Ok(privs) => {
+                plan.privileges = Some(Privileges {
+                    caps: cap_list(&privs.caps),
+                    ambient: cap_list(&privs.ambient),
+                    stripped: cap_list(&privs.stripped),
+                    drop_setpcap: privs.drop_setpcap,
+                })
+            }
+            Err(cap) => plan.refused_ambient = Some(cap.to_string().to_lowercase()),
+        }
+    }
+    plan
+}
+
+pub(crate) fn run(args: &[OsString]) {
+    let (config_path, show_plan) = match args {
+        [config_path] => (config_path, false),
+        [config_path, flag] if flag == "--plan" => (config_path, true),
+        _ => fail!(Exit::Usage, "usage: --validate <config> [--plan]"),
+    };
+    let config_path = config_path
+        .to_str()
+        .unwrap_or_else(|| fail!(Exit::Usage, "bad config path {:?}", config_path));
+    let config = Config::load(config_path);
+    if !show_plan {
+        println!("{}: {} helpers, valid", config_path, config.helpers.len());
+        return;
+    }
+    let plans: Vec<LaunchPlan> = config
+        .helpers
+        .iter()
+        .map(|helper| launch_plan(&config, helper))
+        .collect();
+    let json = serde_json::to_string_pretty(&plans)
+        .unwrap_or_else(|e| fail!(Exit::Config, "couldn't serialize launch plans: {}", e));
+    println!("{}", json);
+}
@@ -0,0 +1,98 @@
+load helpers
+
+function setup() {
+    make_tempdir
+}
+
+function teardown() {
+    cleanup
+}
+
+@test "args_template matches literals and <any>" {
+    cat <<EOF > "${TEMP_DIR}/usermode-helper.conf"
+[[helpers]]
+path = "/bin/true"
+args_template = ["-q", "<any>"]
+EOF
+    usermode-helper /bin/true -q foo
+    usermode-helper-deny /bin/true -v foo
+    usermode-helper-deny /bin/true -q
+    usermode-helper-deny /bin/true -q foo bar
+}
+
+@test "args_template back-reference accepts consistent arguments" {
+    cat <<EOF > "${TEMP_DIR}/usermode-helper.conf"
+[[helpers]]
+path = "/bin/true"
+args_template = ["<any>", "--device", "<ref:1>"]
+EOF
+    usermode-helper /bin/true /dev/sda1 --device /dev/sda1
+}
+
+@test "args_template back-reference rejects inconsistent arguments" {
+    cat <<EOF > "${TEMP_DIR}/usermode-helper.conf"
+[[helpers]]
+path = "/bin/true"
+args_template = ["<any>", "--device", "<ref:1>"]
+EOF
+    usermode-helper-deny /bin/true /dev/sda1 --device /dev/sdb1
+}
+
+@test "args_template can refer to argv[0]" {
+    cat <<EOF > "${TEMP_DIR}/usermode-helper.conf"
+[[helpers]]
+path = "/bin/true"
+args_template = ["<ref:0>"]
+EOF
+    usermode-helper /bin/true /bin/true
+    usermode-helper-deny /bin/true /bin/false
+}
+
+@test "args_template forward references are rejected" {
+    cat <<EOF > "${TEMP_DIR}/usermode-helper.conf"
+[[helpers]]
+path = "/bin/true"
+args_template = ["<ref:2>", "<any>"]
+EOF
+    usermode-helper-fail 5 /bin/true a a
+    echo "$output" | grep "<ref:2> refers forward from argument 1"
+
+    cat <<EOF > "${TEMP_DIR}/usermode-helper.conf"
+[[helpers]]
+path = "/bin/true"
+args_template = ["<ref:1>"]
+EOF
+    usermode-helper-fail 5 /bin/true a
+    echo "$output" | grep "<ref:1> refers forward from argument 1"
+}
+
+@test "args_template out-of-range references are rejected" {
+    cat <<EOF > "${TEMP_DIR}/usermode-helper.conf"
+[[helpers]]
+path = "/bin/true"
+args_template = ["<any>", "<ref:5>"]
+EOF
+    usermode-helper-fail 5 /bin/true a a
+    echo "$output" | grep "<ref:5> is out of range"
+}
+
+@test "args_template rejects unknown placeholders" {
+    cat <<EOF > "${TEMP_DIR}/usermode-helper.conf"
+[[helpers]]
+path = "/bin/true"
+args_template = ["<ref:x>"]
+EOF
+    usermode-helper-fail 5 /bin/true a
+    echo "$output" | grep "bad argument placeholder <ref:x>"
+}
+
+@test "args_template must agree with argc" {
+    cat <<EOF > "${TEMP_DIR}/usermode-helper.conf"
+[[helpers]]
+path = "/bin/true"
+argc = 2
+args_template = ["<any>", "<any>"]
+EOF
+    usermode-helper-fail 5 /bin/true a b
+    echo "$output" | grep "args_template has 3 arguments but argc is 2"
+}
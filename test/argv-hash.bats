@@ -0,0 +1,54 @@
+load helpers
+
+function setup() {
+    make_tempdir
+    : "${UMH_BIN:=${ROOT_DIR}/target/debug/usermode-helper}"
+}
+
+function teardown() {
+    cleanup
+}
+
+function argv-hash() {
+    run "${UMH_BIN}" --argv-hash "$@"
+    echo "$output"
+    [ "$status" -eq 0 ]
+}
+
+@test "--argv-hash digests length-prefixed arguments" {
+    argv-hash /bin/true a b
+    expected="$(printf '\0\0\0\0\0\0\0\x09/bin/true\0\0\0\0\0\0\0\x01a\0\0\0\0\0\0\0\x01b' | sha256sum | cut -d' ' -f1)"
+    [ "$output" = "${expected}" ]
+
+    # Moving bytes between arguments changes the digest.
+    argv-hash /bin/true ab ""
+    [ "$output" != "${expected}" ]
+}
+
+@test "argv_hashes only allows the listed argvs" {
+    argv-hash /bin/true one two
+    one_two="$output"
+    argv-hash /bin/true three
+    # Digests are accepted in either case.
+    three="$(echo "$output" | tr a-f A-F)"
+    cat <<EOF > "${TEMP_DIR}/usermode-helper.conf"
+[[helpers]]
+path = "/bin/true"
+argv_hashes = ["${one_two}", "${three}"]
+EOF
+    usermode-helper /bin/true one two
+    usermode-helper /bin/true three
+    usermode-helper-deny /bin/true one
+    usermode-helper-deny /bin/true one two three
+    usermode-helper-deny /bin/true
+}
+
+@test "argv_hashes entries must be SHA-256 digests" {
+    cat <<EOF > "${TEMP_DIR}/usermode-helper.conf"
+[[helpers]]
+path = "/bin/true"
+argv_hashes = ["abc"]
+EOF
+    usermode-helper-fail 5 /bin/true
+    echo "$output" | grep "argv_hashes entry abc isn't a SHA-256 digest"
+}
@@ -0,0 +1,79 @@
+load helpers
+
+function setup() {
+    make_tempdir
+    : "${UMH_BIN:=${ROOT_DIR}/target/debug/usermode-helper}"
+    run "${UMH_BIN}" --as
+    if [ "$status" -ne 11 ]; then
+        skip "built without the test-mode feature"
+    fi
+    # An earlier pattern helper that would match /sbin/modprobe first.
+    cat <<EOF > "${TEMP_DIR}/usermode-helper.conf"
+[[helpers]]
+path_prefix = "/sbin/"
+capabilities = "cap_chown"
+
+[[helpers]]
+path = "/sbin/modprobe"
+capabilities = "cap_sys_module"
+args_template = ["-q", "--", "<any>"]
+
+[[helpers]]
+path = "/bin/true"
+capabilities = "cap_net_admin"
+EOF
+}
+
+function teardown() {
+    cleanup
+}
+
+function as-helper() {
+    pushd "${TEMP_DIR}"
+    run unshare -Uur bash -c "HULDUFOLK_DEBUG=1 \"${UMH_BIN}\" --as $*"
+    popd
+    echo "$output"
+}
+
+@test "--as --plan selects the helper configured with the path" {
+    as-helper /sbin/modprobe --plan -- -q -- ext4
+    [ "$status" -eq 0 ]
+    echo "$output" | grep -x "helper: /sbin/modprobe"
+    echo "$output" | grep -x "caps: cap_sys_module"
+    echo "$output" | grep -x "ambient: cap_sys_module"
+    echo "$output" | grep -x "stripped: none"
+    echo "$output" | grep -x "drop_setpcap: true"
+}
+
+@test "--as --plan applies ambient_denylist" {
+    sed -i '1i ambient_denylist = "cap_sys_module"' "${TEMP_DIR}/usermode-helper.conf"
+    as-helper /sbin/modprobe --plan -- -q -- ext4
+    [ "$status" -eq 0 ]
+    echo "$output" | grep -x "caps: cap_sys_module"
+    echo "$output" | grep -x "ambient: none"
+    echo "$output" | grep -x "stripped: cap_sys_module"
+}
+
+@test "--as runs the helper with its restrictions" {
+    as-helper /bin/true -- anything
+    [ "$status" -eq 0 ]
+    echo "$output" | grep -x "Ambient: {CAP_NET_ADMIN}"
+}
+
+@test "--as still checks the helper's other rules" {
+    as-helper /sbin/modprobe --plan -- -v ext4
+    [ "$status" -eq 1 ]
+    echo "$output" | grep "helper /sbin/modprobe doesn't allow"
+}
+
+@test "--as only selects helpers by their configured path" {
+    as-helper /sbin/insmod --plan --
+    [ "$status" -eq 11 ]
+    echo "$output" | grep "no helper is configured with path \"/sbin/insmod\""
+}
+
+@test "--as needs a -- before the helper's arguments" {
+    as-helper /bin/true anything
+    [ "$status" -eq 11 ]
+    echo "$output" | grep "usage: --as <path> \[--plan\] -- <args...>"
+}
@@ -0,0 +1,172 @@
+load helpers
+
+function setup() {
+    make_tempdir
+    AUDIT="$(realpath "${TEMP_DIR}")/audit.log"
+    cat <<EOF > "${TEMP_DIR}/usermode-helper.conf"
+audit_log = "${AUDIT}"
+
+[[helpers]]
+path = "/bin/true"
+EOF
+}
+
+function teardown() {
+    cleanup
+}
+
+@test "allowed invocations are audited" {
+    usermode-helper /bin/true one
+    grep '"decision":"allowed","helper":"/bin/true","argv":\["/bin/true","one"\]' "${AUDIT}"
+}
+
+@test "denied invocations are audited" {
+    usermode-helper-deny /bin/false
+    grep '"decision":"denied","helper":null,"argv":\["/bin/false"\]' "${AUDIT}"
+}
+
+@test "audit records capture the invocation environment" {
+    export ACTION=add DEVPATH=/devices/foo
+    usermode-helper /bin/true
+    grep '"ACTION":"add"' "${AUDIT}"
+    grep '"DEVPATH":"/devices/foo"' "${AUDIT}"
+}
+
+@test "audited environment is redacted and bounded" {
+    export API_TOKEN=hunter2
+    export AUDIO_PASSTHROUGH=on
+    export HUGE=$(head -c 1000 /dev/zero | tr '\0' x)
+    usermode-helper /bin/true
+    grep '"API_TOKEN":"<redacted>"' "${AUDIT}"
+    grep '"AUDIO_PASSTHROUGH":"on"' "${AUDIT}"
+    if grep hunter2 "${AUDIT}"; then
+        echo "secret leaked into the audit log" && false
+    fi
+    grep "\"HUGE\":\"$(head -c 256 /dev/zero | tr '\0' x)...\"" "${AUDIT}"
+
+    for i in $(seq 100); do
+        export "MANY_${i}=1"
+    done
+    usermode-helper /bin/true
+    tail -n 1 "${AUDIT}" | grep '"env_truncated":true'
+}
+
+@test "audit log is private" {
+    usermode-helper /bin/true
+    [ "$(stat -c %a "${AUDIT}")" = "600" ]
+}
+
+@test "log_success logs a summary line for allowed invocations" {
+    cat <<EOF > "${TEMP_DIR}/usermode-helper.conf"
+log_success = true
+
+[[helpers]]
+path = "/bin/true"
+capabilities = "cap_sys_admin,cap_net_admin"
+
+[[helpers]]
+path = "/bin/echo"
+EOF
+    usermode-helper /bin/true one two
+    echo "$output" | grep -x "huldufolk: allowed /bin/true argc=3 caps=cap_net_admin,cap_sys_admin"
+    usermode-helper /bin/echo
+    echo "$output" | grep -x "huldufolk: allowed /bin/echo argc=1 caps=unrestricted"
+}
+
+@test "log_success is off by default and skips rejected invocations" {
+    usermode-helper /bin/true
+    if echo "$output" | grep "huldufolk: allowed"; then
+        echo "logged without log_success" && false
+    fi
+
+    cat <<EOF > "${TEMP_DIR}/usermode-helper.conf"
+log_success = true
+
+[[helpers]]
+path = "/bin/true"
+pre_exec_hook = "/bin/false"
+EOF
+    usermode-helper-fail 7 /bin/true
+    if echo "$output" | grep "huldufolk: allowed"; then
+        echo "logged a rejected invocation" && false
+    fi
+}
+
+@test "a full audit log is skipped by default" {
+    sed -i "s|^audit_log = .*|audit_log = \"/dev/full\"|" "${TEMP_DIR}/usermode-helper.conf"
+    usermode-helper /bin/true
+    echo "$output" | grep "audit log /dev/full is full, dropped a record"
+}
+
+@test "a full audit log denies with audit_required" {
+    sed -i "s|^audit_log = .*|audit_log = \"/dev/full\"\naudit_required = true|" "${TEMP_DIR}/usermode-helper.conf"
+    usermode-helper-fail 14 /bin/true
+    echo "$output" | grep "couldn't write audit log /dev/full, denying /bin/true: No space left on device"
+}
+
+@test "audit_full_policy = drop overrides audit_required" {
+    sed -i "s|^audit_log = .*|audit_log = \"/dev/full\"\naudit_required = true\naudit_full_policy = \"drop\"|" "${TEMP_DIR}/usermode-helper.conf"
+    usermode-helper /bin/true
+    echo "$output" | grep "dropped a record"
+}
+
+@test "audit_full_policy = fail denies without audit_required" {
+    sed -i "s|^audit_log = .*|audit_log = \"/dev/full\"\naudit_full_policy = \"fail\"|" "${TEMP_DIR}/usermode-helper.conf"
+    usermode-helper-fail 14 /bin/true
+    echo "$output" | grep "denying /bin/true"
+}
+
+@test "audit_required denies when the audit log can't be written" {
+    sed -i "s|^audit_log = .*|audit_log = \"/nonexistent/audit.log\"\naudit_required = true|" "${TEMP_DIR}/usermode-helper.conf"
+    usermode-helper-fail 14 /bin/true
+
+    sed -i "/^audit_required/d" "${TEMP_DIR}/usermode-helper.conf"
+    usermode-helper /bin/true
+    echo "$output" | grep "couldn't write audit log /nonexistent/audit.log"
+}
+
+# Runs usermode-helper with the audit log on a small tmpfs, filled up by
+# the file named by $1 (relative to the audit log's directory), and then
+# prints the number of records in the audit log and whether audit.log.old is
+# there.
+function usermode-helper-full-disk() {
+    if ! unshare -Uur unshare -m true 2>/dev/null; then
+        skip "needs user and mount namespaces"
+    fi
+    local dir
+    dir="$(dirname "${AUDIT}")/audit"
+    mkdir "${dir}"
+    sed -i "s|^audit_log = .*|audit_log = \"${dir}/audit.log\"\naudit_full_policy = \"rotate\"|" "${TEMP_DIR}/usermode-helper.conf"
+    cat <<EOF > "${TEMP_DIR}/full-disk.sh"
+#!/bin/bash
+exec unshare -m bash -c '
+    mount -t tmpfs -o size=16k tmpfs "${dir}" || exit 1
+    # A page of its own, so any record needs another one.
+    head -c 4095 /dev/zero | tr "\\0" x > "${dir}/audit.log"
+    echo >> "${dir}/audit.log"
+    head -c 64k /dev/zero >> "${dir}/$1" 2>/dev/null
+    "\$@"
+    status=\$?
+    echo "records: \$(grep -c decision "${dir}/audit.log")"
+    [ -e "${dir}/audit.log.old" ] && echo "old: \$(cat "${dir}/audit.log.old" | head -c 2)"
+    exit \$status' - "\$@"
+EOF
+    chmod +x "${TEMP_DIR}/full-disk.sh"
+    UMH_PREFIX="$(realpath "${TEMP_DIR}")/full-disk.sh" usermode-helper /bin/true
+}
+
+@test "audit_full_policy = rotate moves the log out of the way" {
+    usermode-helper-full-disk audit.log.old
+    echo "$output" | grep "moved it to .*/audit.log.old"
+    echo "$output" | grep -x "records: 1"
+    echo "$output" | grep -x "old: xx"
+}
+
+@test "audit_full_policy = rotate discards a log that fills the disk itself" {
+    usermode-helper-full-disk audit.log
+    echo "$output" | grep "audit log .*/audit.log is full, discarded it"
+    echo "$output" | grep -x "records: 1"
+    if echo "$output" | grep "^old:"; then
+        echo "kept the full log" && false
+    fi
+}
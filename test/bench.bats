@@ -0,0 +1,65 @@
+load helpers
+
+function setup() {
+    make_tempdir
+}
+
+function teardown() {
+    cleanup
+}
+
+function bench() {
+    : "${UMH_BIN:=${ROOT_DIR}/target/debug/usermode-helper}"
+    pushd "${TEMP_DIR}"
+    run unshare -Uur "${UMH_BIN}" --bench "$@"
+    popd
+    echo "$output"
+}
+
+@test "benchmark reports latencies for a trivial config" {
+    cat <<EOF > "${TEMP_DIR}/usermode-helper.conf"
+[[helpers]]
+path = "/bin/true"
+capabilities = "cap_sys_admin"
+EOF
+    bench usermode-helper.conf /bin/true
+    [ "$status" -eq 0 ]
+    echo "$output" | grep -x "helper: /bin/true"
+    echo "$output" | grep -x "iterations: 1000"
+    mean=$(echo "$output" | sed -n 's/^mean: \([0-9.]*\)us$/\1/p')
+    median=$(echo "$output" | sed -n 's/^median: \([0-9.]*\)us$/\1/p')
+    # Positive, and well under a second per invocation.
+    awk -v m="$mean" -v d="$median" 'BEGIN { exit !(m > 0 && d > 0 && m < 1000000 && d < 1000000) }'
+}
+
+@test "benchmark doesn't run the helper" {
+    cat <<EOF > "${TEMP_DIR}/usermode-helper.conf"
+[[helpers]]
+path = "/bin/touch"
+capabilities = "cap_sys_admin"
+EOF
+    bench usermode-helper.conf /bin/touch ran
+    [ "$status" -eq 0 ]
+    [ ! -e "${TEMP_DIR}/ran" ]
+    if echo "$output" | grep "DEBUG CAPS"; then
+        echo "helper was executed" && false
+    fi
+}
+
+@test "benchmark of a denied invocation fails" {
+    cat <<EOF > "${TEMP_DIR}/usermode-helper.conf"
+[[helpers]]
+path = "/bin/true"
+EOF
+    bench usermode-helper.conf /bin/false
+    [ "$status" -eq 1 ]
+    echo "$output" | grep "invalid usermode helper"
+}
+
+@test "--bench is only recognized under our own name" {
+    cat <<EOF > "${TEMP_DIR}/usermode-helper.conf"
+[[helpers]]
+path = "/bin/true"
+EOF
+    usermode-helper-deny /sbin/modprobe --bench usermode-helper.conf /bin/true
+}
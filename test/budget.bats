@@ -0,0 +1,76 @@
+load helpers
+
+function setup() {
+    make_tempdir
+    COUNTER="$(realpath "${TEMP_DIR}")/invocations"
+    cat <<EOF > "${TEMP_DIR}/usermode-helper.conf"
+invocation_counter = "${COUNTER}"
+
+[[helpers]]
+path = "/bin/true"
+max_invocations = 2
+
+[[helpers]]
+path = "/bin/echo"
+EOF
+}
+
+function teardown() {
+    cleanup
+}
+
+@test "max_invocations denies the invocation after the budget" {
+    usermode-helper /bin/true
+    usermode-helper /bin/true
+    real-usermode-helper /bin/true
+    [ "$status" -eq 14 ]
+    echo "$output" | grep "/bin/true already ran its max_invocations of 2 since boot"
+    if echo "$output" | grep "DEBUG CAPS"; then
+        echo "helper ran over budget" && false
+    fi
+}
+
+@test "helpers without max_invocations aren't counted" {
+    for _ in 1 2 3; do
+        usermode-helper /bin/echo
+    done
+    [ ! -e "${COUNTER}" ]
+}
+
+@test "resetting the counter restores the budget" {
+    usermode-helper /bin/true
+    usermode-helper /bin/true
+    rm "${COUNTER}"
+    usermode-helper /bin/true
+}
+
+@test "rejected invocations don't use up the budget" {
+    sed -i 's|^max_invocations = 2|max_invocations = 1\npre_exec_hook = "/bin/false"|' "${TEMP_DIR}/usermode-helper.conf"
+    usermode-helper-fail 7 /bin/true
+    usermode-helper-fail 7 /bin/true
+    sed -i '/^pre_exec_hook/d' "${TEMP_DIR}/usermode-helper.conf"
+    usermode-helper /bin/true
+}
+
+@test "unreadable invocation counter denies" {
+    printf 'garbage' > "${COUNTER}"
+    real-usermode-helper /bin/true
+    [ "$status" -eq 14 ]
+    echo "$output" | grep "couldn't count invocation of /bin/true"
+}
+
+@test "concurrent invocations are all counted" {
+    sed -i 's/max_invocations = 2/max_invocations = 100/' "${TEMP_DIR}/usermode-helper.conf"
+    for _ in $(seq 10); do
+        real-usermode-helper /bin/true &
+    done
+    wait
+    grep -x '{"/bin/true":10}' "${COUNTER}"
+}
+
+@test "failed execs use up the budget" {
+    sed -i "s|^max_invocations = 2|max_invocations = 1\nexec_path = \"$(realpath "${TEMP_DIR}")/missing\"|" "${TEMP_DIR}/usermode-helper.conf"
+    usermode-helper-fail 9 /bin/true
+    usermode-helper-fail 14 /bin/true
+    echo "$output" | grep "/bin/true already ran its max_invocations of 1 since boot"
+}
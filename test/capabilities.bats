@@ -14,7 +14,7 @@ function teardown() {
 path = "/bin/hostname"
 capabilities = "="
 EOF
-    usermode-helper-fail /bin/hostname foo
+    usermode-helper-fail 1 /bin/hostname foo
 }
 
 @test "caps are kept correctly" {
@@ -25,3 +25,284 @@ capabilities = "cap_sys_admin+eip"
 EOF
     usermode-helper /bin/hostname foo
 }
+
+@test "unknown capability is an error by default" {
+    cat >> "${TEMP_DIR}/usermode-helper.conf" <<EOF
+[[helpers]]
+path = "/bin/true"
+capabilities = "cap_sys_admin cap_frobnicate"
+EOF
+    usermode-helper-fail 5 /bin/true
+    echo "$output" | grep "bad caps cap_frobnicate"
+}
+
+@test "unknown_cap_policy = error rejects unknown capabilities" {
+    cat >> "${TEMP_DIR}/usermode-helper.conf" <<EOF
+unknown_cap_policy = "error"
+
+[[helpers]]
+path = "/bin/true"
+capabilities = "cap_sys_admin cap_frobnicate"
+EOF
+    usermode-helper-fail 5 /bin/true
+    echo "$output" | grep "bad caps cap_frobnicate"
+}
+
+@test "unknown_cap_policy = warn_skip drops unknown capabilities" {
+    cat >> "${TEMP_DIR}/usermode-helper.conf" <<EOF
+unknown_cap_policy = "warn_skip"
+
+[[helpers]]
+path = "/bin/true"
+capabilities = "cap_sys_admin cap_frobnicate"
+EOF
+    usermode-helper /bin/true
+    echo "$output" | grep "WARNING: ignoring unknown capability cap_frobnicate"
+    echo "$output" | grep "^Permitted: {CAP_SYS_ADMIN}$"
+}
+
+@test "unknown_cap_policy = numeric maps known capability numbers" {
+    cat >> "${TEMP_DIR}/usermode-helper.conf" <<EOF
+unknown_cap_policy = "numeric"
+
+[[helpers]]
+path = "/bin/true"
+capabilities = "cap_21+eip"
+EOF
+    usermode-helper /bin/true
+    echo "$output" | grep "^Permitted: {CAP_SYS_ADMIN}$"
+}
+
+@test "unknown_cap_policy = numeric rejects unknown names and numbers" {
+    cat >> "${TEMP_DIR}/usermode-helper.conf" <<EOF
+unknown_cap_policy = "numeric"
+
+[[helpers]]
+path = "/bin/true"
+capabilities = "cap_frobnicate"
+EOF
+    usermode-helper-fail 5 /bin/true
+    echo "$output" | grep "bad caps cap_frobnicate"
+
+    cat > "${TEMP_DIR}/usermode-helper.conf" <<EOF
+unknown_cap_policy = "numeric"
+
+[[helpers]]
+path = "/bin/true"
+capabilities = "cap_99"
+EOF
+    usermode-helper-fail 5 /bin/true
+    echo "$output" | grep "bad caps cap_99"
+}
+
+@test "missing capabilities of our own are diagnosed" {
+    cat >> "${TEMP_DIR}/usermode-helper.conf" <<EOF
+[[helpers]]
+path = "/bin/true"
+capabilities = "cap_sys_admin,cap_sys_module,cap_net_admin"
+EOF
+    UMH_PREFIX="setpriv --bounding-set=-sys_module,-net_admin" real-usermode-helper /bin/true
+    [ "$status" -eq 4 ]
+    echo "$output" | grep "huldufolk lacks CAP_NET_ADMIN, CAP_SYS_MODULE needed by /bin/true"
+    if echo "$output" | grep "DEBUG CAPS"; then
+        echo "helper ran without its capabilities" && false
+    fi
+}
+
+@test "missing capabilities of our own are reported before other checks" {
+    cat >> "${TEMP_DIR}/usermode-helper.conf" <<EOF
+secure_boot_var = "$(realpath "${TEMP_DIR}")/missing"
+
+[[helpers]]
+path = "/bin/true"
+capabilities = "cap_sys_admin,cap_sys_module"
+require_secure_boot = true
+pre_exec_hook = "/bin/false"
+EOF
+    UMH_PREFIX="setpriv --bounding-set=-sys_module" real-usermode-helper /bin/true
+    [ "$status" -eq 4 ]
+    echo "$output" | grep "huldufolk lacks CAP_SYS_MODULE needed by /bin/true"
+}
+
+@test "helpers without capabilities don't need ours" {
+    cat >> "${TEMP_DIR}/usermode-helper.conf" <<EOF
+[[helpers]]
+path = "/bin/true"
+EOF
+    UMH_PREFIX="setpriv --bounding-set=-sys_module" usermode-helper /bin/true
+}
+
+@test "require_capabilities are granted and checked" {
+    # Needs (namespaced) root to have capabilities to grant at all.
+    if ! unshare -Uur true 2>/dev/null; then
+        skip "no user namespaces, can't be root"
+    fi
+    cat >> "${TEMP_DIR}/usermode-helper.conf" <<EOF
+[[helpers]]
+path = "/bin/true"
+capabilities = "cap_sys_admin"
+require_capabilities = "cap_net_admin"
+EOF
+    usermode-helper /bin/true
+    echo "$output" | grep -E "^Permitted: \{CAP_(SYS|NET)_ADMIN, CAP_(SYS|NET)_ADMIN\}$"
+    echo "$output" | grep -E "^Ambient: \{CAP_(SYS|NET)_ADMIN, CAP_(SYS|NET)_ADMIN\}$"
+}
+
+@test "missing required capability fails closed" {
+    cat >> "${TEMP_DIR}/usermode-helper.conf" <<EOF
+[[helpers]]
+path = "/bin/true"
+require_capabilities = "cap_net_admin"
+EOF
+    UMH_PREFIX="setpriv --bounding-set=-net_admin" real-usermode-helper /bin/true
+    [ "$status" -ne 0 ]
+    echo "$output" | grep "CAP_NET_ADMIN"
+    if echo "$output" | grep "DEBUG CAPS"; then
+        echo "helper ran without a required capability" && false
+    fi
+}
+
+@test "unknown required capabilities are always an error" {
+    cat >> "${TEMP_DIR}/usermode-helper.conf" <<EOF
+unknown_cap_policy = "warn_skip"
+
+[[helpers]]
+path = "/bin/true"
+require_capabilities = "cap_frobnicate"
+EOF
+    usermode-helper-fail 5 /bin/true
+    echo "$output" | grep "bad caps cap_frobnicate"
+}
+
+@test "ambient_denylist keeps capabilities out of the ambient set" {
+    cat >> "${TEMP_DIR}/usermode-helper.conf" <<EOF
+ambient_denylist = "cap_sys_admin"
+
+[[helpers]]
+path = "/bin/true"
+capabilities = "cap_sys_admin,cap_net_admin"
+EOF
+    usermode-helper /bin/true
+    echo "$output" | grep "not raising CAP_SYS_ADMIN ambient"
+    echo "$output" | grep -E "^Permitted: \{CAP_(SYS|NET)_ADMIN, CAP_(SYS|NET)_ADMIN\}$"
+    echo "$output" | grep -x "Ambient: {CAP_NET_ADMIN}"
+}
+
+@test "ambient_denylist can't deny required capabilities" {
+    cat >> "${TEMP_DIR}/usermode-helper.conf" <<EOF
+ambient_denylist = "cap_sys_admin"
+
+[[helpers]]
+path = "/bin/true"
+require_capabilities = "cap_sys_admin"
+EOF
+    usermode-helper-fail 5 /bin/true
+    echo "$output" | grep "helper /bin/true: require_capabilities lists cap_sys_admin, which ambient_denylist denies"
+}
+
+@test "ambient_denylist_policy = fail fails the helper" {
+    cat >> "${TEMP_DIR}/usermode-helper.conf" <<EOF
+ambient_denylist = "cap_sys_admin"
+ambient_denylist_policy = "fail"
+
+[[helpers]]
+path = "/bin/true"
+capabilities = "cap_sys_admin,cap_net_admin"
+EOF
+    usermode-helper-fail 6 /bin/true
+    echo "$output" | grep "refusing to raise CAP_SYS_ADMIN ambient"
+    if echo "$output" | grep "DEBUG CAPS"; then
+        echo "helper ran" && false
+    fi
+}
+
+# Prints the sets of /proc/self/status that CAP_SETPCAP (bit 8) is in.
+function setpcap-sets() {
+    CHECK="$(realpath "${TEMP_DIR}")/setpcap-sets.sh"
+    cat <<EOF > "${CHECK}"
+#!/bin/bash
+while read -r name value; do
+    case "\$name" in
+    Cap*:) (( 0x\$value & 0x100 )) && echo "\$name" >> "\$(dirname "\$0")/setpcap-sets" ;;
+    esac
+done < /proc/self/status
+touch "\$(dirname "\$0")/ran"
+EOF
+    chmod +x "${CHECK}"
+}
+
+@test "CAP_SETPCAP is dropped from every set, bounding included, unless allowed" {
+    setpcap-sets
+    cat >> "${TEMP_DIR}/usermode-helper.conf" <<EOF
+[[helpers]]
+path = "${CHECK}"
+capabilities = "cap_sys_admin,cap_net_admin"
+EOF
+    usermode-helper "${CHECK}"
+    [ -e "${TEMP_DIR}/ran" ]
+    if [ -e "${TEMP_DIR}/setpcap-sets" ]; then
+        cat "${TEMP_DIR}/setpcap-sets"
+        echo "CAP_SETPCAP left in a set" && false
+    fi
+    if echo "$output" | grep "CAP_SETPCAP is allowed"; then
+        echo "warned" && false
+    fi
+}
+
+@test "allowing CAP_SETPCAP warns and keeps it" {
+    setpcap-sets
+    cat >> "${TEMP_DIR}/usermode-helper.conf" <<EOF
+[[helpers]]
+path = "${CHECK}"
+capabilities = "cap_setpcap"
+EOF
+    usermode-helper "${CHECK}"
+    echo "$output" | grep "WARNING: CAP_SETPCAP is allowed, so the helper can change its own capabilities"
+    grep -x "CapBnd:" "${TEMP_DIR}/setpcap-sets"
+    grep -x "CapAmb:" "${TEMP_DIR}/setpcap-sets"
+}
+
+@test "raise_ambient = false leaves the ambient set empty" {
+    # Needs (namespaced) root to have capabilities to grant at all.
+    if ! unshare -Uur true 2>/dev/null; then
+        skip "no user namespaces, can't be root"
+    fi
+    cat >> "${TEMP_DIR}/usermode-helper.conf" <<EOF
+[[helpers]]
+path = "/bin/true"
+capabilities = "cap_sys_admin,cap_net_admin"
+raise_ambient = false
+EOF
+    usermode-helper /bin/true
+    echo "$output" | grep -E "^Permitted: \{CAP_(SYS|NET)_ADMIN, CAP_(SYS|NET)_ADMIN\}$"
+    echo "$output" | grep -E "^Inheritable: \{CAP_(SYS|NET)_ADMIN, CAP_(SYS|NET)_ADMIN\}$"
+    echo "$output" | grep -x "Ambient: {}"
+}
+
+@test "raise_ambient = false doesn't trip ambient_denylist" {
+    cat >> "${TEMP_DIR}/usermode-helper.conf" <<EOF
+ambient_denylist = "cap_sys_admin"
+ambient_denylist_policy = "fail"
+
+[[helpers]]
+path = "/bin/true"
+capabilities = "cap_sys_admin"
+raise_ambient = false
+EOF
+    usermode-helper /bin/true
+    if echo "$output" | grep "ambient_denylist"; then
+        echo "warned about a capability that isn't raised ambient" && false
+    fi
+    echo "$output" | grep -x "Ambient: {}"
+}
+
+@test "require_capabilities needs raise_ambient" {
+    cat >> "${TEMP_DIR}/usermode-helper.conf" <<EOF
+[[helpers]]
+path = "/bin/true"
+require_capabilities = "cap_sys_admin"
+raise_ambient = false
+EOF
+    usermode-helper-fail 5 /bin/true
+    echo "$output" | grep "helper /bin/true: require_capabilities needs raise_ambient"
+}
@@ -0,0 +1,85 @@
+load helpers
+
+function setup() {
+    make_tempdir
+    DIR="$(realpath "${TEMP_DIR}")"
+    AUDIT="${DIR}/audit.log"
+    printf '#!/bin/sh\nsleep 0.3\n' > "${DIR}/helper"
+    chmod 0755 "${DIR}/helper"
+}
+
+function teardown() {
+    cleanup
+}
+
+@test "capability_report logs the capabilities the helper held" {
+    # Needs (namespaced) root to have capabilities to grant at all.
+    if ! unshare -Uur true 2>/dev/null; then
+        skip "no user namespaces, can't be root"
+    fi
+    cat <<EOF > "${TEMP_DIR}/usermode-helper.conf"
+audit_log = "${AUDIT}"
+
+[[helpers]]
+path = "${DIR}/helper"
+capabilities = "cap_net_admin,cap_chown"
+run_timeout = 5
+capability_report = true
+EOF
+    usermode-helper "${DIR}/helper"
+    cat "${AUDIT}"
+    grep '"helper":"'"${DIR}"'/helper","capability_report":{"granted":\["cap_chown","cap_net_admin"\],"observed":\["cap_chown","cap_net_admin"\],"unobserved":\[\],"samples":[1-9]' "${AUDIT}"
+}
+
+@test "capability reports are off by default" {
+    cat <<EOF > "${TEMP_DIR}/usermode-helper.conf"
+audit_log = "${AUDIT}"
+
+[[helpers]]
+path = "${DIR}/helper"
+capabilities = "cap_net_admin"
+run_timeout = 5
+EOF
+    usermode-helper "${DIR}/helper"
+    if grep capability_report "${AUDIT}"; then
+        echo "reported capabilities without capability_report" && false
+    fi
+}
+
+@test "capability_report requires run_timeout" {
+    cat <<EOF > "${TEMP_DIR}/usermode-helper.conf"
+audit_log = "${AUDIT}"
+
+[[helpers]]
+path = "${DIR}/helper"
+capabilities = "cap_net_admin"
+capability_report = true
+EOF
+    usermode-helper-fail 5 "${DIR}/helper"
+    echo "$output" | grep "capability_report requires run_timeout"
+}
+
+@test "capability_report requires capabilities" {
+    cat <<EOF > "${TEMP_DIR}/usermode-helper.conf"
+audit_log = "${AUDIT}"
+
+[[helpers]]
+path = "${DIR}/helper"
+run_timeout = 5
+capability_report = true
+EOF
+    usermode-helper-fail 5 "${DIR}/helper"
+    echo "$output" | grep "capability_report requires capabilities"
+}
+
+@test "capability_report requires audit_log" {
+    cat <<EOF > "${TEMP_DIR}/usermode-helper.conf"
+[[helpers]]
+path = "${DIR}/helper"
+capabilities = "cap_net_admin"
+run_timeout = 5
+capability_report = true
+EOF
+    usermode-helper-fail 5 "${DIR}/helper"
+    echo "$output" | grep "capability_report requires audit_log"
+}
@@ -0,0 +1,117 @@
+load helpers
+
+function setup() {
+    make_tempdir
+    cat <<EOF > "${TEMP_DIR}/usermode-helper.conf"
+[[helpers]]
+path = "/bin/true"
+EOF
+    # Prime the cache, if this build has one.
+    usermode-helper /bin/true
+    if [ ! -e "${TEMP_DIR}/usermode-helper.cache" ]; then
+        skip "built without the config-cache feature"
+    fi
+}
+
+function teardown() {
+    cleanup
+}
+
+@test "config cache is used when fresh" {
+    usermode-helper /bin/true
+    echo "$output" | grep "using cached config"
+}
+
+@test "config cache is invalidated when the config changes" {
+    cat <<EOF > "${TEMP_DIR}/usermode-helper.conf"
+[[helpers]]
+path = "/bin/false"
+EOF
+    usermode-helper-deny /bin/true
+    if echo "$output" | grep "using cached config"; then
+        echo "used a stale cache" && false
+    fi
+
+    # ...and the new config is cached in turn.
+    usermode-helper-fail 1 /bin/false
+    echo "$output" | grep "using cached config"
+}
+
+@test "config cache is invalidated when a drop-in changes" {
+    mkdir "${TEMP_DIR}/usermode-helper.conf.d"
+    cat <<EOF > "${TEMP_DIR}/usermode-helper.conf.d/false.conf"
+[[helpers]]
+path = "/bin/false"
+EOF
+    usermode-helper-fail 1 /bin/false
+    if echo "$output" | grep "using cached config"; then
+        echo "used a stale cache" && false
+    fi
+    usermode-helper-fail 1 /bin/false
+    echo "$output" | grep "using cached config"
+}
+
+@test "corrupt config cache falls back to parsing" {
+    printf 'garbage' > "${TEMP_DIR}/usermode-helper.cache"
+    usermode-helper /bin/true
+    if echo "$output" | grep "using cached config"; then
+        echo "used a corrupt cache" && false
+    fi
+
+    # Flip a byte in the payload of a freshly written cache.
+    size=$(stat -c %s "${TEMP_DIR}/usermode-helper.cache")
+    printf '\xff' | dd of="${TEMP_DIR}/usermode-helper.cache" bs=1 seek=$((size - 1)) conv=notrunc
+    usermode-helper /bin/true
+    if echo "$output" | grep "using cached config"; then
+        echo "used a corrupt cache" && false
+    fi
+}
+
+@test "writable config cache is ignored" {
+    chmod 0666 "${TEMP_DIR}/usermode-helper.cache"
+    usermode-helper /bin/true
+    if echo "$output" | grep "using cached config"; then
+        echo "used an untrusted cache" && false
+    fi
+}
+
+@test "cached config is still subject to permission checks" {
+    chmod 0666 "${TEMP_DIR}/usermode-helper.conf"
+    usermode-helper-fail 5 /bin/true
+    echo "$output" | grep "group/world-writable"
+}
+
+@test "cached config keeps every helper setting" {
+    cat <<EOF > "${TEMP_DIR}/usermode-helper.conf"
+[[helpers]]
+path = "/bin/grep"
+capabilities = "cap_sys_admin,cap_net_admin"
+block_signals = ["SIGTERM", "SIGUSR1"]
+match_env = { ACTION = "add", DEVPATH = { regex = "/devices/.*" } }
+seccomp = "@minimal"
+args_template = ["-q", "<any>", "/proc/self/status"]
+EOF
+    export ACTION=add DEVPATH=/devices/foo
+    usermode-helper /bin/grep -q "^SigBlk:[[:space:]]*0*4200$" /proc/self/status
+    usermode-helper /bin/grep -q "^SigBlk:[[:space:]]*0*4200$" /proc/self/status
+    echo "$output" | grep "using cached config"
+    echo "$output" | grep -E "^Permitted: \{CAP_(SYS|NET)_ADMIN, CAP_(SYS|NET)_ADMIN\}$"
+    echo "$output" | grep "seccomp filter for /bin/grep allows: .*\bexit_group\b"
+
+    export DEVPATH=/elsewhere
+    usermode-helper-deny /bin/grep -q "^SigBlk:[[:space:]]*0*4200$" /proc/self/status
+}
+
+@test "only the kernel's invocations write the config cache" {
+    cd "${TEMP_DIR}"
+    cp usermode-helper.cache primed.cache
+    cat <<EOF > other.conf
+[[helpers]]
+path = "/bin/false"
+EOF
+    for flag in --validate --dump; do
+        run "${UMH_BIN}" "$flag" other.conf
+        [ "$status" -eq 0 ]
+        cmp usermode-helper.cache primed.cache
+    done
+}
@@ -0,0 +1,124 @@
+load helpers
+
+function setup() {
+    make_tempdir
+    : "${UMH_BIN:=${ROOT_DIR}/target/debug/usermode-helper}"
+    cat <<EOF > "${TEMP_DIR}/usermode-helper.conf"
+[[helpers]]
+path = "/bin/true"
+EOF
+}
+
+function teardown() {
+    cleanup
+}
+
+# Test builds read the kernel command line from ./cmdline, i.e. in TEMP_DIR.
+function cmdline() {
+    echo "$@" > "${TEMP_DIR}/cmdline"
+}
+
+function config-hash() {
+    run "${UMH_BIN}" --config-hash "${TEMP_DIR}/usermode-helper.conf"
+    echo "$output"
+    [ "$status" -eq 0 ]
+    HASH="$output"
+}
+
+# A file's length as a big-endian u64, followed by its contents.
+function framed() {
+    printf '%016x' "$(stat -c %s "$1")" | xxd -r -p
+    cat "$1"
+}
+
+@test "--config-hash digests the config and its drop-ins, in order" {
+    mkdir "${TEMP_DIR}/usermode-helper.conf.d"
+    cat <<EOF > "${TEMP_DIR}/usermode-helper.conf.d/false.conf"
+[[helpers]]
+path = "/bin/false"
+EOF
+    config-hash
+    expected="$({
+        framed "${TEMP_DIR}/usermode-helper.conf"
+        framed "${TEMP_DIR}/usermode-helper.conf.d/false.conf"
+    } | sha256sum | cut -d' ' -f1)"
+    [ "${HASH}" = "${expected}" ]
+}
+
+@test "a matching config hash runs helpers" {
+    config-hash
+    cmdline "ro quiet huldufolk.confighash=${HASH} -- init-arg"
+    usermode-helper /bin/true
+    echo "$output" | grep "config hash ${HASH} matches the kernel command line"
+}
+
+@test "a mismatching config hash denies every helper" {
+    config-hash
+    cmdline "ro huldufolk.confighash=${HASH}"
+    echo "# changed" >> "${TEMP_DIR}/usermode-helper.conf"
+    real-usermode-helper /bin/true
+    [ "$status" -eq 13 ]
+    echo "$output" | grep "config hash [0-9a-f]* doesn't match huldufolk.confighash=${HASH}, denying all helpers"
+}
+
+@test "changed drop-ins don't match either" {
+    mkdir "${TEMP_DIR}/usermode-helper.conf.d"
+    config-hash
+    cmdline "huldufolk.confighash=${HASH}"
+    cat <<EOF > "${TEMP_DIR}/usermode-helper.conf.d/false.conf"
+[[helpers]]
+path = "/bin/false"
+EOF
+    real-usermode-helper /bin/true
+    [ "$status" -eq 13 ]
+    echo "$output" | grep "doesn't match huldufolk.confighash"
+}
+
+@test "the config hash may be quoted and in upper case" {
+    config-hash
+    cmdline "huldufolk.confighash=\"${HASH^^}\""
+    usermode-helper /bin/true
+}
+
+@test "no config hash on the command line is fine unless required" {
+    cmdline "ro quiet"
+    usermode-helper /bin/true
+
+    sed -i '1i require_config_hash = true' "${TEMP_DIR}/usermode-helper.conf"
+    real-usermode-helper /bin/true
+    [ "$status" -eq 13 ]
+    echo "$output" | grep "require_config_hash is set, but the kernel command line has no huldufolk.confighash, denying all helpers"
+
+    config-hash
+    cmdline "ro quiet huldufolk.confighash=${HASH}"
+    usermode-helper /bin/true
+}
+
+@test "require_config_hash fails closed without a command line" {
+    echo "require_config_hash = true" > "${TEMP_DIR}/new.conf"
+    cat "${TEMP_DIR}/usermode-helper.conf" >> "${TEMP_DIR}/new.conf"
+    mv "${TEMP_DIR}/new.conf" "${TEMP_DIR}/usermode-helper.conf"
+    real-usermode-helper /bin/true
+    [ "$status" -eq 13 ]
+    echo "$output" | grep "couldn't read ./cmdline: .*, denying all helpers"
+}
+
+@test "config hashes after -- are for init and ignored" {
+    cmdline "ro -- huldufolk.confighash=0000000000000000000000000000000000000000000000000000000000000000"
+    usermode-helper /bin/true
+}
+
+@test "malformed config hashes deny every helper" {
+    cmdline "huldufolk.confighash=abc"
+    real-usermode-helper /bin/true
+    [ "$status" -eq 13 ]
+    echo "$output" | grep 'huldufolk.confighash="abc" isn'"'"'t a SHA-256 digest, denying all helpers'
+}
+
+@test "repeated config hashes deny every helper" {
+    config-hash
+    cmdline "huldufolk.confighash=${HASH} huldufolk.confighash=${HASH}"
+    real-usermode-helper /bin/true
+    [ "$status" -eq 13 ]
+    echo "$output" | grep "huldufolk.confighash is given more than once, denying all helpers"
+}
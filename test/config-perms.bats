@@ -0,0 +1,69 @@
+load helpers
+
+function setup() {
+    make_tempdir
+}
+
+function teardown() {
+    cleanup
+}
+
+@test "world-writable config is rejected" {
+    cat <<EOF > "${TEMP_DIR}/usermode-helper.conf"
+[[helpers]]
+path = "/bin/true"
+EOF
+    chmod 0666 "${TEMP_DIR}/usermode-helper.conf"
+    usermode-helper-fail 5 /bin/true
+    echo "$output" | grep "group/world-writable"
+}
+
+@test "group-writable config is rejected" {
+    cat <<EOF > "${TEMP_DIR}/usermode-helper.conf"
+[[helpers]]
+path = "/bin/true"
+EOF
+    chmod 0664 "${TEMP_DIR}/usermode-helper.conf"
+    usermode-helper-fail 5 /bin/true
+    echo "$output" | grep "group/world-writable"
+}
+
+@test "symlinked config is followed" {
+    cat <<EOF > "${TEMP_DIR}/real.conf"
+[[helpers]]
+path = "/bin/true"
+EOF
+    ln -s real.conf "${TEMP_DIR}/usermode-helper.conf"
+    usermode-helper /bin/true
+}
+
+@test "symlink to a world-writable config is rejected" {
+    cat <<EOF > "${TEMP_DIR}/real.conf"
+[[helpers]]
+path = "/bin/true"
+EOF
+    chmod 0666 "${TEMP_DIR}/real.conf"
+    ln -s real.conf "${TEMP_DIR}/usermode-helper.conf"
+    usermode-helper-fail 5 /bin/true
+    echo "$output" | grep "group/world-writable"
+}
+
+@test "the config can't turn off its own permission check" {
+    cat <<EOF > "${TEMP_DIR}/usermode-helper.conf"
+verify_config_perms = false
+
+[[helpers]]
+path = "/bin/true"
+EOF
+    chmod 0666 "${TEMP_DIR}/usermode-helper.conf"
+    usermode-helper-fail 5 /bin/true
+    echo "$output" | grep "group/world-writable"
+}
+
+@test "permissions are checked before the config is parsed" {
+    echo "not toml [" > "${TEMP_DIR}/usermode-helper.conf"
+    chmod 0666 "${TEMP_DIR}/usermode-helper.conf"
+    usermode-helper-fail 5 /bin/true
+    echo "$output" | grep "group/world-writable"
+    ! echo "$output" | grep "couldn't parse"
+}
@@ -0,0 +1,69 @@
+load helpers
+
+function setup() {
+    make_tempdir
+    CHECK="$(realpath "${TEMP_DIR}")/check-core.sh"
+    cat <<EOF > "${CHECK}"
+#!/bin/sh
+grep -q "^Max core file size  *\$1  *\$2  *bytes" /proc/self/limits
+EOF
+    chmod +x "${CHECK}"
+}
+
+function teardown() {
+    cleanup
+}
+
+@test "helpers can't dump core by default" {
+    cat <<EOF > "${TEMP_DIR}/usermode-helper.conf"
+[[helpers]]
+path = "${CHECK}"
+EOF
+    usermode-helper "${CHECK}" 0 0
+    if echo "$output" | grep "core dumps are enabled"; then
+        echo "warned without allow_core" && false
+    fi
+}
+
+@test "allow_core lifts the core limit and warns" {
+    cat <<EOF > "${TEMP_DIR}/usermode-helper.conf"
+[[helpers]]
+path = "${CHECK}"
+allow_core = true
+EOF
+    usermode-helper "${CHECK}" unlimited unlimited
+    echo "$output" | grep "WARNING: core dumps are enabled for ${CHECK}"
+}
+
+@test "allow_core honours core_limit" {
+    cat <<EOF > "${TEMP_DIR}/usermode-helper.conf"
+[[helpers]]
+path = "${CHECK}"
+allow_core = true
+core_limit = 1048576
+EOF
+    # The hard limit is left alone.
+    usermode-helper "${CHECK}" 1048576 unlimited
+}
+
+@test "core_limit without allow_core has no effect" {
+    cat <<EOF > "${TEMP_DIR}/usermode-helper.conf"
+[[helpers]]
+path = "${CHECK}"
+core_limit = 1048576
+EOF
+    usermode-helper "${CHECK}" 0 0
+}
+
+@test "allow_core works with a seccomp filter that doesn't allow prctl" {
+    cat <<EOF > "${TEMP_DIR}/usermode-helper.conf"
+[[helpers]]
+path = "/bin/true"
+allow_core = true
+seccomp = "@minimal"
+EOF
+    usermode-helper /bin/true
+    if echo "$output" | grep "allows: .*\bprctl\b"; then
+        echo "@minimal allows prctl" && false
+    fi
+}
@@ -0,0 +1,47 @@
+load helpers
+
+function setup() {
+    make_tempdir
+    DIR="$(realpath "${TEMP_DIR}")"
+    cat <<EOF > "${DIR}/check-cpu.sh"
+#!/bin/sh
+grep -q "^Max cpu time  *\$1  *\$2  *seconds" /proc/self/limits
+EOF
+    cat <<EOF > "${DIR}/spin.sh"
+#!/bin/sh
+while :; do :; done
+EOF
+    chmod +x "${DIR}/check-cpu.sh" "${DIR}/spin.sh"
+}
+
+function teardown() {
+    cleanup
+}
+
+@test "cpu_time_secs sets RLIMIT_CPU, with a higher hard limit" {
+    cat <<EOF > "${TEMP_DIR}/usermode-helper.conf"
+[[helpers]]
+path = "${DIR}/check-cpu.sh"
+cpu_time_secs = 30
+EOF
+    usermode-helper "${DIR}/check-cpu.sh" 30 32
+}
+
+@test "RLIMIT_CPU is left alone without cpu_time_secs" {
+    cat <<EOF > "${TEMP_DIR}/usermode-helper.conf"
+[[helpers]]
+path = "${DIR}/check-cpu.sh"
+EOF
+    usermode-helper "${DIR}/check-cpu.sh" unlimited unlimited
+}
+
+@test "a helper using up its CPU time gets SIGXCPU" {
+    cat <<EOF > "${TEMP_DIR}/usermode-helper.conf"
+[[helpers]]
+path = "${DIR}/spin.sh"
+cpu_time_secs = 1
+run_timeout = 30
+EOF
+    # 128 + SIGXCPU, passed through by the supervisor.
+    usermode-helper-fail 152 "${DIR}/spin.sh"
+}
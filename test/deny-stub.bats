@@ -0,0 +1,67 @@
+load helpers
+
+function setup() {
+    make_tempdir
+    DIR="$(realpath "${TEMP_DIR}")"
+    # Records its arguments and capability sets.
+    cat <<EOF > "${DIR}/stub"
+#!/bin/sh
+echo "\$*" > "${DIR}/stub-ran"
+grep ^Cap /proc/self/status >> "${DIR}/stub-ran"
+grep ^NoNewPrivs /proc/self/status >> "${DIR}/stub-ran"
+exit 3
+EOF
+    chmod 0755 "${DIR}/stub"
+    cat <<EOF > "${TEMP_DIR}/usermode-helper.conf"
+deny_stub = "${DIR}/stub"
+
+[[helpers]]
+path = "/bin/true"
+capabilities = "cap_sys_admin"
+EOF
+}
+
+function teardown() {
+    cleanup
+}
+
+@test "deny_stub runs instead of failing unmatched invocations" {
+    real-usermode-helper /bin/false one two
+    cat "${DIR}/stub-ran"
+    [ "$status" -eq 3 ]
+    echo "$output" | grep "invalid usermode helper \"/bin/false\", running ${DIR}/stub"
+    grep -x "one two" "${DIR}/stub-ran"
+}
+
+@test "deny_stub runs with no capabilities at all" {
+    # Needs (namespaced) root to have capabilities to lose at all.
+    if ! unshare -Uur true 2>/dev/null; then
+        skip "no user namespaces, can't be root"
+    fi
+    real-usermode-helper /bin/false
+    cat "${DIR}/stub-ran"
+    [ "$status" -eq 3 ]
+    for set in CapInh CapPrm CapEff CapBnd CapAmb; do
+        grep -x "${set}:[[:space:]]*0000000000000000" "${DIR}/stub-ran"
+    done
+    grep -x "NoNewPrivs:[[:space:]]*1" "${DIR}/stub-ran"
+}
+
+@test "matched helpers don't run deny_stub" {
+    usermode-helper /bin/true
+    [ ! -e "${DIR}/stub-ran" ]
+}
+
+@test "deny_stub must be an absolute path" {
+    sed -i 's|^deny_stub = .*|deny_stub = "stub"|' "${TEMP_DIR}/usermode-helper.conf"
+    usermode-helper-fail 5 /bin/true
+    echo "$output" | grep "deny_stub stub isn't an absolute path"
+}
+
+@test "a deny_stub that can't run still denies" {
+    rm "${DIR}/stub"
+    real-usermode-helper /bin/false
+    [ "$status" -eq 9 ]
+    echo "$output" | grep "invalid usermode helper \"/bin/false\", running ${DIR}/stub"
+    echo "$output" | grep "couldn't run deny_stub ${DIR}/stub"
+}
@@ -11,7 +11,57 @@ load helpers
     # helper turns off dmesg logging.
     run bash -c "exec -a /bin/true \"$UMH_BIN\""
     echo "$output"
-    [ "$status" -eq 1 ]
+    [ "$status" -eq 5 ]
 
     dmesg | tail | grep "couldn't read config file ./usermode-helper.conf"
 }
+
+@test "kmsg messages get the priority of their category" {
+    if [ "$(id -u)" != "0" ]; then
+        skip "not root, can't test dmesg"
+    fi
+
+    : "${UMH_BIN:=${ROOT_DIR}/target/debug/usermode-helper}"
+    make_tempdir
+    DIR="$(realpath "${TEMP_DIR}")"
+    printf '#!/bin/sh\n' > "${DIR}/helper"
+    chmod 0755 "${DIR}/helper"
+    cat <<EOF > "${DIR}/usermode-helper.conf"
+log_success = true
+secure_boot_unknown = "allow"
+secure_boot_var = "${DIR}/missing"
+kmsg_priority = { error = 2, warn = 3, debug = 6, audit = 4 }
+
+[[helpers]]
+path = "${DIR}/helper"
+require_secure_boot = true
+EOF
+
+    cd "${DIR}"
+    run env HULDUFOLK_DEBUG=kmsg bash -c "exec -a ${DIR}/helper \"$UMH_BIN\""
+    echo "$output"
+    [ "$status" -eq 0 ]
+    run bash -c "exec -a ${DIR}/other \"$UMH_BIN\""
+    [ "$status" -eq 1 ]
+    cd - && cleanup
+
+    run bash -c "dmesg --decode | grep '${DIR}'"
+    echo "$output"
+    echo "$output" | grep -E "^user *:err *: \[.*\] WARNING: secure boot state is unknown, allowing ${DIR}/helper"
+    echo "$output" | grep -E "^user *:info *: \[.*\] -- DEBUG CAPS for ${DIR}/helper --"
+    echo "$output" | grep -E "^user *:warn *: \[.*\] huldufolk: allowed ${DIR}/helper argc=1"
+    echo "$output" | grep -E "^user *:crit *: \[.*\] ERROR: invalid usermode helper \"${DIR}/other\""
+}
+
+@test "kmsg_priority values are syslog priorities" {
+    make_tempdir
+    cat <<EOF > "${TEMP_DIR}/usermode-helper.conf"
+kmsg_priority = { error = 8 }
+
+[[helpers]]
+path = "/bin/true"
+EOF
+    usermode-helper-fail 5 /bin/true
+    cleanup
+    echo "$output" | grep "kmsg_priority values must be between 0 and 7"
+}
@@ -0,0 +1,191 @@
+load helpers
+
+function setup() {
+    make_tempdir
+    DROPINS="${TEMP_DIR}/usermode-helper.conf.d"
+    mkdir "${DROPINS}"
+    cat <<EOF > "${TEMP_DIR}/usermode-helper.conf"
+[[helpers]]
+path = "/bin/false"
+EOF
+}
+
+function teardown() {
+    cleanup
+}
+
+function add_dropin() {
+    mkdir -p "$(dirname "$1")"
+    cat <<EOF > "$1"
+[[helpers]]
+path = "$2"
+EOF
+}
+
+@test "drop-ins add helpers" {
+    add_dropin "${DROPINS}/true.conf" /bin/true
+    usermode-helper /bin/true
+    usermode-helper-fail 1 /bin/false
+}
+
+@test "only .conf files are drop-ins" {
+    add_dropin "${DROPINS}/true.conf.disabled" /bin/true
+    usermode-helper-deny /bin/true
+}
+
+@test "drop-ins can't change global settings" {
+    cat <<EOF > "${DROPINS}/global.conf"
+killswitch = "${TEMP_DIR}/killswitch"
+EOF
+    usermode-helper-fail 5 /bin/false
+    echo "$output" | grep "couldn't parse config file .*/global.conf"
+}
+
+@test "drop-ins are subject to permission checks" {
+    add_dropin "${DROPINS}/true.conf" /bin/true
+    chmod 0666 "${DROPINS}/true.conf"
+    usermode-helper-fail 5 /bin/true
+    echo "$output" | grep "refusing to trust config file .*/true.conf: mode 666 is group/world-writable"
+}
+
+@test "drop-in permissions are checked before it's parsed" {
+    echo "not toml [" > "${DROPINS}/bad.conf"
+    chmod 0666 "${DROPINS}/bad.conf"
+    usermode-helper-fail 5 /bin/true
+    echo "$output" | grep "refusing to trust config file .*/bad.conf"
+    ! echo "$output" | grep "couldn't parse"
+}
+
+@test "invalid drop-in helpers name their file" {
+    cat <<EOF > "${DROPINS}/bad.conf"
+[[helpers]]
+path = "/bin/true"
+workdir = "relative"
+EOF
+    usermode-helper-fail 5 /bin/true
+    echo "$output" | grep "invalid helper /bin/true in .*/bad.conf: workdir relative isn't an absolute path"
+}
+
+@test "max_dropin_files bounds the number of drop-ins" {
+    for helper in true echo printf; do
+        add_dropin "${DROPINS}/${helper}.conf" "/bin/${helper}"
+    done
+    # Top-level keys have to come before any table.
+    sed -i '1i max_dropin_files = 2' "${TEMP_DIR}/usermode-helper.conf"
+    usermode-helper-fail 5 /bin/true
+    echo "$output" | grep "too many drop-in files in .*usermode-helper.conf.d (max_dropin_files is 2)"
+
+    sed -i 's/max_dropin_files = 2/max_dropin_files = 3/' "${TEMP_DIR}/usermode-helper.conf"
+    usermode-helper /bin/true
+}
+
+@test "subdirectories are only read with recursive_dropins" {
+    add_dropin "${DROPINS}/sub/true.conf" /bin/true
+    usermode-helper-deny /bin/true
+
+    sed -i '1i recursive_dropins = true' "${TEMP_DIR}/usermode-helper.conf"
+    usermode-helper /bin/true
+}
+
+@test "max_dropin_files counts drop-ins in subdirectories" {
+    add_dropin "${DROPINS}/a.conf" /bin/true
+    add_dropin "${DROPINS}/sub/b.conf" /bin/true
+    sed -i '1i recursive_dropins = true\nmax_dropin_files = 1' "${TEMP_DIR}/usermode-helper.conf"
+    usermode-helper-fail 5 /bin/true
+    echo "$output" | grep "too many drop-in files"
+}
+
+@test "helpers defined in two files are an error by default" {
+    add_dropin "${DROPINS}/false.conf" /bin/false
+    usermode-helper-fail 5 /bin/false
+    echo "$output" | grep "helper /bin/false is defined in both .*/usermode-helper.conf and .*/usermode-helper.conf.d/false.conf"
+
+    # So are two drop-ins defining the same helper.
+    sed -i 's|^path = "/bin/false"$|path = "/bin/true"|' "${DROPINS}/false.conf"
+    add_dropin "${DROPINS}/true.conf" /bin/true
+    usermode-helper-fail 5 /bin/true
+    echo "$output" | grep "helper /bin/true is defined in both .*/false.conf and .*/true.conf"
+}
+
+@test "helpers with different argc don't conflict" {
+    cat <<EOF > "${DROPINS}/true.conf"
+[[helpers]]
+path = "/bin/true"
+argc = 1
+
+[[helpers]]
+path = "/bin/true"
+argc = 2
+EOF
+    cat <<EOF > "${DROPINS}/true-3.conf"
+[[helpers]]
+path = "/bin/true"
+argc = 3
+EOF
+    usermode-helper /bin/true one two
+}
+
+@test "a helper without argc conflicts with every argc" {
+    cat <<EOF > "${DROPINS}/true.conf"
+[[helpers]]
+path = "/bin/true"
+argc = 2
+EOF
+    cat <<EOF >> "${TEMP_DIR}/usermode-helper.conf"
+
+[[helpers]]
+path = "/bin/true"
+EOF
+    usermode-helper-fail 5 /bin/true one
+    echo "$output" | grep "helper /bin/true is defined in both .*/usermode-helper.conf and .*/true.conf"
+}
+
+@test "several entries for a helper in one file don't conflict" {
+    cat <<EOF > "${DROPINS}/true.conf"
+[[helpers]]
+path = "/bin/true"
+args_template = ["a"]
+
+[[helpers]]
+path = "/bin/true"
+args_template = ["b"]
+EOF
+    usermode-helper /bin/true a
+    usermode-helper /bin/true b
+}
+
+@test "duplicate_policy = first_wins keeps the earlier file's helper" {
+    sed -i '1i duplicate_policy = "first_wins"' "${TEMP_DIR}/usermode-helper.conf"
+    cat <<EOF >> "${TEMP_DIR}/usermode-helper.conf"
+
+[[helpers]]
+path = "/bin/true"
+args_template = ["first"]
+EOF
+    cat <<EOF > "${DROPINS}/true.conf"
+[[helpers]]
+path = "/bin/true"
+args_template = ["second"]
+EOF
+    usermode-helper /bin/true first
+    echo "$output" | grep "ignoring helper /bin/true in .*/true.conf, .*/usermode-helper.conf defines it too"
+    usermode-helper-deny /bin/true second
+}
+
+@test "duplicate_policy = last_wins keeps the later file's helper" {
+    sed -i '1i duplicate_policy = "last_wins"' "${TEMP_DIR}/usermode-helper.conf"
+    cat <<EOF >> "${TEMP_DIR}/usermode-helper.conf"
+
+[[helpers]]
+path = "/bin/true"
+args_template = ["first"]
+EOF
+    cat <<EOF > "${DROPINS}/true.conf"
+[[helpers]]
+path = "/bin/true"
+args_template = ["second"]
+EOF
+    usermode-helper /bin/true second
+    echo "$output" | grep "ignoring helper /bin/true in .*/usermode-helper.conf, .*/true.conf defines it too"
+    usermode-helper-deny /bin/true first
+}
@@ -0,0 +1,67 @@
+load helpers
+
+function setup() {
+    make_tempdir
+}
+
+function teardown() {
+    cleanup
+}
+
+function dump() {
+    : "${UMH_BIN:=${ROOT_DIR}/target/debug/usermode-helper}"
+    run "${UMH_BIN}" --dump "${TEMP_DIR}/usermode-helper.conf"
+    echo "$output"
+    [ "$status" -eq 0 ]
+}
+
+@test "--dump prints capabilities sorted and deduplicated" {
+    cat >> "${TEMP_DIR}/usermode-helper.conf" <<EOF
+[[helpers]]
+path = "/bin/true"
+capabilities = "CAP_SYS_ADMIN cap_net_admin,cap_sys_admin+eip"
+require_capabilities = "cap_chown"
+EOF
+    dump
+    echo "$output" | grep -x 'capabilities = "=cap_chown,cap_net_admin,cap_sys_admin"'
+    echo "$output" | grep "capability cap_sys_admin is listed more than once"
+}
+
+@test "a capability given by name and by number is a duplicate" {
+    cat >> "${TEMP_DIR}/usermode-helper.conf" <<EOF
+unknown_cap_policy = "numeric"
+
+[[helpers]]
+path = "/bin/true"
+capabilities = "cap_sys_admin,cap_21"
+EOF
+    dump
+    echo "$output" | grep -x 'capabilities = "=cap_sys_admin"'
+    echo "$output" | grep "capability cap_sys_admin is listed more than once"
+}
+
+@test "capabilities the ambient_denylist also denies are flagged" {
+    cat >> "${TEMP_DIR}/usermode-helper.conf" <<EOF
+ambient_denylist = "cap_sys_admin,cap_sys_module"
+
+[[helpers]]
+path = "/bin/true"
+capabilities = "cap_sys_module,cap_net_admin,cap_sys_admin"
+EOF
+    dump
+    echo "$output" | grep "helper /bin/true lists cap_sys_admin,cap_sys_module, which ambient_denylist also denies"
+}
+
+@test "--dump leaves helpers clear of the ambient_denylist alone" {
+    cat >> "${TEMP_DIR}/usermode-helper.conf" <<EOF
+ambient_denylist = "cap_sys_admin"
+
+[[helpers]]
+path = "/bin/true"
+capabilities = "cap_net_admin"
+EOF
+    dump
+    if echo "$output" | grep "WARNING"; then
+        echo "warned" && false
+    fi
+}
@@ -0,0 +1,147 @@
+load helpers
+
+function setup() {
+    make_tempdir
+    HELPER="$(realpath "${TEMP_DIR}")/check-env.sh"
+    cat <<EOF > "${HELPER}"
+#!/bin/sh
+[ "\$FSCK_DEVICE" = "/dev/sda1" ] || exit 1
+[ "\$HELPER_PATH" = "${HELPER}" ] || exit 1
+EOF
+    chmod +x "${HELPER}"
+}
+
+function teardown() {
+    cleanup
+}
+
+@test "env_from_args sets variables from arguments" {
+    cat <<EOF > "${TEMP_DIR}/usermode-helper.conf"
+[[helpers]]
+path = "${HELPER}"
+env_from_args = { FSCK_DEVICE = 1, HELPER_PATH = 0 }
+EOF
+    usermode-helper "${HELPER}" /dev/sda1
+}
+
+@test "env_from_args with the wrong argument fails in the helper" {
+    cat <<EOF > "${TEMP_DIR}/usermode-helper.conf"
+[[helpers]]
+path = "${HELPER}"
+env_from_args = { FSCK_DEVICE = 1, HELPER_PATH = 0 }
+EOF
+    usermode-helper-fail 1 "${HELPER}" /dev/sdb1
+}
+
+@test "env_from_args index beyond the invocation's arguments fails" {
+    cat <<EOF > "${TEMP_DIR}/usermode-helper.conf"
+[[helpers]]
+path = "${HELPER}"
+env_from_args = { FSCK_DEVICE = 3 }
+EOF
+    usermode-helper-fail 7 "${HELPER}" /dev/sda1
+    echo "$output" | grep "FSCK_DEVICE refers to missing argument 3"
+}
+
+@test "env_from_args index beyond argc is rejected at load" {
+    cat <<EOF > "${TEMP_DIR}/usermode-helper.conf"
+[[helpers]]
+path = "${HELPER}"
+argc = 2
+env_from_args = { FSCK_DEVICE = 2 }
+EOF
+    usermode-helper-fail 5 "${HELPER}" /dev/sda1
+    echo "$output" | grep "FSCK_DEVICE refers to argument 2 but argc is 2"
+}
+
+@test "env_from_args rejects bad variable names" {
+    cat <<EOF > "${TEMP_DIR}/usermode-helper.conf"
+[[helpers]]
+path = "${HELPER}"
+env_from_args = { "A=B" = 1 }
+EOF
+    usermode-helper-fail 5 "${HELPER}" /dev/sda1
+    echo "$output" | grep "bad environment variable name"
+}
+
+# Writes a script that succeeds iff PATH is exactly $2.
+function make_path_check() {
+    cat <<EOF > "$1"
+#!/bin/sh
+[ "\${PATH-unset}" = "$2" ]
+EOF
+    chmod +x "$1"
+}
+
+@test "helpers get the default sanitized PATH" {
+    CHECK="$(realpath "${TEMP_DIR}")/check-path.sh"
+    make_path_check "${CHECK}" "/sbin:/bin:/usr/sbin:/usr/bin"
+    cat <<EOF > "${TEMP_DIR}/usermode-helper.conf"
+[[helpers]]
+path = "${CHECK}"
+EOF
+    usermode-helper "${CHECK}"
+}
+
+@test "empty default_env_path reaches the helper" {
+    CHECK="$(realpath "${TEMP_DIR}")/check-path.sh"
+    make_path_check "${CHECK}" ""
+    cat <<EOF > "${TEMP_DIR}/usermode-helper.conf"
+default_env_path = ""
+
+[[helpers]]
+path = "${CHECK}"
+EOF
+    usermode-helper "${CHECK}"
+}
+
+@test "per-helper env_path overrides default_env_path" {
+    CHECK="$(realpath "${TEMP_DIR}")/check-path.sh"
+    make_path_check "${CHECK}" ""
+    cat <<EOF > "${TEMP_DIR}/usermode-helper.conf"
+default_env_path = "/opt/bin"
+
+[[helpers]]
+path = "${CHECK}"
+env_path = ""
+EOF
+    usermode-helper "${CHECK}"
+}
+
+@test "empty env_path requires absolute program paths" {
+    cat <<EOF > "${TEMP_DIR}/usermode-helper.conf"
+[[helpers]]
+path = "/bin/true"
+pre_exec_hook = "true"
+env_path = ""
+EOF
+    usermode-helper-fail 5 /bin/true
+    echo "$output" | grep "true must be an absolute path with an empty env_path"
+}
+
+@test "max_env_vars bounds the helper's final environment" {
+    # HOME, TERM and PATH, plus two from env_from_args.
+    cat <<EOF > "${TEMP_DIR}/usermode-helper.conf"
+[[helpers]]
+path = "${HELPER}"
+env_from_args = { FSCK_DEVICE = 1, HELPER_PATH = 0 }
+max_env_vars = 5
+EOF
+    usermode-helper "${HELPER}" /dev/sda1
+
+    sed -i 's/max_env_vars = 5/max_env_vars = 4/' "${TEMP_DIR}/usermode-helper.conf"
+    usermode-helper-fail 7 "${HELPER}" /dev/sda1
+    echo "$output" | grep "${HELPER} would get 5 environment variables, more than max_env_vars (4)"
+}
+
+@test "env entries count towards max_env_vars" {
+    cat <<EOF > "${TEMP_DIR}/usermode-helper.conf"
+[[helpers]]
+path = "/bin/true"
+env = { A = "1", B = "2", HOME = "/tmp" }
+max_env_vars = 4
+EOF
+    # HOME is overridden rather than added.
+    usermode-helper-fail 7 /bin/true
+    echo "$output" | grep "would get 5 environment variables"
+}
@@ -0,0 +1,121 @@
+load helpers
+
+function setup() {
+    make_tempdir
+    DIR="$(realpath "${TEMP_DIR}")"
+}
+
+function teardown() {
+    cleanup
+}
+
+# Configures /bin/true to exec $1 instead, with diagnostics on.
+function diagnose() {
+    cat <<EOF > "${TEMP_DIR}/usermode-helper.conf"
+[[helpers]]
+path = "/bin/true"
+exec_path = "$1"
+on_exec_failure = "diagnose"
+EOF
+}
+
+@test "failed execs only report the errno by default" {
+    cat <<EOF > "${TEMP_DIR}/usermode-helper.conf"
+[[helpers]]
+path = "/bin/true"
+exec_path = "${DIR}/missing"
+EOF
+    usermode-helper-fail 9 /bin/true
+    echo "$output" | grep "exec failed: No such file or directory (os error 2) (exit 9"
+}
+
+@test "a missing exec target is diagnosed" {
+    diagnose "${DIR}/missing"
+    usermode-helper-fail 9 /bin/true
+    echo "$output" | grep "exec failed: No such file or directory (os error 2); ${DIR}/missing doesn't exist (only ${DIR} does)"
+}
+
+@test "a missing directory is diagnosed" {
+    diagnose "${DIR}/sub/dir/helper"
+    usermode-helper-fail 9 /bin/true
+    echo "$output" | grep "${DIR}/sub/dir/helper doesn't exist (only ${DIR} does)"
+}
+
+@test "a dangling symlink is diagnosed" {
+    ln -s "${DIR}/missing" "${DIR}/link"
+    diagnose "${DIR}/link"
+    usermode-helper-fail 9 /bin/true
+    echo "$output" | grep "${DIR}/link is a dangling symlink to ${DIR}/missing"
+}
+
+@test "missing execute permission is diagnosed" {
+    printf '#!/bin/sh\ntrue\n' > "${DIR}/helper"
+    chmod 644 "${DIR}/helper"
+    diagnose "${DIR}/helper"
+    usermode-helper-fail 9 /bin/true
+    echo "$output" | grep "exec failed: Permission denied (os error 13); regular file, mode 0644, owner [0-9]*:[0-9]*; nobody may execute it; it's a script for \"/bin/sh\""
+}
+
+@test "a directory is diagnosed" {
+    mkdir "${DIR}/helper"
+    diagnose "${DIR}/helper"
+    usermode-helper-fail 9 /bin/true
+    echo "$output" | grep "exec failed: Permission denied (os error 13); directory, mode 0755"
+}
+
+@test "a file in no known format is diagnosed" {
+    echo "just text" > "${DIR}/helper"
+    diagnose "${DIR}/helper"
+    usermode-helper-fail 9 /bin/true
+    echo "$output" | grep "nobody may execute it; it's neither an ELF binary nor a #! script"
+}
+
+@test "an empty file is diagnosed" {
+    touch "${DIR}/helper"
+    diagnose "${DIR}/helper"
+    usermode-helper-fail 9 /bin/true
+    echo "$output" | grep "nobody may execute it; it's empty"
+}
+
+@test "a script's missing interpreter is diagnosed" {
+    printf '#!/nonexistent/sh -e\ntrue\n' > "${DIR}/helper"
+    chmod 755 "${DIR}/helper"
+    diagnose "${DIR}/helper"
+    usermode-helper-fail 9 /bin/true
+    echo "$output" | grep "exec failed: No such file or directory (os error 2); .*; it's a script for \"/nonexistent/sh\", which doesn't exist"
+}
+
+@test "DOS line endings in a script's #! line are diagnosed" {
+    printf '#!/bin/sh\r\ntrue\r\n' > "${DIR}/helper"
+    chmod 755 "${DIR}/helper"
+    diagnose "${DIR}/helper"
+    usermode-helper-fail 9 /bin/true
+    echo "$output" | grep -F "it's a script for \"/bin/sh\\r\", which doesn't exist"
+}
+
+@test "an ELF binary's missing interpreter is diagnosed" {
+    {
+        # A little-endian ELF64 file whose only program header is a PT_INTERP
+        # pointing at "/evil/ld.so" (see interp.bats).
+        printf '\x7fELF\x02\x01\x01\x00\x00\x00\x00\x00\x00\x00\x00\x00'
+        printf '\x02\x00\x3e\x00\x01\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00'
+        printf '\x40\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00'
+        printf '\x00\x00\x00\x00\x40\x00\x38\x00\x01\x00\x00\x00\x00\x00\x00\x00'
+        printf '\x03\x00\x00\x00\x04\x00\x00\x00\x78\x00\x00\x00\x00\x00\x00\x00'
+        printf '\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00'
+        printf '\x0c\x00\x00\x00\x00\x00\x00\x00\x0c\x00\x00\x00\x00\x00\x00\x00'
+        printf '\x01\x00\x00\x00\x00\x00\x00\x00'
+        printf '/evil/ld.so\x00'
+    } > "${DIR}/helper"
+    chmod 755 "${DIR}/helper"
+    diagnose "${DIR}/helper"
+    usermode-helper-fail 9 /bin/true
+    echo "$output" | grep "exec failed: .*; it's an ELF binary loaded by /evil/ld.so, which doesn't exist"
+}
+
+@test "supervised helpers are diagnosed too" {
+    diagnose "${DIR}/missing"
+    echo "run_timeout = 5" >> "${TEMP_DIR}/usermode-helper.conf"
+    usermode-helper-fail 9 /bin/true
+    echo "$output" | grep "${DIR}/missing doesn't exist"
+}
@@ -0,0 +1,85 @@
+load helpers
+
+function setup() {
+    make_tempdir
+    WRAPPER="$(realpath "${TEMP_DIR}")/wrapper.sh"
+    cat <<EOF > "${WRAPPER}"
+#!/bin/sh
+[ "\$*" = "one two" ]
+EOF
+    chmod +x "${WRAPPER}"
+}
+
+function teardown() {
+    cleanup
+}
+
+@test "exec_path runs the interposer instead of the helper" {
+    cat <<EOF > "${TEMP_DIR}/usermode-helper.conf"
+[[helpers]]
+path = "/bin/false"
+exec_path = "${WRAPPER}"
+EOF
+    usermode-helper /bin/false one two
+    echo "$output" | grep "/bin/false will exec ${WRAPPER}"
+    if echo "$output" | grep "incoherent exec"; then
+        echo "coherent config flagged" && false
+    fi
+}
+
+@test "plain helpers are coherent" {
+    cat <<EOF > "${TEMP_DIR}/usermode-helper.conf"
+[[helpers]]
+path = "/bin/true"
+EOF
+    usermode-helper /bin/true
+    if echo "$output" | grep "incoherent exec"; then
+        echo "coherent config flagged" && false
+    fi
+}
+
+@test "exec_path resolving to the helper itself is flagged" {
+    ln -s /bin/true "${TEMP_DIR}/not-a-wrapper"
+    cat <<EOF > "${TEMP_DIR}/usermode-helper.conf"
+[[helpers]]
+path = "/bin/true"
+exec_path = "$(realpath -s "${TEMP_DIR}")/not-a-wrapper"
+EOF
+    usermode-helper /bin/true
+    echo "$output" | grep "WARNING: incoherent exec for /bin/true: exec_path .*/not-a-wrapper resolves to .*true itself"
+
+    sed -i '1i exec_coherence = "deny"' "${TEMP_DIR}/usermode-helper.conf"
+    usermode-helper-fail 7 /bin/true
+    echo "$output" | grep "ERROR: incoherent exec for /bin/true"
+
+    sed -i 's/exec_coherence = "deny"/exec_coherence = "ignore"/' "${TEMP_DIR}/usermode-helper.conf"
+    usermode-helper /bin/true
+    if echo "$output" | grep "incoherent exec"; then
+        echo "ignored policy still flagged" && false
+    fi
+}
+
+@test "missing exec target is flagged" {
+    cat <<EOF > "${TEMP_DIR}/usermode-helper.conf"
+exec_coherence = "deny"
+
+[[helpers]]
+path = "/bin/true"
+exec_path = "/nonexistent/wrapper"
+EOF
+    usermode-helper-fail 7 /bin/true
+    echo "$output" | grep "incoherent exec for /bin/true: exec target /nonexistent/wrapper doesn't resolve"
+    if echo "$output" | grep "DEBUG CAPS"; then
+        echo "denied helper got to run" && false
+    fi
+}
+
+@test "relative exec_path is rejected" {
+    cat <<EOF > "${TEMP_DIR}/usermode-helper.conf"
+[[helpers]]
+path = "/bin/true"
+exec_path = "wrapper.sh"
+EOF
+    usermode-helper-fail 5 /bin/true
+    echo "$output" | grep "exec_path wrapper.sh isn't an absolute path"
+}
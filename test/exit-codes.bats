@@ -0,0 +1,75 @@
+load helpers
+
+function setup() {
+    make_tempdir
+}
+
+function teardown() {
+    cleanup
+}
+
+function exit_codes() {
+    : "${UMH_BIN:=${ROOT_DIR}/target/debug/usermode-helper}"
+    run "${UMH_BIN}" --exit-codes
+    echo "$output"
+    [ "$status" -eq 0 ]
+}
+
+@test "--exit-codes prints the exit code table" {
+    exit_codes
+    echo "$output" | grep -xP "1\tdenied\t.+"
+    echo "$output" | grep -xP "4\tunderprivileged\t.+"
+    echo "$output" | grep -xP "5\tconfig\t.+"
+    echo "$output" | grep -xP "13\tintegrity\t.+"
+    echo "$output" | grep -xP "14\tpolicy\t.+"
+    # Codes and names are unique.
+    [ -z "$(echo "$output" | cut -f1 | sort | uniq -d)" ]
+    [ -z "$(echo "$output" | cut -f2 | sort | uniq -d)" ]
+}
+
+@test "exits use the code for their cause" {
+    usermode-helper-fail 5 /bin/true
+    echo "$output" | grep "couldn't read config file .* (exit 5: "
+
+    make_passwd
+    cat <<EOF > "${TEMP_DIR}/usermode-helper.conf"
+passwd_file = "${PASSWD}"
+
+[[helpers]]
+path = "/bin/true"
+pre_exec_hook = "/bin/false"
+user = "hookuser"
+
+[[helpers]]
+path = "/bin/sleep"
+pre_exec_hook = "/bin/sleep"
+user = "hookuser"
+setup_timeout = 1
+EOF
+    usermode-helper-deny /bin/false
+    echo "$output" | grep "(exit 1: "
+    usermode-helper-fail 7 /bin/true
+    usermode-helper-fail 8 /bin/sleep 3
+    echo "$output" | grep "setup timeout exceeded (exit 8: "
+}
+
+@test "every exit uses a code from the table" {
+    exit_codes
+    table="$output"
+
+    # Exits go through exit::Exit (clippy.toml forbids std::process::exit
+    # elsewhere). The only other ones are the passthrough of a supervised
+    # helper's status and _exit()s where Exit can't be formatted.
+    run grep -rnE '\bexit\(|_exit\(' "${ROOT_DIR}/src"
+    echo "$output"
+    if echo "$output" | grep -vE 'fn exit\(self\)|\.exit\(\)|std::process::exit\((self\.code\(\)|exit_code\(status\))\)|_exit\((Exit::[A-Za-z]+\.code\(\)|0)\)'; then
+        echo "exit with a code outside the table" && false
+    fi
+
+    # And every Exit variant used is listed.
+    for name in $(grep -ohE 'Exit::[A-Z][A-Za-z]+' "${ROOT_DIR}"/src/*.rs | sort -u | cut -d: -f3); do
+        [ "$name" = "ALL" ] && continue
+        code=$(grep -oE "^ *${name} = [0-9]+" "${ROOT_DIR}/src/exit.rs" | grep -oE '[0-9]+$')
+        echo "$table" | grep -P "^${code}\t"
+    done
+}
@@ -0,0 +1,73 @@
+load helpers
+
+function setup() {
+    make_tempdir
+}
+
+function teardown() {
+    cleanup
+}
+
+function export_policy() {
+    : "${UMH_BIN:=${ROOT_DIR}/target/debug/usermode-helper}"
+    run "${UMH_BIN}" --export-policy "${TEMP_DIR}/usermode-helper.conf"
+    echo "$output"
+    [ "$status" -eq 0 ]
+}
+
+@test "--export-policy lists helpers and their capabilities" {
+    cat >> "${TEMP_DIR}/usermode-helper.conf" <<EOF
+[[helpers]]
+path = "/bin/true"
+capabilities = "cap_sys_admin,cap_net_admin"
+
+[[helpers]]
+path = "/bin/false"
+capabilities = "cap_chown"
+raise_ambient = false
+EOF
+    export_policy
+    echo "$output" | jq -e '.version == 1'
+    echo "$output" | jq -e '.helpers[] | select(.path == "/bin/true")
+        | .capabilities == ["cap_net_admin", "cap_sys_admin"]
+          and .ambient == ["cap_net_admin", "cap_sys_admin"]
+          and .unrestricted == false'
+    echo "$output" | jq -e '.helpers[] | select(.path == "/bin/false")
+        | .capabilities == ["cap_chown"] and .ambient == []'
+}
+
+@test "--export-policy marks helpers without capabilities unrestricted" {
+    cat >> "${TEMP_DIR}/usermode-helper.conf" <<EOF
+[[helpers]]
+path = "/bin/false"
+EOF
+    export_policy
+    echo "$output" | jq -e '.helpers[] | select(.path == "/bin/false")
+        | .unrestricted and (.capabilities | index("cap_sys_module") != null)'
+}
+
+@test "--export-policy includes path patterns and the deny stub" {
+    cat >> "${TEMP_DIR}/usermode-helper.conf" <<EOF
+deny_stub = "/bin/false"
+
+[[helpers]]
+path_prefix = "/sbin/modprobe"
+capabilities = "cap_sys_module"
+EOF
+    export_policy
+    echo "$output" | jq -e '.deny_stub == "/bin/false"'
+    echo "$output" | jq -e '.helpers[] | select(.path_prefix == "/sbin/modprobe")
+        | .path == null and .capabilities == ["cap_sys_module"]'
+}
+
+@test "--export-policy output is stable" {
+    cat >> "${TEMP_DIR}/usermode-helper.conf" <<EOF
+[[helpers]]
+path = "/bin/true"
+capabilities = "cap_sys_admin,cap_net_admin,cap_chown,cap_kill"
+EOF
+    export_policy
+    first="$output"
+    export_policy
+    [ "$output" = "$first" ]
+}
@@ -2,6 +2,8 @@ ROOT_DIR=$(git rev-parse --show-toplevel)
 
 function make_tempdir()
 {
+    # usermode-helper refuses group/world-writable configs by default.
+    umask 022
     declare -g TEMP_DIR=$(mktemp -d huldufolk-test.XXXXXXXX)
 }
 
@@ -17,8 +19,9 @@ function real-usermode-helper {
 
     # We always run the helper in a user namespace, that way we can check
     # capabilities stuff without having root. It doesn't hurt anything else
-    # (except for dmesg, which we have a special test for anyway).
-    run unshare -Uur bash -c "HULDUFOLK_DEBUG=1 exec -a $1 \"$UMH_BIN\" $args"
+    # (except for dmesg, which we have a special test for anyway). UMH_PREFIX
+    # can wrap it further, e.g. to run it with fewer privileges.
+    run unshare -Uur ${UMH_PREFIX} bash -c "HULDUFOLK_DEBUG=1 exec -a $1 \"$UMH_BIN\" $args"
     popd
     echo "$output"
 }
@@ -36,12 +39,26 @@ function usermode-helper-deny {
     [ "$status" -eq 1 ]
 }
 
+# The first argument is the expected status: one of our exit codes (see
+# --exit-codes), or the helper's own when it ran and failed.
 function usermode-helper-fail {
-    real-usermode-helper "$@"
+    expected="$1"
+    real-usermode-helper "${@:2}"
     if echo "$output" | grep 'invalid usermode helper'; then
         echo "failed, but was denied" && false
     fi
-    [ "$status" -eq 1 ]
+    [ "$status" -eq "$expected" ]
+}
+
+# Writes a passwd fixture whose "hookuser" is uid 0, the only uid mapped in
+# the user namespace the tests run in, for hooks that have to be able to switch
+# to the helper's user.
+function make_passwd() {
+    declare -g PASSWD="$(realpath "${TEMP_DIR}")/passwd"
+    cat <<EOF > "${PASSWD}"
+root:x:0:0:root:/root:/bin/sh
+hookuser:x:0:0:Hook:/:/usr/sbin/nologin
+EOF
 }
 
 function cleanup {
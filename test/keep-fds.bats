@@ -0,0 +1,88 @@
+load helpers
+
+function setup() {
+    make_tempdir
+    HELPER="$(realpath "${TEMP_DIR}")/check-fds.sh"
+    # Arguments are fds the helper must have open, or, prefixed with "-",
+    # must not.
+    cat <<EOF > "${HELPER}"
+#!/bin/sh
+for fd in "\$@"; do
+    case "\$fd" in
+    -*) [ ! -e "/proc/self/fd/\${fd#-}" ] || exit 1 ;;
+    *) [ -e "/proc/self/fd/\$fd" ] || exit 1 ;;
+    esac
+done
+EOF
+    chmod +x "${HELPER}"
+    exec 6</dev/null 7</dev/null 8</dev/null
+}
+
+function teardown() {
+    exec 6<&- 7<&- 8<&-
+    cleanup
+}
+
+@test "inherited fds are closed by default" {
+    cat <<EOF > "${TEMP_DIR}/usermode-helper.conf"
+[[helpers]]
+path = "${HELPER}"
+EOF
+    usermode-helper "${HELPER}" -6 -7 -8
+}
+
+@test "keep_fds keeps fds in the middle of the sweep" {
+    cat <<EOF > "${TEMP_DIR}/usermode-helper.conf"
+[[helpers]]
+path = "${HELPER}"
+keep_fds = [7]
+EOF
+    usermode-helper "${HELPER}" -6 7 -8
+}
+
+@test "close_fds_from keeps everything below it" {
+    cat <<EOF > "${TEMP_DIR}/usermode-helper.conf"
+[[helpers]]
+path = "${HELPER}"
+close_fds_from = 8
+EOF
+    usermode-helper "${HELPER}" 6 7 -8
+}
+
+@test "keep_fds can't include stdio" {
+    cat <<EOF > "${TEMP_DIR}/usermode-helper.conf"
+[[helpers]]
+path = "${HELPER}"
+keep_fds = [2]
+EOF
+    usermode-helper-fail 5 "${HELPER}"
+    echo "$output" | grep "fd 2 is out of range"
+}
+
+@test "keep_fds can't go above 1023" {
+    cat <<EOF > "${TEMP_DIR}/usermode-helper.conf"
+[[helpers]]
+path = "${HELPER}"
+keep_fds = [1024]
+EOF
+    usermode-helper-fail 5 "${HELPER}"
+    echo "$output" | grep "fd 1024 is out of range, keep_fds and close_fds_from go from 3 to 1023"
+}
+
+@test "--as closes inherited fds too" {
+    : "${UMH_BIN:=${ROOT_DIR}/target/debug/usermode-helper}"
+    run "${UMH_BIN}" --as
+    if [ "$status" -ne 11 ]; then
+        skip "built without the test-mode feature"
+    fi
+    cat <<EOF > "${TEMP_DIR}/usermode-helper.conf"
+[[helpers]]
+path = "${HELPER}"
+keep_fds = [7]
+EOF
+    pushd "${TEMP_DIR}"
+    run unshare -Uur bash -c "HULDUFOLK_DEBUG=1 \"${UMH_BIN}\" --as ${HELPER} -- -6 7 -8"
+    popd
+    echo "$output"
+    [ "$status" -eq 0 ]
+}
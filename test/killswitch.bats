@@ -0,0 +1,45 @@
+load helpers
+
+function setup() {
+    make_tempdir
+    cat <<EOF > "${TEMP_DIR}/usermode-helper.conf"
+killswitch = "$(realpath "${TEMP_DIR}")/killswitch"
+
+[[helpers]]
+path = "/bin/true"
+EOF
+}
+
+function teardown() {
+    cleanup
+}
+
+@test "absent killswitch allows helpers" {
+    usermode-helper /bin/true
+}
+
+@test "present killswitch denies a normally-allowed helper" {
+    touch "${TEMP_DIR}/killswitch"
+    usermode-helper-fail 1 /bin/true
+    echo "$output" | grep "killswitch .* is active, denying all helpers"
+}
+
+@test "dangling killswitch symlink counts as present" {
+    ln -s does-not-exist "${TEMP_DIR}/killswitch"
+    usermode-helper-fail 1 /bin/true
+    echo "$output" | grep "killswitch .* is active, denying all helpers"
+}
+
+@test "configured killswitch is checked before the config hash" {
+    sed -i '1i require_config_hash = true' "${TEMP_DIR}/usermode-helper.conf"
+    touch "${TEMP_DIR}/killswitch"
+    usermode-helper-fail 1 /bin/true
+    echo "$output" | grep "killswitch .* is active, denying all helpers"
+}
+
+@test "built-in killswitch works even with a broken config" {
+    echo "this isn't toml" > "${TEMP_DIR}/usermode-helper.conf"
+    touch "${TEMP_DIR}/usermode-helper.killswitch"
+    usermode-helper-fail 1 /bin/true
+    echo "$output" | grep "killswitch .*usermode-helper.killswitch is active, denying all helpers"
+}
@@ -0,0 +1,94 @@
+load helpers
+
+function setup() {
+    make_tempdir
+}
+
+function teardown() {
+    cleanup
+}
+
+@test "match_env exact value matches" {
+    cat <<EOF > "${TEMP_DIR}/usermode-helper.conf"
+[[helpers]]
+path = "/bin/true"
+match_env = { ACTION = "add" }
+EOF
+    export ACTION=add
+    usermode-helper /bin/true
+}
+
+@test "match_env exact value mismatch is denied" {
+    cat <<EOF > "${TEMP_DIR}/usermode-helper.conf"
+[[helpers]]
+path = "/bin/true"
+match_env = { ACTION = "add" }
+EOF
+    export ACTION=remove
+    usermode-helper-deny /bin/true
+}
+
+@test "match_env regex matches the whole value" {
+    cat <<EOF > "${TEMP_DIR}/usermode-helper.conf"
+[[helpers]]
+path = "/bin/true"
+match_env = { DEVPATH = { regex = "/devices/pci[0-9:.]+/.*" } }
+EOF
+    export DEVPATH=/devices/pci0000:00/0000:00:1f.2
+    usermode-helper /bin/true
+
+    export DEVPATH=/evil/devices/pci0000:00/0000:00:1f.2
+    usermode-helper-deny /bin/true
+}
+
+@test "match_env selects between helper entries" {
+    cat <<EOF > "${TEMP_DIR}/usermode-helper.conf"
+[[helpers]]
+path = "/bin/true"
+match_env = { ACTION = "remove" }
+capabilities = "="
+
+[[helpers]]
+path = "/bin/true"
+match_env = { ACTION = "add" }
+capabilities = "cap_sys_admin"
+EOF
+    export ACTION=add
+    usermode-helper /bin/true
+    echo "$output" | grep "^Permitted: {CAP_SYS_ADMIN}$"
+}
+
+@test "match_env absent variable is denied by default" {
+    cat <<EOF > "${TEMP_DIR}/usermode-helper.conf"
+[[helpers]]
+path = "/bin/true"
+match_env = { ACTION = "add" }
+EOF
+    unset ACTION
+    usermode-helper-deny /bin/true
+}
+
+@test "match_env_absent = ignore skips absent variables" {
+    cat <<EOF > "${TEMP_DIR}/usermode-helper.conf"
+[[helpers]]
+path = "/bin/true"
+match_env = { ACTION = "add" }
+match_env_absent = "ignore"
+EOF
+    unset ACTION
+    usermode-helper /bin/true
+
+    export ACTION=remove
+    usermode-helper-deny /bin/true
+}
+
+@test "match_env rejects bad regexes at load" {
+    cat <<EOF > "${TEMP_DIR}/usermode-helper.conf"
+[[helpers]]
+path = "/bin/true"
+match_env = { ACTION = { regex = "(" } }
+EOF
+    export ACTION=add
+    usermode-helper-fail 5 /bin/true
+    echo "$output" | grep "bad regex ("
+}
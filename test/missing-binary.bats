@@ -0,0 +1,58 @@
+load helpers
+
+function setup() {
+    make_tempdir
+}
+
+function teardown() {
+    cleanup
+}
+
+@test "a missing binary fails to exec by default" {
+    cat <<EOF > "${TEMP_DIR}/usermode-helper.conf"
+[[helpers]]
+path = "/nonexistent/helper"
+EOF
+    usermode-helper-fail 9 /nonexistent/helper
+}
+
+@test "missing_binary_policy = skip falls through to the next helper" {
+    cat <<EOF > "${TEMP_DIR}/usermode-helper.conf"
+[[helpers]]
+path = "/bin/true"
+exec_path = "/nonexistent/true"
+missing_binary_policy = "skip"
+capabilities = "cap_chown"
+
+[[helpers]]
+path = "/bin/true"
+capabilities = "cap_sys_admin"
+EOF
+    usermode-helper /bin/true
+    echo "$output" | grep 'skipping helper /bin/true, "/nonexistent/true" doesn'"'"'t exist (missing_binary_policy)'
+    echo "$output" | grep "^Permitted: {CAP_SYS_ADMIN}$"
+}
+
+@test "missing_binary_policy = skip denies without another helper" {
+    cat <<EOF > "${TEMP_DIR}/usermode-helper.conf"
+[[helpers]]
+path = "/nonexistent/helper"
+missing_binary_policy = "skip"
+EOF
+    usermode-helper-deny /nonexistent/helper
+}
+
+@test "missing_binary_policy = skip matches helpers that exist" {
+    cat <<EOF > "${TEMP_DIR}/usermode-helper.conf"
+[[helpers]]
+path = "/bin/true"
+missing_binary_policy = "skip"
+capabilities = "cap_chown"
+
+[[helpers]]
+path = "/bin/true"
+capabilities = "cap_sys_admin"
+EOF
+    usermode-helper /bin/true
+    echo "$output" | grep "^Permitted: {CAP_CHOWN}$"
+}
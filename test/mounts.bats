@@ -0,0 +1,67 @@
+load helpers
+
+function setup() {
+    make_tempdir
+    DIR="$(realpath "${TEMP_DIR}")"
+    mkdir "${DIR}/source" "${DIR}/target" "${DIR}/elsewhere"
+    touch "${DIR}/source/marker"
+    cat <<EOF > "${DIR}/helper"
+#!/bin/sh
+[ -e "${DIR}/target/marker" ] && touch "${DIR}/saw-marker"
+touch "${DIR}/target/written" && touch "${DIR}/wrote"
+touch "${DIR}/ran"
+EOF
+    chmod 0755 "${DIR}/helper"
+}
+
+function teardown() {
+    cleanup
+}
+
+function config() {
+    cat <<EOF > "${TEMP_DIR}/usermode-helper.conf"
+[[helpers]]
+path = "${DIR}/helper"
+mount_namespace = true
+verify_mounts = true
+bind_mounts = [{ source = "${DIR}/source", target = "$1", read_only = ${2:-false} }]
+EOF
+}
+
+@test "bind mounts are set up in the helper's mount namespace" {
+    config "${DIR}/target"
+    usermode-helper "${DIR}/helper"
+    echo "$output" | grep "verified mount namespace for ${DIR}/helper"
+    [ -e "${DIR}/saw-marker" ]
+    [ -e "${DIR}/source/written" ]
+    # Nothing leaks out of the namespace.
+    [ ! -e "${DIR}/target/marker" ]
+}
+
+@test "read-only bind mounts are read-only" {
+    config "${DIR}/target" true
+    usermode-helper "${DIR}/helper"
+    [ -e "${DIR}/saw-marker" ]
+    [ ! -e "${DIR}/wrote" ]
+}
+
+@test "verify_mounts catches mounts that didn't end up where configured" {
+    # The mount follows the symlink, so it succeeds, but not at the target.
+    ln -s elsewhere "${DIR}/link"
+    config "${DIR}/link"
+    usermode-helper-fail 6 "${DIR}/helper"
+    echo "$output" | grep "mount namespace for ${DIR}/helper isn't as configured: nothing is mounted at ${DIR}/link"
+    [ ! -e "${DIR}/ran" ]
+
+    # Without verify_mounts, the helper runs regardless.
+    sed -i '/verify_mounts/d' "${TEMP_DIR}/usermode-helper.conf"
+    usermode-helper "${DIR}/helper"
+    [ -e "${DIR}/ran" ]
+}
+
+@test "bind_mounts require mount_namespace" {
+    config "${DIR}/target"
+    sed -i '/mount_namespace/d' "${TEMP_DIR}/usermode-helper.conf"
+    usermode-helper-fail 5 "${DIR}/helper"
+    echo "$output" | grep "bind_mounts and verify_mounts require mount_namespace"
+}
@@ -0,0 +1,113 @@
+load helpers
+
+function setup() {
+    make_tempdir
+    DIR="$(realpath "${TEMP_DIR}")"
+    cat <<EOF > "${DIR}/chatty"
+#!/bin/sh
+echo "first line"
+echo "oops" >&2
+printf "no newline"
+EOF
+    printf '#!/bin/sh\necho 0123456789abcdef\necho more\n' > "${DIR}/big"
+    printf '#!/bin/sh\necho bye\nexit 3\n' > "${DIR}/failing"
+    printf '#!/bin/sh\necho started\nsleep 30\n' > "${DIR}/slow"
+    chmod 0755 "${DIR}/chatty" "${DIR}/big" "${DIR}/failing" "${DIR}/slow"
+}
+
+function teardown() {
+    cleanup
+}
+
+@test "captured output is logged line by line to output_log" {
+    cat <<EOF > "${TEMP_DIR}/usermode-helper.conf"
+[[helpers]]
+path = "${DIR}/chatty"
+run_timeout = 10
+capture_output = true
+output_log = "${DIR}/output.log"
+EOF
+    usermode-helper "${DIR}/chatty"
+    cat "${DIR}/output.log"
+    grep -E '^[0-9]+ \[stdout\] first line$' "${DIR}/output.log"
+    grep -E '^[0-9]+ \[stderr\] oops$' "${DIR}/output.log"
+    grep -E '^[0-9]+ \[stdout\] no newline$' "${DIR}/output.log"
+    [ "$(stat -c %a "${DIR}/output.log")" = 600 ]
+}
+
+@test "captured output goes to the audit log without an output_log" {
+    cat <<EOF > "${TEMP_DIR}/usermode-helper.conf"
+audit_log = "${DIR}/audit.log"
+
+[[helpers]]
+path = "${DIR}/chatty"
+run_timeout = 10
+capture_output = true
+EOF
+    usermode-helper "${DIR}/chatty"
+    cat "${DIR}/audit.log"
+    grep -E "^\{\"time\":[0-9]+,\"helper\":\"${DIR}/chatty\",\"output\":\"\[stdout\] first line\"\}$" "${DIR}/audit.log"
+    grep "\"output\":\"\[stderr\] oops\"" "${DIR}/audit.log"
+}
+
+@test "captured output is bounded by max_output_bytes" {
+    cat <<EOF > "${TEMP_DIR}/usermode-helper.conf"
+[[helpers]]
+path = "${DIR}/big"
+run_timeout = 10
+capture_output = true
+output_log = "${DIR}/output.log"
+max_output_bytes = 10
+EOF
+    usermode-helper "${DIR}/big"
+    cat "${DIR}/output.log"
+    grep -E '^[0-9]+ \[stdout\] 0123456789$' "${DIR}/output.log"
+    grep -E '^[0-9]+ \[huldufolk\] output truncated after 10 bytes$' "${DIR}/output.log"
+    if grep "more" "${DIR}/output.log"; then
+        echo "logged past the limit" && false
+    fi
+}
+
+@test "the helper's status is passed through with capture_output" {
+    cat <<EOF > "${TEMP_DIR}/usermode-helper.conf"
+[[helpers]]
+path = "${DIR}/failing"
+run_timeout = 10
+capture_output = true
+output_log = "${DIR}/output.log"
+EOF
+    usermode-helper-fail 3 "${DIR}/failing"
+    grep -E '^[0-9]+ \[stdout\] bye$' "${DIR}/output.log"
+}
+
+@test "run_timeout still applies with capture_output" {
+    cat <<EOF > "${TEMP_DIR}/usermode-helper.conf"
+[[helpers]]
+path = "${DIR}/slow"
+run_timeout = 1
+capture_output = true
+output_log = "${DIR}/output.log"
+EOF
+    usermode-helper-fail 10 "${DIR}/slow"
+    grep -E '^[0-9]+ \[stdout\] started$' "${DIR}/output.log"
+}
+
+@test "capture_output requires run_timeout and somewhere to log" {
+    cat <<EOF > "${TEMP_DIR}/usermode-helper.conf"
+[[helpers]]
+path = "${DIR}/chatty"
+capture_output = true
+output_log = "${DIR}/output.log"
+EOF
+    usermode-helper-fail 5 "${DIR}/chatty"
+    echo "$output" | grep "capture_output requires run_timeout"
+
+    cat <<EOF > "${TEMP_DIR}/usermode-helper.conf"
+[[helpers]]
+path = "${DIR}/chatty"
+run_timeout = 10
+capture_output = true
+EOF
+    usermode-helper-fail 5 "${DIR}/chatty"
+    echo "$output" | grep "capture_output requires output_log or audit_log"
+}
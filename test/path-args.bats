@@ -0,0 +1,45 @@
+load helpers
+
+function setup() {
+    make_tempdir
+}
+
+function teardown() {
+    cleanup
+}
+
+@test "path arguments have to be under allowed_path_prefixes" {
+    cat <<EOF > "${TEMP_DIR}/usermode-helper.conf"
+[[helpers]]
+path = "/bin/true"
+allowed_path_prefixes = ["/lib/firmware", "/run/foo/"]
+EOF
+    usermode-helper /bin/true /lib/firmware/a.bin
+    usermode-helper /bin/true -v /run/foo/x /lib/firmware
+    usermode-helper /bin/true relative/path
+    usermode-helper-deny /bin/true /etc/shadow
+    usermode-helper-deny /bin/true /lib/firmware/a.bin /etc/shadow
+    usermode-helper-deny /bin/true /lib/firmwarex/a.bin
+    usermode-helper-deny /bin/true /lib/firmware/../../etc/shadow
+}
+
+@test "path_check_exempt_args skips arguments that aren't paths" {
+    cat <<EOF > "${TEMP_DIR}/usermode-helper.conf"
+[[helpers]]
+path = "/bin/true"
+allowed_path_prefixes = ["/lib/firmware"]
+path_check_exempt_args = [1]
+EOF
+    usermode-helper /bin/true /not/a/path /lib/firmware/a.bin
+    usermode-helper-deny /bin/true /not/a/path /etc/shadow
+}
+
+@test "path_check_exempt_args requires allowed_path_prefixes" {
+    cat <<EOF > "${TEMP_DIR}/usermode-helper.conf"
+[[helpers]]
+path = "/bin/true"
+path_check_exempt_args = [1]
+EOF
+    usermode-helper-fail 5 /bin/true
+    echo "$output" | grep "path_check_exempt_args requires allowed_path_prefixes"
+}
@@ -0,0 +1,186 @@
+load helpers
+
+function setup() {
+    make_tempdir
+    DIR="$(realpath "${TEMP_DIR}")"
+    mkdir "${DIR}/lib" "${DIR}/sub"
+    for helper in lib/foo-a lib/foo- lib/bar-a sub/x-helper sub/x-helper.bak; do
+        printf '#!/bin/sh\ntouch "%s/ran-%s"\n' "${DIR}" "$(basename "${helper}")" > "${DIR}/${helper}"
+        chmod 0755 "${DIR}/${helper}"
+    done
+}
+
+function teardown() {
+    cleanup
+}
+
+@test "path_prefix matches helpers by prefix" {
+    cat <<EOF > "${TEMP_DIR}/usermode-helper.conf"
+[[helpers]]
+path_prefix = "${DIR}/lib/foo-"
+EOF
+    usermode-helper "${DIR}/lib/foo-a"
+    [ -e "${DIR}/ran-foo-a" ]
+
+    usermode-helper-deny "${DIR}/lib/bar-a"
+    # The prefix alone, or anything in another directory, doesn't match.
+    usermode-helper-deny "${DIR}/lib/foo-"
+    usermode-helper-deny "${DIR}/lib/foo-a/../bar-a"
+    usermode-helper-deny "${DIR}/lib/foo-/../../sub/x-helper"
+}
+
+@test "path_suffix matches helpers by suffix" {
+    cat <<EOF > "${TEMP_DIR}/usermode-helper.conf"
+[[helpers]]
+path_suffix = "-helper"
+EOF
+    usermode-helper "${DIR}/sub/x-helper"
+    [ -e "${DIR}/ran-x-helper" ]
+
+    usermode-helper-deny "${DIR}/sub/x-helper.bak"
+    usermode-helper-deny "-helper"
+    usermode-helper-deny "sub/x-helper"
+    usermode-helper-deny "${DIR}/lib/../sub/x-helper"
+}
+
+@test "pattern-matched helpers keep their constraints" {
+    cat <<EOF > "${TEMP_DIR}/usermode-helper.conf"
+[[helpers]]
+path_prefix = "${DIR}/lib/foo-"
+argc = 2
+EOF
+    usermode-helper-deny "${DIR}/lib/foo-a"
+    usermode-helper "${DIR}/lib/foo-a" one
+}
+
+@test "pattern-matched helpers must be root's alone" {
+    cat <<EOF > "${TEMP_DIR}/usermode-helper.conf"
+[[helpers]]
+path_prefix = "${DIR}/lib/foo-"
+EOF
+    chmod g+w "${DIR}/lib"
+    usermode-helper-fail 7 "${DIR}/lib/foo-a"
+    echo "$output" | grep "refusing ${DIR}/lib/foo-a, matched by ${DIR}/lib/foo-\*: ${DIR}/lib is group/world-writable"
+    [ ! -e "${DIR}/ran-foo-a" ]
+
+    chmod g-w "${DIR}/lib"
+    chmod o+w "${DIR}/lib/foo-a"
+    usermode-helper-fail 7 "${DIR}/lib/foo-a"
+    echo "$output" | grep "${DIR}/lib/foo-a is group/world-writable"
+}
+
+@test "exactly one of path, path_prefix, path_suffix and allowed_dirs is required" {
+    cat <<EOF > "${TEMP_DIR}/usermode-helper.conf"
+[[helpers]]
+path = "/bin/true"
+path_suffix = "-helper"
+EOF
+    usermode-helper-fail 5 /bin/true
+    echo "$output" | grep "exactly one of path, path_prefix, path_suffix and allowed_dirs must be set"
+
+    cat <<EOF > "${TEMP_DIR}/usermode-helper.conf"
+[[helpers]]
+argc = 1
+EOF
+    usermode-helper-fail 5 /bin/true
+    echo "$output" | grep "exactly one of path, path_prefix, path_suffix and allowed_dirs must be set"
+}
+
+@test "path_prefix must be absolute" {
+    cat <<EOF > "${TEMP_DIR}/usermode-helper.conf"
+[[helpers]]
+path_prefix = "lib/foo-"
+EOF
+    usermode-helper-fail 5 /bin/true
+    echo "$output" | grep "invalid helper lib/foo-\* in .*: path_prefix lib/foo- isn't an absolute path"
+}
+
+@test "allowed_dirs matches programs directly inside the directories" {
+    mkdir "${DIR}/lib/nested"
+    printf '#!/bin/sh\ntouch "%s/ran-nested"\n' "${DIR}" > "${DIR}/lib/nested/plugin"
+    chmod 0755 "${DIR}/lib/nested/plugin"
+    cat <<EOF > "${TEMP_DIR}/usermode-helper.conf"
+[[helpers]]
+allowed_dirs = ["${DIR}/lib/", "${DIR}/empty"]
+EOF
+    usermode-helper "${DIR}/lib/bar-a"
+    [ -e "${DIR}/ran-bar-a" ]
+
+    # Subdirectories, and ways out of the directory, don't match.
+    usermode-helper-deny "${DIR}/lib/nested/plugin"
+    [ ! -e "${DIR}/ran-nested" ]
+    usermode-helper-deny "${DIR}/lib"
+    usermode-helper-deny "${DIR}/lib/"
+    usermode-helper-deny "${DIR}/lib/.."
+    usermode-helper-deny "${DIR}/lib/../sub/x-helper"
+    usermode-helper-deny "${DIR}/sub/x-helper"
+}
+
+@test "allowed_dirs helpers must be root's alone" {
+    cat <<EOF > "${TEMP_DIR}/usermode-helper.conf"
+[[helpers]]
+allowed_dirs = ["${DIR}/lib"]
+EOF
+    chmod o+w "${DIR}/lib/bar-a"
+    usermode-helper-fail 7 "${DIR}/lib/bar-a"
+    echo "$output" | grep "refusing ${DIR}/lib/bar-a, matched by ${DIR}/lib/\*: ${DIR}/lib/bar-a is group/world-writable"
+    [ ! -e "${DIR}/ran-bar-a" ]
+}
+
+@test "allowed_dirs helpers may be symlinks into the allowed directories" {
+    mkdir "${DIR}/plugins"
+    ln -s ../lib/bar-a "${DIR}/plugins/other-dir-link"
+    ln -s bar-a "${DIR}/lib/bar-link"
+    cat <<EOF > "${TEMP_DIR}/usermode-helper.conf"
+[[helpers]]
+allowed_dirs = ["${DIR}/lib", "${DIR}/plugins"]
+EOF
+    usermode-helper "${DIR}/lib/bar-link"
+    [ -e "${DIR}/ran-bar-a" ]
+    rm "${DIR}/ran-bar-a"
+    usermode-helper "${DIR}/plugins/other-dir-link"
+    [ -e "${DIR}/ran-bar-a" ]
+}
+
+@test "allowed_dirs helpers can't be symlinks out of the allowed directories" {
+    ln -s ../sub/x-helper "${DIR}/lib/escape"
+    ln -s nested/plugin "${DIR}/lib/into-subdir"
+    mkdir "${DIR}/lib/nested"
+    printf '#!/bin/sh\ntouch "%s/ran-nested"\n' "${DIR}" > "${DIR}/lib/nested/plugin"
+    chmod 0755 "${DIR}/lib/nested/plugin"
+    cat <<EOF > "${TEMP_DIR}/usermode-helper.conf"
+[[helpers]]
+allowed_dirs = ["${DIR}/lib"]
+EOF
+    usermode-helper-fail 7 "${DIR}/lib/escape"
+    echo "$output" | grep "refusing ${DIR}/lib/escape, matched by ${DIR}/lib/\*: it resolves to ${DIR}/sub/x-helper, outside allowed_dirs"
+    [ ! -e "${DIR}/ran-x-helper" ]
+
+    # Subdirectories aren't allowed either.
+    usermode-helper-fail 7 "${DIR}/lib/into-subdir"
+    echo "$output" | grep "it resolves to ${DIR}/lib/nested/plugin, outside allowed_dirs"
+    [ ! -e "${DIR}/ran-nested" ]
+}
+
+@test "allowed_dirs requires config permission checks" {
+    cat <<EOF > "${TEMP_DIR}/usermode-helper.conf"
+[[helpers]]
+allowed_dirs = ["${DIR}/lib"]
+EOF
+    chmod 0666 "${TEMP_DIR}/usermode-helper.conf"
+    usermode-helper-fail 5 "${DIR}/lib/bar-a"
+    if echo "$output" | grep -q "group/world-writable"; then
+        skip "only builds with ALLOW_UNSAFE_CONFIG_PERMS skip permission checks"
+    fi
+    echo "$output" | grep "helper ${DIR}/lib/\*: allowed_dirs requires config permission checks"
+    [ ! -e "${DIR}/ran-bar-a" ]
+}
+
+@test "allowed_dirs entries must be absolute" {
+    cat <<EOF > "${TEMP_DIR}/usermode-helper.conf"
+[[helpers]]
+allowed_dirs = ["lib"]
+EOF
+    usermode-helper-fail 5 /bin/true
+    echo "$output" | grep "allowed_dirs entry lib isn't an absolute path"
+}
@@ -0,0 +1,80 @@
+load helpers
+
+function setup() {
+    make_tempdir
+    make_passwd
+}
+
+function teardown() {
+    cleanup
+}
+
+@test "pre-exec hook success runs the helper" {
+    cat <<EOF > "${TEMP_DIR}/usermode-helper.conf"
+passwd_file = "${PASSWD}"
+
+[[helpers]]
+path = "/bin/true"
+pre_exec_hook = "/bin/true"
+user = "hookuser"
+EOF
+    usermode-helper /bin/true
+    echo "$output" | grep "DEBUG CAPS for /bin/true"
+}
+
+@test "pre-exec hook failure denies the helper" {
+    cat <<EOF > "${TEMP_DIR}/usermode-helper.conf"
+passwd_file = "${PASSWD}"
+
+[[helpers]]
+path = "/bin/true"
+pre_exec_hook = "/bin/false"
+user = "hookuser"
+EOF
+    usermode-helper-fail 7 /bin/true
+    echo "$output" | grep "pre-exec hook /bin/false rejected /bin/true"
+    if echo "$output" | grep "DEBUG CAPS"; then
+        echo "helper ran despite hook failure" && false
+    fi
+}
+
+@test "pre-exec hook gets the same argv and no capabilities" {
+    cat <<EOF > "${TEMP_DIR}/hook.sh"
+#!/bin/sh
+[ "\$*" = "one two" ] || exit 1
+grep -q "^CapEff:[[:space:]]*0*$" /proc/self/status || exit 1
+grep -q "^CapBnd:[[:space:]]*0*$" /proc/self/status || exit 1
+grep -q "^NoNewPrivs:[[:space:]]*1$" /proc/self/status
+EOF
+    chmod +x "${TEMP_DIR}/hook.sh"
+    cat <<EOF > "${TEMP_DIR}/usermode-helper.conf"
+passwd_file = "${PASSWD}"
+
+[[helpers]]
+path = "/bin/true"
+pre_exec_hook = "$(realpath "${TEMP_DIR}")/hook.sh"
+user = "hookuser"
+EOF
+    usermode-helper /bin/true one two
+}
+
+@test "pre-exec hook runs as nobody for helpers without a user" {
+    cat <<EOF > "${TEMP_DIR}/hook.sh"
+#!/bin/sh
+[ "\$(id -u)" = 65534 ] && [ "\$(id -g)" = 65534 ]
+EOF
+    chmod +x "${TEMP_DIR}/hook.sh"
+    cat <<EOF > "${TEMP_DIR}/usermode-helper.conf"
+[[helpers]]
+path = "/bin/true"
+pre_exec_hook = "$(realpath "${TEMP_DIR}")/hook.sh"
+EOF
+    real-usermode-helper /bin/true
+    # Where only root is mapped (as in the tests' user namespace), the switch
+    # itself fails, which still keeps the hook from running as root.
+    if echo "$output" | grep "couldn't switch to user nobody"; then
+        [ "$status" -eq 7 ]
+    else
+        [ "$status" -eq 0 ]
+    fi
+}
@@ -0,0 +1,66 @@
+load helpers
+
+function setup() {
+    make_tempdir
+    CHECK="$(realpath "${TEMP_DIR}")/precheck.sh"
+}
+
+function teardown() {
+    cleanup
+}
+
+function make_check() {
+    printf '#!/bin/sh\n%s\n' "$1" > "${CHECK}"
+    chmod +x "${CHECK}"
+}
+
+@test "passing capability precheck runs the helper" {
+    # Runs with exactly the helper's capabilities.
+    make_check 'grep -q "^CapEff:[[:space:]]*0*200000$" /proc/self/status'
+    cat <<EOF > "${TEMP_DIR}/usermode-helper.conf"
+[[helpers]]
+path = "/bin/true"
+capabilities = "cap_sys_admin"
+capability_precheck = "${CHECK}"
+EOF
+    usermode-helper /bin/true
+    echo "$output" | grep "capability precheck ${CHECK} passed for /bin/true"
+}
+
+@test "failing capability precheck denies the helper" {
+    make_check 'exit 1'
+    cat <<EOF > "${TEMP_DIR}/usermode-helper.conf"
+[[helpers]]
+path = "/bin/true"
+capabilities = "cap_sys_admin"
+capability_precheck = "${CHECK}"
+EOF
+    usermode-helper-fail 7 /bin/true
+    echo "$output" | grep "capability precheck ${CHECK} failed for /bin/true"
+    if echo "$output" | grep "DEBUG CAPS"; then
+        echo "helper ran despite precheck failure" && false
+    fi
+}
+
+@test "capability precheck gets the helper's arguments" {
+    make_check '[ "$*" = "one two" ]'
+    cat <<EOF > "${TEMP_DIR}/usermode-helper.conf"
+[[helpers]]
+path = "/bin/true"
+capability_precheck = "${CHECK}"
+EOF
+    usermode-helper /bin/true one two
+    usermode-helper-fail 7 /bin/true one three
+}
+
+@test "slow capability precheck times out" {
+    make_check 'sleep 5'
+    cat <<EOF > "${TEMP_DIR}/usermode-helper.conf"
+[[helpers]]
+path = "/bin/true"
+capability_precheck = "${CHECK}"
+capability_precheck_timeout = 1
+EOF
+    usermode-helper-fail 7 /bin/true
+    echo "$output" | grep "capability precheck ${CHECK} for /bin/true timed out after 1s"
+}
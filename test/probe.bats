@@ -0,0 +1,70 @@
+load helpers
+
+function setup() {
+    make_tempdir
+    DIR="$(realpath "${TEMP_DIR}")"
+    # A fake helper: "--version" probes it, anything else is a real run that
+    # leaves a marker behind.
+    cat <<EOF > "${DIR}/helper.sh"
+#!/bin/sh
+if [ "\$1" = --version ]; then
+    case "\$2" in
+    fail) exit 3 ;;
+    hang) sleep 10 ;;
+    caps) grep -q "^CapEff:[[:space:]]*0*1000$" /proc/self/status ;;
+    esac
+    exit
+fi
+touch "${DIR}/ran"
+EOF
+    chmod +x "${DIR}/helper.sh"
+}
+
+function teardown() {
+    cleanup
+}
+
+function config() {
+    cat <<EOF > "${TEMP_DIR}/usermode-helper.conf"
+[[helpers]]
+path = "${DIR}/helper.sh"
+capabilities = "cap_net_admin"
+$1
+EOF
+}
+
+@test "helper runs after a successful probe" {
+    config 'probe_args = ["--version"]'
+    usermode-helper "${DIR}/helper.sh"
+    echo "$output" | grep "probe of ${DIR}/helper.sh passed"
+    [ -e "${DIR}/ran" ]
+}
+
+@test "probe runs with the helper's restrictions" {
+    config 'probe_args = ["--version", "caps"]'
+    usermode-helper "${DIR}/helper.sh"
+    [ -e "${DIR}/ran" ]
+}
+
+@test "failed probe stops the helper" {
+    config 'probe_args = ["--version", "fail"]'
+    usermode-helper-fail 12 "${DIR}/helper.sh"
+    echo "$output" | grep "probe of ${DIR}/helper.sh failed: exit status: 3"
+    [ ! -e "${DIR}/ran" ]
+}
+
+@test "hanging probe is killed after probe_timeout" {
+    config 'probe_args = ["--version", "hang"]
+probe_timeout = 1'
+    usermode-helper-fail 12 "${DIR}/helper.sh"
+    echo "$output" | grep "probe of ${DIR}/helper.sh timed out after 1s"
+    [ ! -e "${DIR}/ran" ]
+}
+
+@test "helpers aren't probed by default" {
+    config ""
+    usermode-helper "${DIR}/helper.sh"
+    if echo "$output" | grep "probe"; then
+        echo "helper was probed" && false
+    fi
+}
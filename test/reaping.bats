@@ -0,0 +1,68 @@
+load helpers
+
+function setup() {
+    make_tempdir
+    DIR="$(realpath "${TEMP_DIR}")"
+    # Leaves a grandchild behind, reparented as soon as the helper exits.
+    cat <<EOF > "${DIR}/double-fork"
+#!/bin/sh
+(sleep 1; touch "${DIR}/grandchild-done") 2>/dev/null &
+exit 0
+EOF
+    cat <<EOF > "${DIR}/lingering"
+#!/bin/sh
+sh -c 'echo \$\$ > "${DIR}/grandchild-pid"; exec sleep 30' 2>/dev/null &
+exit 0
+EOF
+    chmod 0755 "${DIR}/double-fork" "${DIR}/lingering"
+}
+
+function teardown() {
+    if [ -e "${DIR}/grandchild-pid" ]; then
+        kill "$(cat "${DIR}/grandchild-pid")" 2>/dev/null || true
+    fi
+    cleanup
+}
+
+function config() {
+    cat <<EOF > "${TEMP_DIR}/usermode-helper.conf"
+[[helpers]]
+path = "${DIR}/$1"
+run_timeout = ${2:-10}
+reap_descendants = ${3:-true}
+EOF
+}
+
+@test "reap_descendants waits for what the helper leaves behind" {
+    config double-fork
+    usermode-helper "${DIR}/double-fork"
+    [ -e "${DIR}/grandchild-done" ]
+    echo "$output" | grep "reaped descendant"
+}
+
+@test "without reap_descendants, leftovers aren't waited for" {
+    config double-fork 10 false
+    usermode-helper "${DIR}/double-fork"
+    [ ! -e "${DIR}/grandchild-done" ]
+}
+
+@test "reap_descendants kills leftovers at run_timeout" {
+    config lingering 1
+    SECONDS=0
+    usermode-helper "${DIR}/lingering"
+    [ "$SECONDS" -lt 10 ]
+    [ -e "${DIR}/grandchild-pid" ]
+    if kill -0 "$(cat "${DIR}/grandchild-pid")"; then
+        echo "grandchild survived" && false
+    fi
+}
+
+@test "reap_descendants requires run_timeout" {
+    cat <<EOF > "${TEMP_DIR}/usermode-helper.conf"
+[[helpers]]
+path = "/bin/true"
+reap_descendants = true
+EOF
+    usermode-helper-fail 5 /bin/true
+    echo "$output" | grep "reap_descendants requires run_timeout"
+}
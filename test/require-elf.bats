@@ -0,0 +1,64 @@
+load helpers
+
+function setup() {
+    make_tempdir
+    DIR="$(realpath "${TEMP_DIR}")"
+    printf '#!/bin/sh\nexit 0\n' > "${DIR}/script.sh"
+    printf 'exit 0\n' > "${DIR}/text"
+    chmod +x "${DIR}/script.sh" "${DIR}/text"
+    mkdir "${DIR}/dir"
+}
+
+function teardown() {
+    cleanup
+}
+
+function config() {
+    cat <<EOF > "${TEMP_DIR}/usermode-helper.conf"
+[[helpers]]
+path = "$1"
+require_elf = true
+EOF
+}
+
+@test "require_elf runs ELF binaries" {
+    config /bin/true
+    usermode-helper /bin/true
+}
+
+@test "require_elf refuses scripts" {
+    config "${DIR}/script.sh"
+    usermode-helper-fail 7 "${DIR}/script.sh"
+    echo "$output" | grep "${DIR}/script.sh is not an executable binary (require_elf): it's a script"
+}
+
+@test "require_elf refuses text files" {
+    config "${DIR}/text"
+    usermode-helper-fail 7 "${DIR}/text"
+    echo "$output" | grep "${DIR}/text is not an executable binary (require_elf): it's not ELF"
+}
+
+@test "require_elf refuses directories" {
+    config "${DIR}/dir"
+    usermode-helper-fail 7 "${DIR}/dir"
+    echo "$output" | grep "${DIR}/dir is not an executable binary (require_elf): Is a directory"
+}
+
+@test "require_elf checks exec_path" {
+    cat <<EOF > "${TEMP_DIR}/usermode-helper.conf"
+[[helpers]]
+path = "/bin/true"
+exec_path = "${DIR}/script.sh"
+require_elf = true
+EOF
+    usermode-helper-fail 7 /bin/true
+    echo "$output" | grep "${DIR}/script.sh is not an executable binary"
+}
+
+@test "scripts run without require_elf" {
+    cat <<EOF > "${TEMP_DIR}/usermode-helper.conf"
+[[helpers]]
+path = "${DIR}/script.sh"
+EOF
+    usermode-helper "${DIR}/script.sh"
+}
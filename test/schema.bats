@@ -0,0 +1,51 @@
+load helpers
+
+function schema() {
+    : "${UMH_BIN:=${ROOT_DIR}/target/debug/usermode-helper}"
+    run "${UMH_BIN}" --schema
+    echo "$output"
+    [ "$status" -eq 0 ]
+}
+
+@test "--schema describes helper fields" {
+    schema
+    echo "$output" | grep '"\$ref": "#/\$defs/helper"'
+    echo "$output" | grep -A2 '"capabilities": {'
+    echo "$output" | grep '"seccomp": {'
+}
+
+@test "--schema describes top-level fields, including the preamble" {
+    schema
+    echo "$output" | grep '"killswitch": {'
+    echo "$output" | grep '"max_dropin_files": {'
+    echo "$output" | grep -A6 '"exec_coherence": {' | grep '"deny"'
+}
+
+@test "--schema is only recognized under our own name" {
+    : "${UMH_BIN:=${ROOT_DIR}/target/debug/usermode-helper}"
+    make_tempdir
+    run bash -c "cd ${TEMP_DIR} && exec -a /bin/true \"${UMH_BIN}\" --schema"
+    cleanup
+    [ "$status" -ne 0 ]
+    if echo "$output" | grep '"properties"'; then
+        echo "printed the schema" && false
+    fi
+}
+
+@test "--schema only lists choices the config accepts" {
+    schema
+    local schema="$output"
+    make_tempdir
+    local conf="${TEMP_DIR}/usermode-helper.conf"
+    while read -r field value; do
+        printf '%s = "%s"\n\n[[helpers]]\npath = "/bin/true"\n' "$field" "$value" > "$conf"
+        run "${UMH_BIN}" --validate "$conf"
+        [ "$status" -eq 0 ] || { echo "$field = $value: $output"; cleanup; false; }
+    done < <(echo "$schema" | jq -r '.properties | to_entries[] | select(.value.enum) | .key + " " + .value.enum[]')
+    while read -r field value; do
+        printf '[[helpers]]\npath = "/bin/true"\n%s = "%s"\n' "$field" "$value" > "$conf"
+        run "${UMH_BIN}" --validate "$conf"
+        [ "$status" -eq 0 ] || { echo "$field = $value: $output"; cleanup; false; }
+    done < <(echo "$schema" | jq -r '."$defs".helper.properties | to_entries[] | select(.value.enum) | .key + " " + .value.enum[]')
+    cleanup
+}
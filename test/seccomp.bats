@@ -0,0 +1,123 @@
+load helpers
+
+function setup() {
+    make_tempdir
+}
+
+function teardown() {
+    cleanup
+}
+
+@test "helper runs under the built-in minimal profile" {
+    cat <<EOF > "${TEMP_DIR}/usermode-helper.conf"
+[[helpers]]
+path = "/bin/true"
+seccomp = "@minimal"
+EOF
+    usermode-helper /bin/true
+    echo "$output" | grep "seccomp filter for /bin/true allows: .*\bexit_group\b"
+}
+
+@test "built-in filesystem profile extends minimal" {
+    cat <<EOF > "${TEMP_DIR}/usermode-helper.conf"
+[[helpers]]
+path = "/bin/true"
+seccomp = "@filesystem"
+EOF
+    usermode-helper /bin/true
+    echo "$output" | grep "seccomp filter for /bin/true allows: .*\bmkdirat\b"
+    echo "$output" | grep "seccomp filter for /bin/true allows: .*\bexit_group\b"
+    if echo "$output" | grep "allows: .*\bfinit_module\b"; then
+        echo "filesystem profile allows loading modules" && false
+    fi
+}
+
+@test "syscalls outside the profile are denied" {
+    DIR="$(realpath "${TEMP_DIR}")/newdir"
+    cat <<EOF > "${TEMP_DIR}/usermode-helper.conf"
+[[helpers]]
+path = "/bin/mkdir"
+seccomp = "@minimal"
+EOF
+    usermode-helper-fail 1 /bin/mkdir "${DIR}"
+    [ ! -e "${DIR}" ]
+
+    cat <<EOF > "${TEMP_DIR}/usermode-helper.conf"
+[[helpers]]
+path = "/bin/mkdir"
+seccomp = "@filesystem"
+EOF
+    usermode-helper /bin/mkdir "${DIR}"
+    [ -d "${DIR}" ]
+}
+
+@test "config profiles resolve references" {
+    cat <<EOF > "${TEMP_DIR}/usermode-helper.conf"
+[seccomp_profiles]
+base = ["@minimal", "sethostname"]
+modprobe = ["@base", "finit_module"]
+
+[[helpers]]
+path = "/bin/true"
+seccomp = ["@modprobe", "delete_module"]
+EOF
+    usermode-helper /bin/true
+    for sc in exit_group sethostname finit_module delete_module; do
+        echo "$output" | grep "seccomp filter for /bin/true allows: .*\b${sc}\b"
+    done
+}
+
+@test "config profiles override built-in ones" {
+    cat <<EOF > "${TEMP_DIR}/usermode-helper.conf"
+[seccomp_profiles]
+filesystem = ["@minimal"]
+
+[[helpers]]
+path = "/bin/true"
+seccomp = "@filesystem"
+EOF
+    usermode-helper /bin/true
+    if echo "$output" | grep "allows: .*\bmkdirat\b"; then
+        echo "built-in profile used" && false
+    fi
+}
+
+@test "unknown seccomp profile is rejected" {
+    cat <<EOF > "${TEMP_DIR}/usermode-helper.conf"
+[[helpers]]
+path = "/bin/true"
+seccomp = "@nonexistent"
+EOF
+    usermode-helper-fail 5 /bin/true
+    echo "$output" | grep "unknown seccomp profile @nonexistent"
+}
+
+@test "unknown syscall is rejected" {
+    cat <<EOF > "${TEMP_DIR}/usermode-helper.conf"
+[seccomp_profiles]
+broken = ["@minimal", "not_a_syscall"]
+
+[[helpers]]
+path = "/bin/false"
+seccomp = "@broken"
+
+[[helpers]]
+path = "/bin/true"
+EOF
+    usermode-helper-fail 5 /bin/true
+    echo "$output" | grep "unknown syscall not_a_syscall"
+}
+
+@test "recursive seccomp profiles are rejected" {
+    cat <<EOF > "${TEMP_DIR}/usermode-helper.conf"
+[seccomp_profiles]
+a = ["@b"]
+b = ["@a"]
+
+[[helpers]]
+path = "/bin/true"
+seccomp = "@a"
+EOF
+    usermode-helper-fail 5 /bin/true
+    echo "$output" | grep "seccomp profile @a includes itself"
+}
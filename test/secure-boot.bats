@@ -0,0 +1,75 @@
+load helpers
+
+function setup() {
+    make_tempdir
+    EFIVAR="$(realpath "${TEMP_DIR}")/SecureBoot"
+}
+
+function teardown() {
+    cleanup
+}
+
+# efivarfs format: 4 bytes of attributes (NV+BS+RT), then the value.
+function secure_boot() {
+    printf "\x06\x00\x00\x00\x$1" > "${EFIVAR}"
+}
+
+function config() {
+    cat <<EOF > "${TEMP_DIR}/usermode-helper.conf"
+secure_boot_var = "${EFIVAR}"
+$1
+
+[[helpers]]
+path = "/bin/true"
+
+[[helpers]]
+path = "/bin/echo"
+require_secure_boot = false
+EOF
+}
+
+@test "require_secure_boot allows helpers with secure boot on" {
+    config "require_secure_boot = true"
+    secure_boot 01
+    usermode-helper /bin/true
+    echo "$output" | grep "secure boot is on"
+}
+
+@test "require_secure_boot denies helpers with secure boot off" {
+    config "require_secure_boot = true"
+    secure_boot 00
+    real-usermode-helper /bin/true
+    [ "$status" -eq 14 ]
+    echo "$output" | grep "secure boot is off, denying /bin/true"
+
+    # ...except where overridden.
+    usermode-helper /bin/echo
+}
+
+@test "secure boot state is ignored without require_secure_boot" {
+    config ""
+    secure_boot 00
+    usermode-helper /bin/true
+}
+
+@test "unknown secure boot state denies by default" {
+    config "require_secure_boot = true"
+    real-usermode-helper /bin/true
+    [ "$status" -eq 14 ]
+    echo "$output" | grep "secure boot state is unknown, denying /bin/true"
+}
+
+@test "secure_boot_unknown = allow allows on non-EFI systems" {
+    config 'require_secure_boot = true
+secure_boot_unknown = "allow"'
+    usermode-helper /bin/true
+    echo "$output" | grep "WARNING: secure boot state is unknown, allowing /bin/true"
+}
+
+@test "malformed SecureBoot variable is an unknown state" {
+    config "require_secure_boot = true"
+    secure_boot 02
+    real-usermode-helper /bin/true
+    [ "$status" -eq 14 ]
+    echo "$output" | grep "bad SecureBoot variable"
+}
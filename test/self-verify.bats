@@ -0,0 +1,37 @@
+load helpers
+
+function setup() {
+    make_tempdir
+    cat <<EOF > "${TEMP_DIR}/usermode-helper.conf"
+[[helpers]]
+path = "/bin/true"
+EOF
+    : "${UMH_BIN:=${ROOT_DIR}/target/debug/usermode-helper}"
+    # Without self-verify, a wrong hash is just ignored.
+    echo "huldufolk.selfhash=$(printf '0%.0s' {1..64})" > "${TEMP_DIR}/cmdline"
+    real-usermode-helper /bin/true
+    if [ "$status" -eq 0 ]; then
+        skip "not built with the self-verify feature"
+    fi
+}
+
+function teardown() {
+    cleanup
+}
+
+@test "a wrong binary hash denies all helpers" {
+    usermode-helper-fail 13 /bin/true
+    echo "$output" | grep "doesn't match huldufolk.selfhash=0000"
+}
+
+@test "a missing binary hash denies all helpers" {
+    echo "ro quiet" > "${TEMP_DIR}/cmdline"
+    usermode-helper-fail 13 /bin/true
+    echo "$output" | grep "the kernel command line has no huldufolk.selfhash, denying all helpers"
+}
+
+@test "the right binary hash runs helpers" {
+    echo "huldufolk.selfhash=$(sha256sum "${UMH_BIN}" | cut -d' ' -f1)" > "${TEMP_DIR}/cmdline"
+    usermode-helper /bin/true
+    echo "$output" | grep "binary hash matches the kernel command line"
+}
@@ -0,0 +1,84 @@
+load helpers
+
+function setup() {
+    make_tempdir
+}
+
+function teardown() {
+    cleanup
+}
+
+@test "helper starts with no signals blocked by default" {
+    cat <<EOF > "${TEMP_DIR}/usermode-helper.conf"
+[[helpers]]
+path = "/bin/grep"
+EOF
+    usermode-helper /bin/grep -q "^SigBlk:[[:space:]]*0*$" /proc/self/status
+}
+
+@test "block_signals are blocked in the helper" {
+    # SIGUSR1 (10) and SIGTERM (15): bits 9 and 14.
+    cat <<EOF > "${TEMP_DIR}/usermode-helper.conf"
+[[helpers]]
+path = "/bin/grep"
+block_signals = ["SIGTERM", "usr1"]
+EOF
+    usermode-helper /bin/grep -q "^SigBlk:[[:space:]]*0*4200$" /proc/self/status
+}
+
+@test "block_signals applies to supervised helpers" {
+    cat <<EOF > "${TEMP_DIR}/usermode-helper.conf"
+[[helpers]]
+path = "/bin/grep"
+block_signals = ["SIGTERM", "SIGUSR1"]
+run_timeout = 5
+EOF
+    usermode-helper /bin/grep -q "^SigBlk:[[:space:]]*0*4200$" /proc/self/status
+}
+
+@test "block_signals rejects SIGKILL and SIGSTOP" {
+    cat <<EOF > "${TEMP_DIR}/usermode-helper.conf"
+[[helpers]]
+path = "/bin/true"
+block_signals = ["SIGKILL"]
+EOF
+    usermode-helper-fail 5 /bin/true
+    echo "$output" | grep "signal SIGKILL can't be blocked"
+
+    cat <<EOF > "${TEMP_DIR}/usermode-helper.conf"
+[[helpers]]
+path = "/bin/true"
+block_signals = ["SIGSTOP"]
+EOF
+    usermode-helper-fail 5 /bin/true
+    echo "$output" | grep "signal SIGSTOP can't be blocked"
+}
+
+@test "block_signals rejects unknown signals" {
+    cat <<EOF > "${TEMP_DIR}/usermode-helper.conf"
+[[helpers]]
+path = "/bin/true"
+block_signals = ["SIGFOO"]
+EOF
+    usermode-helper-fail 5 /bin/true
+    echo "$output" | grep "bad signal SIGFOO"
+}
+
+@test "helper starts with default signal dispositions" {
+    cat <<EOF > "${TEMP_DIR}/usermode-helper.conf"
+[[helpers]]
+path = "/bin/grep"
+EOF
+    # nohup starts us with SIGHUP ignored.
+    UMH_PREFIX=nohup usermode-helper /bin/grep -q "^SigIgn:[[:space:]]*0*$" /proc/self/status
+}
+
+@test "reset_signals = false keeps inherited dispositions" {
+    cat <<EOF > "${TEMP_DIR}/usermode-helper.conf"
+[[helpers]]
+path = "/bin/grep"
+reset_signals = false
+EOF
+    # SIGHUP (1): bit 0.
+    UMH_PREFIX=nohup usermode-helper /bin/grep -q "^SigIgn:[[:space:]]*0*1$" /proc/self/status
+}
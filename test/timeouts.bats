@@ -0,0 +1,86 @@
+load helpers
+
+function setup() {
+    make_tempdir
+}
+
+function teardown() {
+    cleanup
+}
+
+@test "slow setup is killed by setup_timeout" {
+    # The pre-exec hook runs during setup, so a slow hook is a slow setup.
+    make_passwd
+    cat <<EOF > "${TEMP_DIR}/usermode-helper.conf"
+passwd_file = "${PASSWD}"
+
+[[helpers]]
+path = "/bin/sleep"
+pre_exec_hook = "/bin/sleep"
+user = "hookuser"
+setup_timeout = 1
+run_timeout = 10
+EOF
+    usermode-helper-fail 8 /bin/sleep 3
+    echo "$output" | grep "setup timeout exceeded"
+    if echo "$output" | grep "run timeout"; then
+        echo "attributed to the wrong timeout" && false
+    fi
+}
+
+@test "slow helper is killed by run_timeout" {
+    cat <<EOF > "${TEMP_DIR}/usermode-helper.conf"
+[[helpers]]
+path = "/bin/sleep"
+setup_timeout = 1
+run_timeout = 1
+EOF
+    usermode-helper-fail 10 /bin/sleep 3
+    echo "$output" | grep "/bin/sleep exceeded run timeout of 1s"
+    if echo "$output" | grep "setup timeout"; then
+        echo "attributed to the wrong timeout" && false
+    fi
+}
+
+@test "setup_timeout doesn't apply to the exec'd helper" {
+    cat <<EOF > "${TEMP_DIR}/usermode-helper.conf"
+[[helpers]]
+path = "/bin/sleep"
+setup_timeout = 1
+EOF
+    usermode-helper /bin/sleep 2
+}
+
+@test "setup_timeout doesn't apply to a supervised helper" {
+    cat <<EOF > "${TEMP_DIR}/usermode-helper.conf"
+[[helpers]]
+path = "/bin/sleep"
+setup_timeout = 1
+run_timeout = 5
+EOF
+    usermode-helper /bin/sleep 2
+}
+
+@test "supervised helper's exit status is propagated" {
+    cat <<EOF > "${TEMP_DIR}/usermode-helper.conf"
+[[helpers]]
+path = "/bin/sh"
+run_timeout = 5
+EOF
+    real-usermode-helper /bin/sh -c "'exit 7'"
+    [ "$status" -eq 7 ]
+}
+
+@test "a hang before matching is cut short by the match timeout" {
+    # Opening a FIFO nobody reads blocks, and the audit log is written
+    # while matching, before any setup_timeout is known.
+    mkfifo "${TEMP_DIR}/audit.fifo"
+    cat <<EOF > "${TEMP_DIR}/usermode-helper.conf"
+audit_log = "$(realpath "${TEMP_DIR}")/audit.fifo"
+
+[[helpers]]
+path = "/bin/true"
+EOF
+    usermode-helper-fail 8 /bin/true
+    echo "$output" | grep "setup timeout exceeded"
+}
@@ -0,0 +1,29 @@
+load helpers
+
+function setup() {
+    make_tempdir
+}
+
+function teardown() {
+    cleanup
+}
+
+@test "require_urandom_ready runs helpers once the RNG is initialized" {
+    cat <<EOF > "${TEMP_DIR}/usermode-helper.conf"
+[[helpers]]
+path = "/bin/true"
+require_urandom_ready = true
+urandom_wait_secs = 5
+EOF
+    usermode-helper /bin/true
+}
+
+@test "urandom_wait_secs requires require_urandom_ready" {
+    cat <<EOF > "${TEMP_DIR}/usermode-helper.conf"
+[[helpers]]
+path = "/bin/true"
+urandom_wait_secs = 5
+EOF
+    usermode-helper-fail 5 /bin/true
+    echo "$output" | grep "urandom_wait_secs requires require_urandom_ready"
+}
@@ -0,0 +1,135 @@
+load helpers
+
+function setup() {
+    make_tempdir
+    cat <<EOF > "${TEMP_DIR}/passwd"
+# A fixture; only uid 0 is mapped in the tests' user namespace.
+root:x:0:0:root:/root:/bin/sh
+helperuser:x:0:0:Helper:/var/lib/helperuser:/usr/sbin/nologin
+EOF
+    CHECK="$(realpath "${TEMP_DIR}")/check-home.sh"
+    cat <<EOF > "${CHECK}"
+#!/bin/sh
+[ "\$HOME" = "\$1" ]
+EOF
+    chmod +x "${CHECK}"
+}
+
+function teardown() {
+    cleanup
+}
+
+@test "HOME is / without a user" {
+    cat <<EOF > "${TEMP_DIR}/usermode-helper.conf"
+[[helpers]]
+path = "${CHECK}"
+EOF
+    usermode-helper "${CHECK}" /
+}
+
+@test "HOME comes from the user's passwd entry" {
+    cat <<EOF > "${TEMP_DIR}/usermode-helper.conf"
+passwd_file = "$(realpath "${TEMP_DIR}")/passwd"
+
+[[helpers]]
+path = "${CHECK}"
+user = "helperuser"
+EOF
+    usermode-helper "${CHECK}" /var/lib/helperuser
+}
+
+@test "explicit env.HOME beats the passwd entry" {
+    cat <<EOF > "${TEMP_DIR}/usermode-helper.conf"
+passwd_file = "$(realpath "${TEMP_DIR}")/passwd"
+
+[[helpers]]
+path = "${CHECK}"
+user = "helperuser"
+env = { HOME = "/srv/elsewhere" }
+EOF
+    usermode-helper "${CHECK}" /srv/elsewhere
+}
+
+@test "users can be given by uid" {
+    cat <<EOF > "${TEMP_DIR}/usermode-helper.conf"
+passwd_file = "$(realpath "${TEMP_DIR}")/passwd"
+
+[[helpers]]
+path = "${CHECK}"
+user = "0"
+EOF
+    # The first entry for uid 0 wins.
+    usermode-helper "${CHECK}" /root
+}
+
+@test "user keeps its capabilities" {
+    cat <<EOF > "${TEMP_DIR}/usermode-helper.conf"
+passwd_file = "$(realpath "${TEMP_DIR}")/passwd"
+
+[[helpers]]
+path = "/bin/true"
+user = "helperuser"
+capabilities = "cap_sys_admin"
+EOF
+    usermode-helper /bin/true
+    echo "$output" | grep "^Permitted: {CAP_SYS_ADMIN}$"
+}
+
+@test "unknown user is rejected" {
+    cat <<EOF > "${TEMP_DIR}/usermode-helper.conf"
+passwd_file = "$(realpath "${TEMP_DIR}")/passwd"
+
+[[helpers]]
+path = "/bin/true"
+user = "nobody-here"
+EOF
+    usermode-helper-fail 5 /bin/true
+    echo "$output" | grep "unknown user nobody-here for /bin/true"
+}
+
+@test "env can't set dynamic linker variables" {
+    cat <<EOF > "${TEMP_DIR}/usermode-helper.conf"
+[[helpers]]
+path = "/bin/true"
+env = { LD_PRELOAD = "/tmp/evil.so" }
+EOF
+    usermode-helper-fail 5 /bin/true
+    echo "$output" | grep "LD_PRELOAD can't be set for helpers"
+}
+
+@test "allowed_target_uids allows listed uids" {
+    cat <<EOF > "${TEMP_DIR}/usermode-helper.conf"
+passwd_file = "$(realpath "${TEMP_DIR}")/passwd"
+allowed_target_uids = [0, "1000-1999"]
+
+[[helpers]]
+path = "${CHECK}"
+user = "helperuser"
+EOF
+    usermode-helper "${CHECK}" /var/lib/helperuser
+}
+
+@test "allowed_target_uids rejects helpers running as other uids" {
+    echo "otheruser:x:2000:2000:Other:/:/usr/sbin/nologin" >> "${TEMP_DIR}/passwd"
+    cat <<EOF > "${TEMP_DIR}/usermode-helper.conf"
+passwd_file = "$(realpath "${TEMP_DIR}")/passwd"
+allowed_target_uids = ["1000-1999"]
+
+[[helpers]]
+path = "/bin/true"
+user = "otheruser"
+EOF
+    usermode-helper-fail 5 /bin/true
+    echo "$output" | grep "helper /bin/true: user otheruser has uid 2000, which allowed_target_uids doesn't allow"
+}
+
+@test "allowed_target_uids ranges must be well-formed" {
+    cat <<EOF > "${TEMP_DIR}/usermode-helper.conf"
+allowed_target_uids = ["1999-1000"]
+
+[[helpers]]
+path = "/bin/true"
+EOF
+    usermode-helper-fail 5 /bin/true
+    echo "$output" | grep 'bad uid range "1999-1000"'
+}
@@ -0,0 +1,72 @@
+load helpers
+
+function setup() {
+    make_tempdir
+}
+
+function teardown() {
+    cleanup
+}
+
+# The usual test namespace only maps root, so the helper's unprivileged id
+# can't be mapped inside it; run as real root instead.
+function root-usermode-helper {
+    if [ "$(id -u)" != "0" ]; then
+        skip "not root, can't map ids for a user namespace"
+    fi
+    : "${UMH_BIN:=${ROOT_DIR}/target/debug/usermode-helper}"
+    pushd "${TEMP_DIR}"
+    run env UMH_BIN="${UMH_BIN}" HULDUFOLK_DEBUG=1 bash -c 'exec -a "$0" "$UMH_BIN" "$@"' "$@"
+    popd
+    echo "$output"
+}
+
+@test "userns helper is root inside, mapped to nobody outside" {
+    cat <<EOF > "${TEMP_DIR}/usermode-helper.conf"
+[[helpers]]
+path = "/bin/sh"
+userns = true
+EOF
+    root-usermode-helper /bin/sh -c 'read -r i o n < /proc/self/uid_map && [ "$i $o $n" = "0 65534 1" ] && read -r i o n < /proc/self/gid_map && [ "$i $o $n" = "0 65534 1" ] && [ "$(id -u)" = 0 ]'
+    [ "$status" -eq 0 ]
+}
+
+@test "userns helper is mapped to its user" {
+    cat <<EOF > "${TEMP_DIR}/passwd"
+umh:x:1234:2345::/:/bin/false
+EOF
+    cat <<EOF > "${TEMP_DIR}/usermode-helper.conf"
+[[helpers]]
+path = "/bin/sh"
+userns = true
+user = "umh"
+passwd_file = "$(realpath "${TEMP_DIR}")/passwd"
+EOF
+    root-usermode-helper /bin/sh -c 'read -r i o n < /proc/self/uid_map && [ "$i $o $n" = "0 1234 1" ] && read -r i o n < /proc/self/gid_map && [ "$i $o $n" = "0 2345 1" ]'
+    [ "$status" -eq 0 ]
+}
+
+@test "userns helper's capabilities only apply in its namespace" {
+    SECRET=$(mktemp)
+    chmod 0600 "${SECRET}"
+    cat <<EOF > "${TEMP_DIR}/usermode-helper.conf"
+[[helpers]]
+path = "/bin/sh"
+userns = true
+capabilities = "cap_dac_override"
+EOF
+    root-usermode-helper /bin/sh -c "grep -q '^CapEff:.*0000000000000002$' /proc/self/status && ! cat ${SECRET}"
+    rm -f "${SECRET}"
+    [ "$status" -eq 0 ]
+}
+
+@test "userns fails closed when the ids can't be mapped" {
+    cat <<EOF > "${TEMP_DIR}/usermode-helper.conf"
+[[helpers]]
+path = "/bin/true"
+userns = true
+EOF
+    # Only root is mapped in the test namespace, so there's no nobody to be.
+    usermode-helper-fail 6 /bin/true
+    echo "$output" | grep "couldn't switch to user nobody"
+}
@@ -0,0 +1,84 @@
+load helpers
+
+function setup() {
+    make_tempdir
+}
+
+function teardown() {
+    cleanup
+}
+
+function validate() {
+    : "${UMH_BIN:=${ROOT_DIR}/target/debug/usermode-helper}"
+    run "${UMH_BIN}" --validate "${TEMP_DIR}/usermode-helper.conf" "$@"
+    echo "$output"
+}
+
+# Only the JSON, without warnings about the config.
+function plan() {
+    : "${UMH_BIN:=${ROOT_DIR}/target/debug/usermode-helper}"
+    run bash -c '"$0" --validate "$1" --plan 2>/dev/null' "${UMH_BIN}" "${TEMP_DIR}/usermode-helper.conf"
+    echo "$output"
+}
+
+@test "--validate summarizes a valid config" {
+    cat <<EOF > "${TEMP_DIR}/usermode-helper.conf"
+[[helpers]]
+path = "/bin/true"
+
+[[helpers]]
+path = "/bin/false"
+EOF
+    validate
+    [ "$status" -eq 0 ]
+    echo "$output" | grep "usermode-helper.conf: 2 helpers, valid"
+}
+
+@test "--validate fails on an invalid config" {
+    cat <<EOF > "${TEMP_DIR}/usermode-helper.conf"
+[[helpers]]
+path = "/bin/true"
+urandom_wait_secs = 5
+EOF
+    validate
+    [ "$status" -eq 5 ]
+}
+
+@test "--validate --plan shows expanded capabilities and profiles" {
+    cat <<EOF > "${TEMP_DIR}/usermode-helper.conf"
+ambient_denylist = "cap_sys_module"
+
+[seccomp_profiles]
+base = ["@minimal", "sethostname"]
+
+[[helpers]]
+path = "/bin/true"
+capabilities = "cap_net_admin,cap_sys_module"
+require_capabilities = "cap_chown"
+seccomp = "@base"
+EOF
+    plan
+    [ "$status" -eq 0 ]
+    plan='.[] | select(.helper == "/bin/true")'
+    echo "$output" | jq -e "${plan} | .unrestricted == false
+        and .privileges.caps == [\"cap_chown\", \"cap_net_admin\", \"cap_sys_module\"]
+        and .privileges.ambient == [\"cap_chown\", \"cap_net_admin\"]
+        and .privileges.stripped == [\"cap_sys_module\"]
+        and .privileges.drop_setpcap"
+    echo "$output" | jq -e "${plan} | .config.seccomp | index(\"sethostname\") and index(\"exit_group\")"
+    echo "$output" | jq -e "${plan} | .config.workdir == \"/\" and .config.env_path != null"
+}
+
+@test "--validate --plan flags helpers the ambient_denylist fails" {
+    cat <<EOF > "${TEMP_DIR}/usermode-helper.conf"
+ambient_denylist = "cap_sys_module"
+ambient_denylist_policy = "fail"
+
+[[helpers]]
+path = "/bin/true"
+capabilities = "cap_sys_module"
+EOF
+    plan
+    [ "$status" -eq 0 ]
+    echo "$output" | jq -e '.[0].refused_ambient == "cap_sys_module" and .[0].privileges == null'
+}
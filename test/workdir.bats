@@ -0,0 +1,76 @@
+load helpers
+
+function setup() {
+    make_tempdir
+    HELPER="$(realpath "${TEMP_DIR}")/check-cwd.sh"
+    cat <<EOF > "${HELPER}"
+#!/bin/sh
+[ "\$(pwd -P)" = "\$1" ]
+EOF
+    chmod +x "${HELPER}"
+}
+
+function teardown() {
+    cleanup
+}
+
+@test "helper runs in / by default" {
+    cat <<EOF > "${TEMP_DIR}/usermode-helper.conf"
+[[helpers]]
+path = "${HELPER}"
+EOF
+    usermode-helper "${HELPER}" /
+}
+
+@test "per-helper workdir overrides the default" {
+    cat <<EOF > "${TEMP_DIR}/usermode-helper.conf"
+[[helpers]]
+path = "${HELPER}"
+workdir = "/usr"
+EOF
+    usermode-helper "${HELPER}" /usr
+}
+
+@test "default_workdir is configurable" {
+    cat <<EOF > "${TEMP_DIR}/usermode-helper.conf"
+default_workdir = "/usr"
+
+[[helpers]]
+path = "${HELPER}"
+EOF
+    usermode-helper "${HELPER}" /usr
+}
+
+@test "empty default_workdir keeps the inherited directory" {
+    cat <<EOF > "${TEMP_DIR}/usermode-helper.conf"
+default_workdir = ""
+
+[[helpers]]
+path = "${HELPER}"
+EOF
+    usermode-helper "${HELPER}" "$(realpath "${TEMP_DIR}")"
+}
+
+@test "pre-exec hook runs in the helper's workdir" {
+    make_passwd
+    cat <<EOF > "${TEMP_DIR}/usermode-helper.conf"
+passwd_file = "${PASSWD}"
+
+[[helpers]]
+path = "/bin/true"
+pre_exec_hook = "${HELPER}"
+user = "hookuser"
+workdir = "/usr"
+EOF
+    usermode-helper /bin/true /usr
+}
+
+@test "relative workdir is rejected" {
+    cat <<EOF > "${TEMP_DIR}/usermode-helper.conf"
+[[helpers]]
+path = "${HELPER}"
+workdir = "usr"
+EOF
+    usermode-helper-fail 5 "${HELPER}" /usr
+    echo "$output" | grep "workdir usr isn't an absolute path"
+}